@@ -0,0 +1,183 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use toml::{Table, Value};
+
+use crate::judge::{
+    compiler, playground::PlaygroundLimits, resource::ResourceLimits, sandbox::SandboxConfig,
+    workspace::WorkspaceConfig,
+};
+use crate::server::{
+    auth::ApiTokens, frontend::FrontendConfig, health::HealthConfig, listener::ListenerConfig,
+    models::SourceLimits, rate_limit::RateLimitConfig,
+};
+use crate::store::DatabaseConfig;
+use crate::thread_pool::QueueConfig;
+use crate::webhook::WebhookConfig;
+
+/// Commands by language, split on whitespace; `None` for the languages
+/// listed in `languages` without one.
+pub type Recipes = BTreeMap<String, Option<Vec<String>>>;
+
+/// Everything in `config.toml`, read once by `main` and handed out by
+/// section. Sections left out take their defaults, but for the recipes.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `languages` and `[compile]`
+    pub compile: Recipes,
+    /// `languages` and `[execute]`
+    pub execute: Recipes,
+    pub limits: ResourceLimits,
+    /// `[limits] compile_wall_time`, milliseconds a compiler may take
+    pub compile_wall_time_limit: u64,
+    /// `[sandbox]` and `[cgroup]`
+    pub sandbox: SandboxConfig,
+    pub workspace: WorkspaceConfig,
+    pub max_source_size: SourceLimits,
+    pub database: DatabaseConfig,
+    pub queue: QueueConfig,
+    pub webhook: WebhookConfig,
+    pub playground: PlaygroundLimits,
+    pub rate_limit: RateLimitConfig,
+    pub health: HealthConfig,
+    pub auth: ApiTokens,
+    pub server: ListenerConfig,
+    pub web: FrontendConfig,
+}
+
+impl Config {
+    /// Panics when the file is missing or a section isn't set correctly,
+    /// the judge can't start without it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Config {
+        let path = path.as_ref();
+        let config_text = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => panic!("config: `{}` is missing: {e}", path.display()),
+        };
+
+        let data: Table = match toml::from_str(&config_text) {
+            Ok(d) => d,
+            Err(e) => panic!("config: Can't read from `{}`: {e}", path.display()),
+        };
+
+        Config::from_table(&data)
+    }
+
+    pub fn from_table(data: &Table) -> Config {
+        Config {
+            compile: recipes_from_config(data, "compile"),
+            execute: recipes_from_config(data, "execute"),
+            limits: ResourceLimits::from_config(data),
+            compile_wall_time_limit: compiler::wall_time_limit_from_config(data),
+            sandbox: SandboxConfig::from_config(data),
+            workspace: WorkspaceConfig::from_config(data),
+            max_source_size: SourceLimits::from_config(data),
+            database: DatabaseConfig::from_config(data),
+            queue: QueueConfig::from_config(data),
+            webhook: WebhookConfig::from_config(data),
+            playground: PlaygroundLimits::from_config(data),
+            rate_limit: RateLimitConfig::from_config(data),
+            health: HealthConfig::from_config(data),
+            auth: ApiTokens::from_config(data),
+            server: ListenerConfig::from_config(data),
+            web: FrontendConfig::from_config(data),
+        }
+    }
+}
+
+/// `[<section>]` commands of the languages in `languages`.
+fn recipes_from_config(data: &Table, section: &str) -> Recipes {
+    let mut recipe = Recipes::new();
+
+    let lang_lst = match data.get("languages") {
+        Some(v) => v,
+        _ => panic!("config: [languages] should be set correctly"),
+    };
+    let commands = match data.get(section) {
+        Some(v) => v,
+        _ => panic!("config: [{section}] should be set correctly"),
+    };
+
+    match lang_lst {
+        Value::Array(lang_lst) => {
+            for lang in lang_lst {
+                if let Value::String(lang) = lang {
+                    recipe.insert(lang.clone(), None);
+                } else {
+                    panic!("config: [languages] should be set correctly");
+                }
+            }
+        }
+        _ => panic!("config: [languages] should be set correctly"),
+    };
+
+    match commands {
+        Value::Table(commands) => {
+            for (lang, val) in commands.iter() {
+                if !recipe.contains_key(lang) {
+                    continue;
+                }
+                let val = match val {
+                    Value::String(val) => val,
+                    _ => panic!("config: [{section}] should be set correctly"),
+                };
+                let command_chain: Vec<String> = val
+                    .split_ascii_whitespace()
+                    .map(|s| s.to_string())
+                    .collect();
+                *recipe.get_mut(lang).unwrap() = Some(command_chain);
+            }
+        }
+        _ => panic!("config: [{section}] should be set correctly"),
+    }
+
+    recipe
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Table;
+
+    use super::Config;
+    use crate::judge::consts::{
+        CONFIG_PATH, DEFAULT_COMPILE_WALL_TIME_LIMIT, DEFAULT_MAX_QUEUED, DEFAULT_PORT,
+    };
+
+    #[test]
+    fn test_missing_sections_take_defaults() {
+        let data: Table = toml::from_str(
+            r#"
+languages = ["cpp", "python"]
+[compile]
+cpp = "g++ -o $target $source"
+[execute]
+cpp = "$target"
+python = "python3 $source"
+[queue]
+max_attempts = 5
+"#,
+        )
+        .unwrap();
+        let config = Config::from_table(&data);
+
+        assert_eq!(config.compile["python"], None);
+        assert_eq!(
+            config.execute["python"],
+            Some(vec!["python3".to_string(), "$source".to_string()])
+        );
+        assert_eq!(config.queue.max_attempts, 5);
+        assert_eq!(config.queue.max_queued, DEFAULT_MAX_QUEUED);
+        assert_eq!(config.server.port, DEFAULT_PORT);
+        assert_eq!(config.sandbox.cgroup_root, None);
+        assert!(config.sandbox.read_only.contains(&"/usr".to_string()));
+        assert_eq!(
+            config.compile_wall_time_limit,
+            DEFAULT_COMPILE_WALL_TIME_LIMIT
+        );
+    }
+
+    #[test]
+    fn test_config_file_loads() {
+        let config = Config::load(CONFIG_PATH);
+        assert!(config.compile.contains_key("cpp"));
+    }
+}
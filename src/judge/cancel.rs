@@ -0,0 +1,88 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Weak,
+};
+
+#[derive(Debug, Default)]
+struct Flags {
+    cancelled: AtomicBool,
+    interrupted: AtomicBool,
+}
+
+/// A flag shared by everything working on one submission, once cancelled
+/// the sandbox kills whatever is still running for it.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    flags: Arc<Flags>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.flags.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flags.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Cancels on behalf of a shutdown rather than of the submitter: the
+    /// submission isn't finished, it's judged again after the restart.
+    pub fn interrupt(&self) {
+        self.flags.interrupted.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.flags.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// A handle that doesn't keep the token alive, see [`WeakCancellationToken`].
+    pub fn downgrade(&self) -> WeakCancellationToken {
+        WeakCancellationToken {
+            flags: Arc::downgrade(&self.flags),
+        }
+    }
+}
+
+/// Gone once the task and every job of its submission are dropped, i.e. once
+/// there's nothing left to cancel.
+#[derive(Debug, Clone)]
+pub struct WeakCancellationToken {
+    flags: Weak<Flags>,
+}
+
+impl WeakCancellationToken {
+    pub fn upgrade(&self) -> Option<CancellationToken> {
+        self.flags
+            .upgrade()
+            .map(|flags| CancellationToken { flags })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.flags.strong_count() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancellationToken;
+
+    #[test]
+    fn test_weak_token() {
+        let token = CancellationToken::new();
+        let weak = token.downgrade();
+        weak.upgrade().unwrap().cancel();
+        assert!(token.is_cancelled());
+        assert!(!token.is_interrupted());
+        token.interrupt();
+        assert!(token.is_interrupted());
+
+        drop(token);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use nix::unistd::Pid;
+
+use super::resource::{ResourceLimits, ResourceUsage};
+
+static CGROUP_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+static REQUIRED_CONTROLLERS: [&str; 3] = ["memory", "pids", "cpu"];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    NotDelegated,
+    ControllerMissing(String),
+    FileSystemError(String),
+}
+
+/// A delegated cgroup v2 subtree in which one child cgroup is created per run.
+#[derive(Debug)]
+pub struct CgroupController {
+    root: PathBuf,
+}
+
+impl CgroupController {
+    /// Prepares `root` for per-run cgroups, returns an error when the
+    /// hierarchy is not cgroup v2 or the controllers can't be delegated.
+    pub fn detect(root: &str) -> Result<CgroupController, Error> {
+        let root = PathBuf::from(root);
+        let parent = root.parent().ok_or(Error::NotDelegated)?;
+        if !parent.join("cgroup.controllers").exists() {
+            return Err(Error::NotDelegated);
+        }
+
+        match fs::create_dir(&root) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(_) => return Err(Error::NotDelegated),
+        }
+
+        let available = read_control(&root, "cgroup.controllers")?;
+        for controller in REQUIRED_CONTROLLERS {
+            if !available.split_ascii_whitespace().any(|c| c == controller) {
+                return Err(Error::ControllerMissing(controller.to_string()));
+            }
+        }
+
+        // a cgroup with processes can't enable controllers for its children
+        let subtree_control = REQUIRED_CONTROLLERS
+            .iter()
+            .map(|c| format!("+{c}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write_control(&root, "cgroup.subtree_control", &subtree_control)?;
+
+        Ok(CgroupController { root })
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn create(&self, limits: &ResourceLimits) -> Result<Cgroup, Error> {
+        let name = format!(
+            "run-{}-{}",
+            std::process::id(),
+            CGROUP_SEQUENCE.fetch_add(1, Ordering::SeqCst)
+        );
+        let path = self.root.join(name);
+        fs::create_dir(&path).map_err(|e| Error::FileSystemError(format!("{e}")))?;
+        let cgroup = Cgroup { path };
+
+        write_control(
+            &cgroup.path,
+            "memory.max",
+            &(limits.memory_limit * 1024).to_string(),
+        )?;
+        // don't let the kernel push the program into swap instead of killing it
+        write_control(&cgroup.path, "memory.swap.max", "0").ok();
+        write_control(&cgroup.path, "pids.max", &limits.pids_limit.to_string())?;
+        write_control(&cgroup.path, "cpu.max", "100000 100000")?;
+
+        Ok(cgroup)
+    }
+}
+
+/// A cgroup owning exactly one run, it's killed and removed on drop.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn attach(&self, pid: Pid) -> Result<(), Error> {
+        write_control(&self.path, "cgroup.procs", &pid.to_string())
+    }
+
+    pub fn usage(&self) -> Result<ResourceUsage, Error> {
+        let cpu_stat = read_control(&self.path, "cpu.stat")?;
+        let usage_usec = parse_flat_keyed(&cpu_stat, "usage_usec").unwrap_or(0);
+
+        // memory.peak is only available since Linux 5.19
+        let memory_peak = read_control(&self.path, "memory.peak")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok());
+
+        let memory_events = read_control(&self.path, "memory.events")?;
+        let oom_killed = parse_flat_keyed(&memory_events, "oom_kill").unwrap_or(0) > 0;
+
+        Ok(ResourceUsage {
+            time_cost: usage_usec / 1000,
+            mem_cost: memory_peak.map(|bytes| bytes / 1024),
+            oom_killed,
+        })
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // kill anything the program left behind, then the directory can be removed
+        write_control(&self.path, "cgroup.kill", "1").ok();
+        if fs::remove_dir(&self.path).is_err() {
+            tracing::warn!("cgroup: can't remove `{}`", self.path.display());
+        }
+    }
+}
+
+fn write_control(cgroup: &Path, file: &str, value: &str) -> Result<(), Error> {
+    fs::write(cgroup.join(file), value).map_err(|e| Error::FileSystemError(format!("{file}: {e}")))
+}
+
+fn read_control(cgroup: &Path, file: &str) -> Result<String, Error> {
+    fs::read_to_string(cgroup.join(file))
+        .map_err(|e| Error::FileSystemError(format!("{file}: {e}")))
+}
+
+fn parse_flat_keyed(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        if k == key {
+            v.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_flat_keyed, CgroupController, Error};
+
+    #[test]
+    fn test_parse_flat_keyed() {
+        let cpu_stat = "usage_usec 12345\nuser_usec 10000\nsystem_usec 2345\n";
+        assert_eq!(parse_flat_keyed(cpu_stat, "usage_usec"), Some(12345));
+        assert_eq!(parse_flat_keyed(cpu_stat, "system_usec"), Some(2345));
+        assert_eq!(parse_flat_keyed(cpu_stat, "nr_periods"), None);
+    }
+
+    #[test]
+    fn test_detect_without_delegation() {
+        let ret = CgroupController::detect("/nonexistent/coffee_oj");
+        assert_eq!(ret.unwrap_err(), Error::NotDelegated);
+    }
+}
@@ -73,7 +73,7 @@ impl Comparer {
             }
 
             let left_line_trimmed = left_line.trim_end_matches('\n');
-            let right_line_trimmed = left_line.trim_end_matches('\n');
+            let right_line_trimmed = right_line.trim_end_matches('\n');
             if left_line_trimmed != right_line_trimmed {
                 return Ok(ComparerResult::Inconsistent(
                     cnt_line,
@@ -1,17 +1,22 @@
-#![allow(clippy::missing_safety_doc)]
-
-use std::{collections::HashMap, ffi::CString, fmt::Debug, fs, sync::Mutex};
-
-use nix::{
-    libc,
-    sys::wait::waitpid,
-    unistd::{execvp, fork, write, ForkResult},
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    sync::{Arc, Mutex},
+    time::Instant,
 };
+
 use toml::{Table, Value};
 
-use crate::c_string;
+use crate::config::Config;
+use crate::metrics::METRICS;
 
-use super::{consts::CONFIG_PATH, file::SavedSource};
+use super::{
+    cancel::CancellationToken,
+    consts::DEFAULT_COMPILE_WALL_TIME_LIMIT,
+    file::{self, SavedSource},
+    sandbox::{KillReason, Sandbox, SandboxCommand},
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -19,79 +24,63 @@ pub enum Error {
     ForkFailed,
     NoCompilationLogError,
     CompilationError(String),
+    /// the compiler was killed after the wall time limit, in milliseconds
+    TimedOut(u64),
+    Cancelled,
 }
 
 pub struct Compiler {
     pub compiling_recipe: Mutex<HashMap<String, Option<Vec<String>>>>,
+    /// milliseconds a compiler may take
+    wall_time_limit: u64,
+    sandbox: Arc<dyn Sandbox>,
 }
 
-impl Default for Compiler {
-    fn default() -> Compiler {
-        let mut recipe: HashMap<String, Option<Vec<String>>> = HashMap::new();
-        let config_text = match fs::read_to_string(CONFIG_PATH) {
-            Ok(s) => s,
-            Err(e) => panic!("config: `{CONFIG_PATH}` is missing: {e}"),
-        };
-
-        let data: Table = match toml::from_str(&config_text) {
-            Ok(d) => d,
-            Err(e) => panic!("config: Can't read from `{CONFIG_PATH}`: {e}"),
-        };
-
-        let lang_lst = match data.get("languages") {
-            Some(v) => v,
-            _ => panic!("config: [languages] should be set correctly"),
-        };
-        let compilation_commands = match data.get("compile") {
-            Some(v) => v,
-            _ => panic!("config: [compile] should be set correctly"),
-        };
-
-        match lang_lst {
-            Value::Array(lang_lst) => {
-                for lang in lang_lst {
-                    if let Value::String(lang) = lang {
-                        recipe.insert(lang.clone(), None);
-                    } else {
-                        panic!("config: [languages] should be set correctly");
-                    }
-                }
-            }
-            _ => panic!("config: [languages] should be set correctly"),
-        };
-
-        match compilation_commands {
-            Value::Table(compilation_commands) => {
-                for (lang, val) in compilation_commands.iter() {
-                    if !recipe.contains_key(lang) {
-                        continue;
-                    }
-                    let val = match val {
-                        Value::String(val) => val,
-                        _ => panic!("config: [compile] should be set correctly"),
-                    };
-                    let command_chain: Vec<String> = val
-                        .split_ascii_whitespace()
-                        .map(|s| s.to_string())
-                        .collect();
-                    *recipe.get_mut(lang).unwrap() = Some(command_chain);
-                }
-            }
-            _ => panic!("config: [compile] should be set correctly"),
-        }
+/// `[limits] compile_wall_time`, milliseconds.
+pub fn wall_time_limit_from_config(data: &Table) -> u64 {
+    match data.get("limits") {
+        Some(Value::Table(limits)) => match limits.get("compile_wall_time") {
+            Some(Value::Integer(val)) if *val > 0 => *val as u64,
+            None => DEFAULT_COMPILE_WALL_TIME_LIMIT,
+            _ => panic!("config: [limits] should be set correctly"),
+        },
+        None => DEFAULT_COMPILE_WALL_TIME_LIMIT,
+        _ => panic!("config: [limits] should be set correctly"),
+    }
+}
 
+impl Compiler {
+    /// With the recipes of `[compile]`, in `sandbox`.
+    pub fn new(config: &Config, sandbox: Arc<dyn Sandbox>) -> Compiler {
         Compiler {
-            compiling_recipe: Mutex::new(recipe),
+            compiling_recipe: Mutex::new(config.compile.clone().into_iter().collect()),
+            wall_time_limit: config.compile_wall_time_limit,
+            sandbox,
         }
     }
-}
 
-impl Compiler {
+    /// The compilers the recipes run, e.g. `g++`.
+    pub fn get_programs(&self) -> Vec<String> {
+        let compiling_recipe = self.compiling_recipe.lock().unwrap();
+        let mut programs: Vec<String> = compiling_recipe
+            .values()
+            .filter_map(|command_chain| command_chain.as_ref()?.first().cloned())
+            .collect();
+        programs.sort();
+        programs.dedup();
+        programs
+    }
+
+    /// Where the compiler output of `target` is kept.
+    pub fn get_log_path(target: &str) -> String {
+        format!("{target}.log")
+    }
+
     fn generate_compilation_command(
         &self,
         source: &SavedSource,
         lang: &str,
-    ) -> Result<Option<(String, Vec<CString>)>, Error> {
+    ) -> Result<Option<(String, Vec<String>)>, Error> {
         let compiling_recipe = self.compiling_recipe.lock().unwrap();
         let command_chain = match compiling_recipe.get(lang) {
             Some(chain) => chain,
@@ -104,7 +93,7 @@ impl Compiler {
 
         let target_full_path = format!("{}.exe", source.get_full_path());
 
-        let mut command = Vec::<CString>::new();
+        let mut command = Vec::<String>::new();
         for token in command_chain {
             let mut token: &str = token;
             if token.starts_with('$') {
@@ -115,53 +104,54 @@ impl Compiler {
                     _ => { /* do nothing */ }
                 }
             }
-            command.push(c_string!(token));
+            command.push(token.to_string());
         }
 
         Ok(Some((target_full_path, command)))
     }
 
-    pub fn compile(&self, source: &SavedSource, lang: &str) -> Result<String, Error> {
+    pub fn compile(
+        &self,
+        source: &SavedSource,
+        lang: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String, Error> {
         let ret = self.generate_compilation_command(source, lang)?;
         if ret.is_none() {
             return Ok(source.get_full_path().to_string());
         }
         let (target_full_path, command) = ret.unwrap();
         // dbg!(&command);
-        let log_path = format!("{target_full_path}.log");
+        let log_path = Compiler::get_log_path(&target_full_path);
         // dbg!(&log_path);
 
-        match unsafe { fork() } {
-            Ok(ForkResult::Parent { child, .. }) => {
-                waitpid(child, None).unwrap();
-            }
-            Ok(ForkResult::Child) => {
-                // let log_path = c_string_ptr!(log_path);
-                let log_path = c_string!(log_path.as_str());
-                let w_mode = c_string!("w");
-
-                unsafe {
-                    // open a log file to read in compilation log
-                    let log_output = libc::fdopen(libc::STDERR_FILENO, w_mode.as_ptr());
-                    libc::freopen(log_path.as_ptr(), w_mode.as_ptr(), log_output);
-                }
-
-                match execvp(&command[0], &command) {
-                    Ok(_) => unreachable!(),
-                    Err(errno) => write(
-                        libc::STDERR_FILENO,
-                        format!("Execvp error, errno = {:?}\n", errno).as_bytes(),
-                    )
-                    .ok(),
-                };
-
-                unsafe { libc::exit(0) };
-            }
-            _ => return Err(Error::ForkFailed),
+        let command = SandboxCommand::new(command)
+            .stdout(&log_path)
+            .stderr(&log_path)
+            .wall_time_limit(self.wall_time_limit)
+            .cancel(cancel.clone())
+            .workspace(&file::get_workspace_of(&target_full_path));
+        let started_at = Instant::now();
+        let result = self.sandbox.run(&command).map_err(|e| {
+            METRICS.record_sandbox_failure(&e);
+            Error::ForkFailed
+        })?;
+        match result.killed {
+            Some(KillReason::Cancelled) => return Err(Error::Cancelled),
+            Some(KillReason::WallTimeLimit) => return Err(Error::TimedOut(self.wall_time_limit)),
+            None => {}
         }
-
-        let log_content = fs::read_to_string(log_path).map_err(|_| Error::NoCompilationLogError)?;
-        if log_content.is_empty() {
+        METRICS
+            .compile_duration
+            .with_label_values(&[lang])
+            .observe(started_at.elapsed().as_secs_f64());
+
+        let log_content = fs::read_to_string(log_path).map_err(|_| {
+            let e = Error::NoCompilationLogError;
+            METRICS.record_compile_failure(&e);
+            e
+        })?;
+        if result.status.success() {
             Ok(target_full_path)
         } else {
             Err(Error::CompilationError(log_content))
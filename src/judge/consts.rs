@@ -3,7 +3,9 @@ use std::collections::BTreeMap;
 use once_cell::sync::Lazy;
 
 pub static CONFIG_PATH: &str = "config.toml";
-pub static SOURCE_CODE_SAVED_PATH: &str = "assets/src";
+pub static TESTCASES_ROOT: &str = "assets";
+/// Optional settings of a problem, next to its testcases.
+pub static PROBLEM_CONFIG_FILE: &str = "problem.toml";
 
 pub static LANG_EXTENSIONS: Lazy<BTreeMap<String, String>> = Lazy::new(|| {
     BTreeMap::from([
@@ -13,3 +15,62 @@ pub static LANG_EXTENSIONS: Lazy<BTreeMap<String, String>> = Lazy::new(|| {
         ("python".to_string(), "py".to_string()),
     ])
 });
+
+/// Milliseconds of CPU time per testcase.
+pub static DEFAULT_TIME_LIMIT: u64 = 1000;
+/// KiB of memory per testcase.
+pub static DEFAULT_MEMORY_LIMIT: u64 = 262144;
+pub static DEFAULT_PIDS_LIMIT: u64 = 64;
+/// Milliseconds of wall-clock time a compiler may take.
+pub static DEFAULT_COMPILE_WALL_TIME_LIMIT: u64 = 10000;
+pub static DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup/coffee_oj";
+/// Host paths programs in the hardened sandbox can read.
+pub static DEFAULT_READ_ONLY_PATHS: &[&str] = &[
+    "/bin", "/sbin", "/usr", "/lib", "/lib32", "/lib64", "/etc", "/opt",
+];
+pub static DEFAULT_WORKSPACE_ROOT: &str = "/tmp/coffee_oj";
+pub static DEFAULT_TMPFS_WORKSPACE_ROOT: &str = "/dev/shm/coffee_oj";
+/// Bytes of source of a submission.
+pub static DEFAULT_MAX_SOURCE_SIZE: u64 = 65536;
+pub static DEFAULT_DATABASE_PATH: &str = "data/coffee_oj.db";
+/// Times judging of a submission may be started before it's given up on.
+pub static DEFAULT_MAX_ATTEMPTS: u64 = 3;
+/// Submissions queued or being judged at most, 0 for no cap.
+pub static DEFAULT_MAX_QUEUED: u64 = 10000;
+/// Times a webhook is delivered before it's written to the dead-letter log.
+pub static DEFAULT_WEBHOOK_MAX_ATTEMPTS: u64 = 5;
+/// Milliseconds before the first retry of a webhook, doubled every retry.
+pub static DEFAULT_WEBHOOK_BACKOFF: u64 = 1000;
+/// Milliseconds between retries of a webhook at most.
+pub static DEFAULT_WEBHOOK_MAX_BACKOFF: u64 = 300000;
+/// Milliseconds a webhook request may take.
+pub static DEFAULT_WEBHOOK_TIMEOUT: u64 = 5000;
+pub static DEFAULT_WEBHOOK_DEAD_LETTER_PATH: &str = "data/webhook_dead_letters.log";
+/// Milliseconds of CPU time of a playground run.
+pub static DEFAULT_PLAYGROUND_TIME_LIMIT: u64 = 2000;
+/// Bytes of stdout and of stderr returned by a playground run.
+pub static DEFAULT_PLAYGROUND_OUTPUT_LIMIT: u64 = 65536;
+/// Playground runs queued or running at once.
+pub static DEFAULT_PLAYGROUND_MAX_QUEUED: u64 = 16;
+/// Panicked workers before the judge is reported as not ready.
+pub static DEFAULT_MAX_PANICS: u64 = 10;
+/// Seconds a toolchain check is trusted before it's run again.
+pub static DEFAULT_TOOLCHAIN_CHECK_INTERVAL: u64 = 60;
+/// Requests per second a client may make on average.
+pub static DEFAULT_RATE_LIMIT_RATE: f64 = 5.0;
+/// Requests a client may make at once after idling.
+pub static DEFAULT_RATE_LIMIT_BURST: u64 = 20;
+/// Pending submissions per submitter at most.
+pub static DEFAULT_MAX_PENDING: u64 = 50;
+/// Invalid API tokens an IP may send per minute.
+pub static DEFAULT_MAX_AUTH_FAILURES: u64 = 10;
+/// Address the API listens on.
+pub static DEFAULT_HOST: &str = "0.0.0.0";
+/// Port the API listens on, 0 picks a free one.
+pub static DEFAULT_PORT: u16 = 4514;
+/// The built frontend, served at `/`.
+pub static DEFAULT_WEB_DIST: &str = "web/dist";
+/// Seconds browsers may cache a CORS preflight.
+pub static DEFAULT_CORS_MAX_AGE: usize = 3600;
+/// Seconds open connections and started submissions get on shutdown.
+pub static DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use super::Verdict;
+
+/// Events a subscriber may lag behind before it's dropped, its stream ends
+/// and it catches up from the history once it subscribes again.
+const SUBSCRIBER_CAPACITY: usize = 64;
+/// Events kept per submission for late subscribers, the oldest testcases are
+/// forgotten first.
+const HISTORY_CAPACITY: usize = 256;
+/// Channels nothing was published to for this long are forgotten, e.g. the
+/// ones of submissions that were interrupted or never judged at all.
+const CHANNEL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A step of judging a submission, in the order they happen.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JudgeEvent {
+    Queued,
+    Compiling,
+    /// `log` is the compilation log, errors or warnings
+    Compiled {
+        success: bool,
+        log: String,
+    },
+    TestcaseFinished {
+        testcase: String,
+        verdict: Verdict,
+        time_cost: u64,
+        mem_cost: u64,
+        checked: usize,
+        total: usize,
+    },
+    Finished {
+        verdict: Verdict,
+    },
+    /// Left pending by a shutdown, it's judged again after the restart.
+    Requeued,
+}
+
+impl JudgeEvent {
+    /// The last event of the submission in this process, its channel is
+    /// closed after it.
+    pub fn is_final(&self) -> bool {
+        matches!(self, JudgeEvent::Finished { .. } | JudgeEvent::Requeued)
+    }
+}
+
+struct Channel {
+    history: Vec<JudgeEvent>,
+    subscribers: Vec<Sender<JudgeEvent>>,
+    touched_at: Instant,
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            history: vec![],
+            subscribers: vec![],
+            touched_at: Instant::now(),
+        }
+    }
+
+    fn record(&mut self, event: JudgeEvent) {
+        if self.history.len() >= HISTORY_CAPACITY {
+            let oldest = self
+                .history
+                .iter()
+                .position(|event| matches!(event, JudgeEvent::TestcaseFinished { .. }))
+                .unwrap_or(0);
+            self.history.remove(oldest);
+        }
+        self.history.push(event);
+        self.touched_at = Instant::now();
+    }
+}
+
+/// Fans the events of a submission out to its subscribers. The events are
+/// kept until the submission is finished so that late subscribers catch up,
+/// or until nothing happened to it for a while.
+pub struct EventBus {
+    channels: Mutex<HashMap<u64, Channel>>,
+    ttl: Duration,
+}
+
+impl Default for EventBus {
+    fn default() -> EventBus {
+        EventBus::with_ttl(CHANNEL_TTL)
+    }
+}
+
+impl EventBus {
+    pub fn with_ttl(ttl: Duration) -> EventBus {
+        EventBus {
+            channels: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub fn publish(&self, submission_id: u64, event: JudgeEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        self.forget_stale(&mut channels);
+        if event.is_final() {
+            if let Some(channel) = channels.remove(&submission_id) {
+                for subscriber in channel.subscribers {
+                    let _ = subscriber.try_send(event.clone());
+                }
+            }
+            return;
+        }
+
+        let channel = channels.entry(submission_id).or_insert_with(Channel::new);
+        // gone or lagging subscribers are dropped on the way
+        channel
+            .subscribers
+            .retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+        channel.record(event);
+    }
+
+    /// The events so far and a receiver for the next ones, `None` if nothing
+    /// is known about the submission, e.g. it's finished already.
+    pub fn subscribe(&self, submission_id: u64) -> Option<(Vec<JudgeEvent>, Receiver<JudgeEvent>)> {
+        let mut channels = self.channels.lock().unwrap();
+        self.forget_stale(&mut channels);
+        let channel = channels.get_mut(&submission_id)?;
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_CAPACITY);
+        channel.subscribers.push(sender);
+        Some((channel.history.clone(), receiver))
+    }
+
+    /// Submissions with a channel, finished ones and forgotten ones aside.
+    pub fn len(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Their subscribers see their streams end.
+    fn forget_stale(&self, channels: &mut HashMap<u64, Channel>) {
+        channels.retain(|_, channel| channel.touched_at.elapsed() < self.ttl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use tokio::sync::mpsc::error::TryRecvError;
+
+    use crate::judge::Verdict;
+
+    use super::{EventBus, JudgeEvent, SUBSCRIBER_CAPACITY};
+
+    #[test]
+    fn test_late_subscribers_catch_up() {
+        let bus = EventBus::default();
+        assert!(bus.subscribe(1).is_none());
+
+        bus.publish(1, JudgeEvent::Queued);
+        bus.publish(1, JudgeEvent::Compiling);
+        let (history, mut receiver) = bus.subscribe(1).unwrap();
+        assert_eq!(history, vec![JudgeEvent::Queued, JudgeEvent::Compiling]);
+
+        let finished = JudgeEvent::Finished {
+            verdict: Verdict::Accepted,
+        };
+        bus.publish(1, finished.clone());
+        assert_eq!(receiver.try_recv(), Ok(finished));
+        // the channel is closed and forgotten once finished
+        assert!(receiver.try_recv().is_err());
+        assert!(bus.subscribe(1).is_none());
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn test_lagging_subscribers_are_dropped() {
+        let bus = EventBus::default();
+        bus.publish(1, JudgeEvent::Queued);
+        let (_, mut receiver) = bus.subscribe(1).unwrap();
+
+        for _ in 0..=SUBSCRIBER_CAPACITY {
+            bus.publish(1, JudgeEvent::Compiling);
+        }
+        for _ in 0..SUBSCRIBER_CAPACITY {
+            assert_eq!(receiver.try_recv(), Ok(JudgeEvent::Compiling));
+        }
+        // nothing is sent to it anymore, its stream ends
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_stale_channels_are_forgotten() {
+        let bus = EventBus::with_ttl(Duration::from_millis(50));
+        bus.publish(1, JudgeEvent::Queued);
+        let (_, mut receiver) = bus.subscribe(1).unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        bus.publish(2, JudgeEvent::Queued);
+        assert!(bus.subscribe(1).is_none());
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(bus.len(), 1);
+    }
+
+    #[test]
+    fn test_requeued_ends_the_stream() {
+        let bus = EventBus::default();
+        bus.publish(1, JudgeEvent::Compiling);
+        let (_, mut receiver) = bus.subscribe(1).unwrap();
+
+        bus.publish(1, JudgeEvent::Requeued);
+        assert_eq!(receiver.try_recv(), Ok(JudgeEvent::Requeued));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(bus.is_empty());
+    }
+}
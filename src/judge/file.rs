@@ -1,12 +1,20 @@
-use std::{collections::BTreeMap, fmt::Debug, fs::File, io::Write};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::Debug,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use toml::{Table, Value};
 
 use super::{
-    consts::{LANG_EXTENSIONS, SOURCE_CODE_SAVED_PATH},
+    consts::{LANG_EXTENSIONS, PROBLEM_CONFIG_FILE, TESTCASES_ROOT},
+    workspace::Workspace,
     JudgeStatus,
 };
 
-use random_number::{self, random};
-
 // TODO binds an Arc of Task
 #[derive(Default)]
 pub struct DiffTask {}
@@ -38,11 +46,11 @@ impl TestcaseFile {
     }
 
     pub fn get_name(&self) -> &str {
-        return self.filename.as_str();
+        self.filename.as_str()
     }
 
     pub fn get_path(&self) -> &str {
-        return self.path.as_str();
+        self.path.as_str()
     }
 }
 
@@ -112,6 +120,84 @@ pub fn get_pairwise_testcase_files(testcase_files: Vec<TestcaseFile>) -> Vec<Tes
     ret
 }
 
+/// The directory holding the testcases of `problem_id`.
+pub fn get_testcases_path(problem_id: u64) -> String {
+    format!("{TESTCASES_ROOT}/{problem_id}")
+}
+
+/// The `.in`/`.out` pairs in `testcases_path`, none if it can't be read.
+pub fn list_testcases(testcases_path: &str) -> Vec<Testcase> {
+    let mut testcase_files: Vec<TestcaseFile> = vec![];
+    if let Ok(lst_read_dir) = fs::read_dir(testcases_path) {
+        for dir in lst_read_dir.flatten() {
+            let path = format!("{}", dir.path().display());
+            let sp = path.split_at(testcases_path.len() + 1);
+            testcase_files.push(TestcaseFile::new(sp.1, &path));
+        }
+    }
+    get_pairwise_testcase_files(testcase_files)
+}
+
+/// Orders testcase names by the numbers in them, `2.in` before `10.in`.
+pub fn compare_testcase_names(a: &str, b: &str) -> Ordering {
+    let is_digits = |chunk: &str| chunk.starts_with(|c: char| c.is_ascii_digit());
+    let (mut rest_a, mut rest_b) = (a, b);
+    while !rest_a.is_empty() && !rest_b.is_empty() {
+        let (chunk_a, next_a) = split_chunk(rest_a);
+        let (chunk_b, next_b) = split_chunk(rest_b);
+        let ordering = if is_digits(chunk_a) && is_digits(chunk_b) {
+            let (num_a, num_b) = (
+                chunk_a.trim_start_matches('0'),
+                chunk_b.trim_start_matches('0'),
+            );
+            num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b))
+        } else {
+            chunk_a.cmp(chunk_b)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (rest_a, rest_b) = (next_a, next_b);
+    }
+    rest_a.len().cmp(&rest_b.len()).then_with(|| a.cmp(b))
+}
+
+/// The leading run of digits or of anything else.
+fn split_chunk(s: &str) -> (&str, &str) {
+    let digits = s.starts_with(|c: char| c.is_ascii_digit());
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// `languages` of the problem's `problem.toml`, `None` if every language
+/// is allowed.
+pub fn get_allowed_languages(testcases_path: &str) -> Option<Vec<String>> {
+    let path = Path::new(testcases_path).join(PROBLEM_CONFIG_FILE);
+    let text = fs::read_to_string(&path).ok()?;
+    let data: Table = match toml::from_str(&text) {
+        Ok(d) => d,
+        Err(e) => {
+            tracing::warn!("problem: can't read `{}`: {e}", path.display());
+            return None;
+        }
+    };
+    match data.get("languages") {
+        Some(Value::Array(languages)) => Some(
+            languages
+                .iter()
+                .filter_map(|lang| lang.as_str().map(str::to_string))
+                .collect(),
+        ),
+        None => None,
+        _ => {
+            tracing::warn!("problem: `languages` of `{}` isn't a list", path.display());
+            None
+        }
+    }
+}
+
 pub struct SavedSource {
     submission_id: u64,
     full_path: String,
@@ -134,17 +220,28 @@ impl SavedSource {
     }
 }
 
-pub fn save_source_code(source_code: &str, lang: &str) -> Result<SavedSource, String> {
+/// The workspace `path` was saved into, sources, executables and outputs
+/// are right in it.
+pub fn get_workspace_of(path: &str) -> String {
+    Path::new(path)
+        .parent()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default()
+}
+
+pub fn save_source_code(
+    submission_id: u64,
+    source_code: &str,
+    lang: &str,
+    workspace: &Workspace,
+) -> Result<SavedSource, String> {
     let ext = match LANG_EXTENSIONS.get(lang) {
         Some(ext) => ext,
         None => return Err("Unsupported language".to_string()), // TODO handle as a real error
     };
 
-    // TODO get auto-increased id from SQL
-    let submission_id: u64 = random!(0..1000000u64);
-
-    let filename = format!("{submission_id}.{ext}");
-    let full_path = format!("{}/{filename}", SOURCE_CODE_SAVED_PATH);
+    let filename = format!("main.{ext}");
+    let full_path = workspace.file(&filename);
     let mut file = File::create(&full_path).map_err(|_| format!("Can't create file {filename}"))?;
     // TODO maybe use buf writer?
     file.write_all(source_code.as_bytes())
@@ -152,3 +249,60 @@ pub fn save_source_code(source_code: &str, lang: &str) -> Result<SavedSource, St
 
     Ok(SavedSource::new(submission_id, full_path))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, fs};
+
+    use super::{compare_testcase_names, get_allowed_languages, list_testcases};
+
+    #[test]
+    fn test_problem_settings() {
+        let dir = std::env::temp_dir().join(format!("coj-problem-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.display().to_string();
+
+        fs::write(dir.join("1.in"), "").unwrap();
+        assert!(list_testcases(&path).is_empty());
+        fs::write(dir.join("1.out"), "").unwrap();
+        assert_eq!(list_testcases(&path).len(), 1);
+
+        assert_eq!(get_allowed_languages(&path), None);
+        fs::write(dir.join("problem.toml"), "languages = [\"c\", \"cpp\"]").unwrap();
+        assert_eq!(
+            get_allowed_languages(&path),
+            Some(vec!["c".to_string(), "cpp".to_string()])
+        );
+        // not a testcase
+        assert_eq!(list_testcases(&path).len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compare_testcase_names() {
+        let mut names = vec![
+            "10.in",
+            "a.in",
+            "2.in",
+            "1.in",
+            "case10.in",
+            "case9.in",
+            "02.in",
+        ];
+        names.sort_by(|a, b| compare_testcase_names(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "1.in",
+                "02.in",
+                "2.in",
+                "10.in",
+                "a.in",
+                "case9.in",
+                "case10.in"
+            ]
+        );
+        assert_eq!(compare_testcase_names("1.in", "1.in"), Ordering::Equal);
+    }
+}
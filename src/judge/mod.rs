@@ -1,10 +1,17 @@
+pub mod cancel;
+pub mod cgroup;
 pub mod comparer;
 pub mod compiler;
 pub mod consts;
+pub mod events;
 pub mod file;
 pub mod macros;
+pub mod playground;
+pub mod resource;
 pub mod runner;
+pub mod sandbox;
 pub mod task;
+pub mod workspace;
 
 #[derive(Debug)]
 pub enum JudgeStatus {
@@ -17,4 +24,60 @@ pub enum JudgeStatus {
     TimeLimitExceeded(u64),
     MemoLimitExceeded(u64),
     UnknownError(String),
+    Cancelled,
+}
+
+/// The state of a submission or the outcome of one of its testcases, as
+/// persisted by the store.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Verdict {
+    Pending,
+    Judging,
+    Accepted,
+    WrongAnswer,
+    CompilationError,
+    RuntimeError,
+    TimeLimitExceeded,
+    MemoLimitExceeded,
+    SystemError,
+    Cancelled,
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Pending => "Pending",
+            Verdict::Judging => "Judging",
+            Verdict::Accepted => "Accepted",
+            Verdict::WrongAnswer => "WrongAnswer",
+            Verdict::CompilationError => "CompilationError",
+            Verdict::RuntimeError => "RuntimeError",
+            Verdict::TimeLimitExceeded => "TimeLimitExceeded",
+            Verdict::MemoLimitExceeded => "MemoLimitExceeded",
+            Verdict::SystemError => "SystemError",
+            Verdict::Cancelled => "Cancelled",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Verdict> {
+        let verdict = match s {
+            "Pending" => Verdict::Pending,
+            "Judging" => Verdict::Judging,
+            "Accepted" => Verdict::Accepted,
+            "WrongAnswer" => Verdict::WrongAnswer,
+            "CompilationError" => Verdict::CompilationError,
+            "RuntimeError" => Verdict::RuntimeError,
+            "TimeLimitExceeded" => Verdict::TimeLimitExceeded,
+            "MemoLimitExceeded" => Verdict::MemoLimitExceeded,
+            "SystemError" => Verdict::SystemError,
+            "Cancelled" => Verdict::Cancelled,
+            _ => return None,
+        };
+        Some(verdict)
+    }
+
+    /// Whether judging is over.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, Verdict::Pending | Verdict::Judging)
+    }
 }
@@ -0,0 +1,398 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use toml::{Table, Value};
+
+use super::{
+    cancel::CancellationToken,
+    compiler::{self, Compiler},
+    consts::{
+        DEFAULT_MEMORY_LIMIT, DEFAULT_PIDS_LIMIT, DEFAULT_PLAYGROUND_MAX_QUEUED,
+        DEFAULT_PLAYGROUND_OUTPUT_LIMIT, DEFAULT_PLAYGROUND_TIME_LIMIT, LANG_EXTENSIONS,
+    },
+    file,
+    resource::ResourceLimits,
+    runner::Runner,
+    sandbox::{ExitStatus, KillReason, SandboxResult},
+    workspace::WorkspaceManager,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    LanguageNotFoundError,
+    CompilationError(String),
+    FileSystemError(String),
+    SandboxError(String),
+}
+
+/// How a playground run ended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RunStatus {
+    Exited(i32),
+    Signaled(i32),
+    TimeLimitExceeded,
+    MemoLimitExceeded,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Exited(_) => "Exited",
+            RunStatus::Signaled(_) => "Signaled",
+            RunStatus::TimeLimitExceeded => "TimeLimitExceeded",
+            RunStatus::MemoLimitExceeded => "MemoLimitExceeded",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RunOutput {
+    pub status: RunStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// whether `stdout` or `stderr` was cut at the output limit
+    pub truncated: bool,
+    pub time_cost: u64,
+    pub mem_cost: u64,
+}
+
+/// Limits of a playground run, `[playground]` in the config.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PlaygroundLimits {
+    pub limits: ResourceLimits,
+    /// bytes of stdout and of stderr kept
+    pub output_limit: u64,
+    /// runs queued or running at once, apart from the submissions
+    pub max_queued: u64,
+}
+
+impl Default for PlaygroundLimits {
+    fn default() -> PlaygroundLimits {
+        PlaygroundLimits::from_config(&Table::new())
+    }
+}
+
+impl PlaygroundLimits {
+    pub fn from_config(data: &Table) -> PlaygroundLimits {
+        let playground = match data.get("playground") {
+            Some(Value::Table(playground)) => playground.clone(),
+            None => Table::new(),
+            _ => panic!("config: [playground] should be set correctly"),
+        };
+        let get_limit = |key: &str, default: u64| match playground.get(key) {
+            Some(Value::Integer(val)) if *val > 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [playground] should be set correctly"),
+        };
+
+        PlaygroundLimits {
+            limits: ResourceLimits {
+                time_limit: get_limit("time", DEFAULT_PLAYGROUND_TIME_LIMIT),
+                memory_limit: get_limit("memory", DEFAULT_MEMORY_LIMIT),
+                pids_limit: get_limit("pids", DEFAULT_PIDS_LIMIT),
+                output_limit: None,
+            },
+            output_limit: get_limit("output_limit", DEFAULT_PLAYGROUND_OUTPUT_LIMIT),
+            max_queued: get_limit("max_queued", DEFAULT_PLAYGROUND_MAX_QUEUED),
+        }
+    }
+}
+
+/// Compiles and runs a program once on custom input, with the compiler and
+/// sandbox used for judging but limits of its own. Nothing is compared.
+pub struct Playground {
+    compiler: Arc<Compiler>,
+    runner: Arc<Runner>,
+    workspaces: Arc<WorkspaceManager>,
+    limits: PlaygroundLimits,
+    /// runs holding a [`RunSlot`]
+    queued: AtomicU64,
+}
+
+/// A place among the `max_queued` runs, given back when dropped, be it
+/// after the run or with a job that never ran.
+pub struct RunSlot {
+    playground: Arc<Playground>,
+}
+
+impl RunSlot {
+    pub fn run(&self, lang: &str, source_code: &str, stdin: &str) -> Result<RunOutput, Error> {
+        self.playground.run(lang, source_code, stdin)
+    }
+}
+
+impl Drop for RunSlot {
+    fn drop(&mut self) {
+        self.playground.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Playground {
+    pub fn new(
+        compiler: Arc<Compiler>,
+        runner: Arc<Runner>,
+        workspaces: Arc<WorkspaceManager>,
+    ) -> Playground {
+        Playground {
+            compiler,
+            runner,
+            workspaces,
+            limits: PlaygroundLimits::default(),
+            queued: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: PlaygroundLimits) -> Playground {
+        self.limits = limits;
+        self
+    }
+
+    pub fn get_limits(&self) -> PlaygroundLimits {
+        self.limits
+    }
+
+    /// `None` when `max_queued` runs are queued or running already.
+    pub fn reserve(self: &Arc<Self>) -> Option<RunSlot> {
+        let max_queued = self.limits.max_queued;
+        self.queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < max_queued).then_some(queued + 1)
+            })
+            .ok()?;
+        Some(RunSlot {
+            playground: self.clone(),
+        })
+    }
+
+    pub fn run(&self, lang: &str, source_code: &str, stdin: &str) -> Result<RunOutput, Error> {
+        if !LANG_EXTENSIONS.contains_key(lang) {
+            return Err(Error::LanguageNotFoundError);
+        }
+        let workspace = self
+            .workspaces
+            .create_for_run()
+            .map_err(|e| Error::FileSystemError(format!("{:?}", e)))?;
+
+        let source = file::save_source_code(0, source_code, lang, &workspace)
+            .map_err(Error::FileSystemError)?;
+        let executable_path = self
+            .compiler
+            .compile(&source, lang, &CancellationToken::new())
+            .map_err(|e| match e {
+                compiler::Error::CompilationError(log) => Error::CompilationError(log),
+                compiler::Error::LanguageNotFoundError => Error::LanguageNotFoundError,
+                compiler::Error::TimedOut(limit) => {
+                    Error::CompilationError(format!("compilation timed out after {limit} ms"))
+                }
+                compiler::Error::NoCompilationLogError => Error::FileSystemError(format!("{e:?}")),
+                compiler::Error::ForkFailed | compiler::Error::Cancelled => {
+                    Error::SandboxError(format!("{e:?}"))
+                }
+            })?;
+
+        let (stdin_path, stdout_path, stderr_path) = (
+            workspace.file("stdin"),
+            workspace.file("stdout"),
+            workspace.file("stderr"),
+        );
+        fs::write(&stdin_path, stdin).map_err(|e| Error::FileSystemError(format!("{e}")))?;
+
+        // one more byte than kept tells whether it was cut, the disk isn't
+        // filled meanwhile
+        let limits = ResourceLimits {
+            output_limit: Some(self.limits.output_limit + 1),
+            ..self.limits.limits
+        };
+        let SandboxResult {
+            status,
+            usage,
+            killed,
+        } = self
+            .runner
+            .run_once(
+                &executable_path,
+                lang,
+                &stdin_path,
+                &stdout_path,
+                &stderr_path,
+                limits,
+            )
+            .map_err(|e| Error::SandboxError(format!("{:?}", e)))?;
+
+        let mem_cost = usage.mem_cost.unwrap_or(0);
+        let status = if usage.oom_killed || mem_cost > limits.memory_limit {
+            RunStatus::MemoLimitExceeded
+        } else if status == ExitStatus::Signaled(nix::libc::SIGXCPU)
+            || killed == Some(KillReason::WallTimeLimit)
+            || usage.time_cost > limits.time_limit
+        {
+            RunStatus::TimeLimitExceeded
+        } else {
+            match status {
+                ExitStatus::Exited(code) => RunStatus::Exited(code),
+                ExitStatus::Signaled(signal) => RunStatus::Signaled(signal),
+            }
+        };
+
+        let (stdout, stdout_truncated) = read_truncated(&stdout_path, self.limits.output_limit);
+        let (stderr, stderr_truncated) = read_truncated(&stderr_path, self.limits.output_limit);
+        let exceeded_output = status == RunStatus::Signaled(nix::libc::SIGXFSZ);
+        Ok(RunOutput {
+            status,
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated || exceeded_output,
+            time_cost: usage.time_cost,
+            mem_cost,
+        })
+    }
+}
+
+/// At most `limit` bytes of the file, lossily decoded, and whether there was more.
+fn read_truncated(path: &str, limit: u64) -> (String, bool) {
+    let Ok(file) = File::open(path) else {
+        return (String::new(), false);
+    };
+    let mut buf = vec![];
+    // one more byte tells whether it was cut
+    if file.take(limit + 1).read_to_end(&mut buf).is_err() {
+        return (String::new(), false);
+    }
+    let truncated = buf.len() as u64 > limit;
+    buf.truncate(limit as usize);
+    (String::from_utf8_lossy(&buf).into_owned(), truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, sync::Arc};
+
+    use nix::libc;
+
+    use crate::config::Config;
+    use crate::judge::{
+        compiler::Compiler,
+        consts::CONFIG_PATH,
+        resource::ResourceLimits,
+        runner::Runner,
+        sandbox::{ExitStatus, FakeSandbox, KillReason, Sandbox, SandboxResult},
+        workspace::WorkspaceManager,
+    };
+
+    use super::{Error, Playground, PlaygroundLimits, RunStatus};
+
+    /// Compiles unless the source says `error`, or is killed for its wall
+    /// time if it says `loop`, then prints twice the number it reads and
+    /// exits with 3. Told to `flood`, it's stopped at the output limit.
+    fn sandbox() -> Arc<dyn Sandbox> {
+        Arc::new(FakeSandbox::new(|command, stdin| {
+            if command.stdin.is_none() {
+                let source =
+                    fs::read_to_string(command.command.last().unwrap()).unwrap_or_default();
+                if source.contains("loop") {
+                    let killed = SandboxResult {
+                        status: ExitStatus::Signaled(libc::SIGKILL),
+                        killed: Some(KillReason::WallTimeLimit),
+                        ..FakeSandbox::exited(0)
+                    };
+                    return (String::new(), killed);
+                }
+                let code = if source.contains("error") { 1 } else { 0 };
+                return (
+                    "error: expected expression".to_string(),
+                    FakeSandbox::exited(code),
+                );
+            }
+            if stdin == "flood" {
+                let output_limit = command.limits.unwrap().output_limit.unwrap();
+                let stopped = SandboxResult {
+                    status: ExitStatus::Signaled(libc::SIGXFSZ),
+                    ..FakeSandbox::exited(0)
+                };
+                return ("y".repeat(output_limit as usize), stopped);
+            }
+            let doubled = 2 * stdin.trim().parse::<i64>().unwrap();
+            (doubled.to_string(), FakeSandbox::exited(3))
+        }))
+    }
+
+    fn playground(name: &str, output_limit: u64, max_queued: u64) -> (Arc<Playground>, PathBuf) {
+        let root =
+            std::env::temp_dir().join(format!("coj-playground-{name}-{}", std::process::id()));
+        let config = Config::load(CONFIG_PATH);
+        let sandbox = sandbox();
+        let playground = Playground::new(
+            Arc::new(Compiler::new(&config, sandbox.clone())),
+            Arc::new(Runner::new(&config, sandbox)),
+            Arc::new(WorkspaceManager::new(&root, false).unwrap()),
+        )
+        .with_limits(PlaygroundLimits {
+            limits: ResourceLimits::default(),
+            output_limit,
+            max_queued,
+        });
+        (Arc::new(playground), root)
+    }
+
+    #[test]
+    fn test_run_with_custom_input() {
+        let (playground, root) = playground("input", 1, 1);
+        let output = playground.run("cpp", "int main() {}", "21").unwrap();
+
+        assert_eq!(output.status, RunStatus::Exited(3));
+        assert_eq!(output.stdout, "4");
+        assert!(output.truncated);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_output_is_capped_while_running() {
+        let (playground, root) = playground("flood", 4, 1);
+        let output = playground.run("cpp", "int main() {}", "flood").unwrap();
+
+        assert_eq!(output.status, RunStatus::Signaled(libc::SIGXFSZ));
+        assert_eq!(output.stdout, "yyyy");
+        assert!(output.truncated);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_compilation_error() {
+        let (playground, root) = playground("compile", 1024, 1);
+        let ret = playground.run("cpp", "int main() { return error }", "");
+        assert_eq!(
+            ret,
+            Err(Error::CompilationError(
+                "error: expected expression".to_string()
+            ))
+        );
+        assert_eq!(
+            playground.run("brainfuck", "", ""),
+            Err(Error::LanguageNotFoundError)
+        );
+        let Err(Error::CompilationError(log)) = playground.run("cpp", "// loop", "") else {
+            panic!("a compiler killed for its wall time is a compilation error");
+        };
+        assert!(log.starts_with("compilation timed out after "), "{log}");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_runs_are_capped() {
+        let (playground, root) = playground("capped", 1024, 2);
+        let first = playground.reserve().unwrap();
+        let second = playground.reserve().unwrap();
+        assert!(playground.reserve().is_none());
+
+        assert_eq!(second.run("cpp", "int main() {}", "1").unwrap().stdout, "2");
+        drop(first);
+        assert!(playground.reserve().is_some());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
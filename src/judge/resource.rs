@@ -0,0 +1,99 @@
+use nix::libc;
+use toml::{Table, Value};
+
+use super::consts::{DEFAULT_MEMORY_LIMIT, DEFAULT_PIDS_LIMIT, DEFAULT_TIME_LIMIT};
+
+/// Limits applied to a single run, time in milliseconds and memory in KiB.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ResourceLimits {
+    pub time_limit: u64,
+    pub memory_limit: u64,
+    /// only enforced by the cgroup, `RLIMIT_NPROC` counts every process of
+    /// the uid, the judge's own threads included, and root ignores it
+    pub pids_limit: u64,
+    /// bytes a run may write to a file, its output included, `None` for no
+    /// cap; past it writes fail and `SIGXFSZ` is sent
+    pub output_limit: Option<u64>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> ResourceLimits {
+        ResourceLimits {
+            time_limit: DEFAULT_TIME_LIMIT,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            pids_limit: DEFAULT_PIDS_LIMIT,
+            output_limit: None,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// `[limits]`, per testcase.
+    pub fn from_config(data: &Table) -> ResourceLimits {
+        let limits = match data.get("limits") {
+            Some(Value::Table(limits)) => limits,
+            None => return ResourceLimits::default(),
+            _ => panic!("config: [limits] should be set correctly"),
+        };
+        let get_limit = |key: &str, default: u64| match limits.get(key) {
+            Some(Value::Integer(val)) if *val > 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [limits] should be set correctly"),
+        };
+
+        ResourceLimits {
+            time_limit: get_limit("time", DEFAULT_TIME_LIMIT),
+            memory_limit: get_limit("memory", DEFAULT_MEMORY_LIMIT),
+            pids_limit: get_limit("pids", DEFAULT_PIDS_LIMIT),
+            output_limit: None,
+        }
+    }
+
+    /// Milliseconds of wall-clock time before a run is killed, generous so
+    /// that programs blocked on nothing still end up as TLE.
+    pub fn wall_time_limit(&self) -> u64 {
+        self.time_limit * 3 + 1000
+    }
+
+    /// Applies the limits with `setrlimit`, only async-signal-safe calls are
+    /// made so it can be used between `fork` and `exec`.
+    pub fn apply_rlimits(&self, with_memory: bool) {
+        // whole seconds, one more to leave room for the exact check afterwards
+        let cpu_seconds = self.time_limit / 1000 + 1;
+        set_rlimit(libc::RLIMIT_CPU, cpu_seconds);
+        if with_memory {
+            set_rlimit(libc::RLIMIT_AS, self.memory_limit * 1024);
+        }
+        set_rlimit(libc::RLIMIT_CORE, 0);
+        if let Some(output_limit) = self.output_limit {
+            set_rlimit(libc::RLIMIT_FSIZE, output_limit);
+        }
+    }
+}
+
+fn set_rlimit(resource: libc::__rlimit_resource_t, value: u64) {
+    let rlimit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    unsafe { libc::setrlimit(resource, &rlimit) };
+}
+
+/// Resources consumed by a single run, time in milliseconds and memory in KiB.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ResourceUsage {
+    pub time_cost: u64,
+    pub mem_cost: Option<u64>,
+    pub oom_killed: bool,
+}
+
+impl ResourceUsage {
+    pub fn from_rusage(rusage: &libc::rusage) -> ResourceUsage {
+        let to_millis = |tv: &libc::timeval| tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000;
+        ResourceUsage {
+            time_cost: to_millis(&rusage.ru_utime) + to_millis(&rusage.ru_stime),
+            mem_cost: Some(rusage.ru_maxrss as u64),
+            oom_killed: false,
+        }
+    }
+}
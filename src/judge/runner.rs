@@ -1,27 +1,31 @@
 use std::{
     collections::BTreeMap,
-    ffi::CString,
-    fmt, fs,
+    fmt,
     ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc, Mutex,
     },
+    time::Instant,
 };
 
-use nix::{
-    libc,
-    sys::wait::waitpid,
-    unistd::{self, fork, ForkResult},
-};
-use toml::{Table, Value};
+use nix::libc;
 
-use crate::{
-    c_string,
-    judge::{consts::CONFIG_PATH, file::Testcase},
+use crate::config::Config;
+use crate::judge::{
+    cancel::CancellationToken,
+    file::{self, compare_testcase_names, Testcase},
+};
+use crate::metrics::METRICS;
+
+use super::{
+    comparer::{self, Comparer, ComparerResult},
+    events::{EventBus, JudgeEvent},
+    resource::ResourceLimits,
+    sandbox::{self, ExitStatus, KillReason, Sandbox, SandboxCommand, SandboxResult},
+    workspace::Workspace,
+    Verdict,
 };
-
-use super::comparer::{self, Comparer, ComparerResult};
 
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone)]
 pub enum FileType {
@@ -42,25 +46,46 @@ pub enum Error {
 
 pub struct Runner {
     running_recipe: Mutex<BTreeMap<String, Option<Vec<String>>>>,
+    limits: ResourceLimits,
+    sandbox: Arc<dyn Sandbox>,
 }
 
-type DeferFn = Box<dyn FnOnce(Arc<RunnerJobSharedData>) + Send + 'static>;
+/// Called once with the shared data after the last testcase is checked.
+pub type DeferFn = Box<dyn FnOnce(Arc<RunnerJobSharedData>) + Send + 'static>;
 
-struct RunnerJobSharedData {
+/// The outcome of a single testcase, time in milliseconds and memory in KiB.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TestcaseResult {
+    pub testcase: String,
+    pub verdict: Verdict,
+    pub time_cost: u64,
+    pub mem_cost: u64,
+}
+
+pub struct RunnerJobSharedData {
     cnt_testcases: AtomicUsize,
     cnt_checked: AtomicUsize,
     cnt_wrong_answer: AtomicUsize,
+    cnt_runtime_error: AtomicUsize,
+    cnt_time_limit_exceeded: AtomicUsize,
+    cnt_memo_limit_exceeded: AtomicUsize,
     mem_cost: AtomicU64,
     time_cost: AtomicU64,
     // FIXME shared_data may be deadlocked?
     defer: Mutex<Option<DeferFn>>,
     executable_path: Mutex<String>,
-    command: Mutex<Vec<CString>>,
+    command: Vec<String>,
+    limits: ResourceLimits,
+    sandbox: Arc<dyn Sandbox>,
+    cancel: CancellationToken,
+    workspace: Arc<Workspace>,
+    events: Arc<EventBus>,
+    results: Mutex<Vec<TestcaseResult>>,
 }
 
 impl RunnerJobSharedData {
     pub fn get_cnt_testcases(&self) -> usize {
-        self.cnt_checked.load(Ordering::SeqCst)
+        self.cnt_testcases.load(Ordering::SeqCst)
     }
 
     pub fn get_cnt_checked(&self) -> usize {
@@ -71,6 +96,18 @@ impl RunnerJobSharedData {
         self.cnt_wrong_answer.load(Ordering::SeqCst)
     }
 
+    pub fn get_cnt_runtime_error(&self) -> usize {
+        self.cnt_runtime_error.load(Ordering::SeqCst)
+    }
+
+    pub fn get_cnt_time_limit_exceeded(&self) -> usize {
+        self.cnt_time_limit_exceeded.load(Ordering::SeqCst)
+    }
+
+    pub fn get_cnt_memo_limit_exceeded(&self) -> usize {
+        self.cnt_memo_limit_exceeded.load(Ordering::SeqCst)
+    }
+
     pub fn get_mem_cost(&self) -> u64 {
         self.mem_cost.load(Ordering::SeqCst)
     }
@@ -78,6 +115,31 @@ impl RunnerJobSharedData {
     pub fn get_time_cost(&self) -> u64 {
         self.time_cost.load(Ordering::SeqCst)
     }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Results of the testcases checked so far, ordered by testcase name,
+    /// see [`compare_testcase_names`].
+    pub fn get_results(&self) -> Vec<TestcaseResult> {
+        let mut results = self.results.lock().unwrap().clone();
+        results.sort_by(|a, b| compare_testcase_names(&a.testcase, &b.testcase));
+        results
+    }
+
+    /// The verdict of the first testcase that didn't pass, `Accepted` if all
+    /// did, `Cancelled` if the submission was cancelled before the end.
+    pub fn get_verdict(&self) -> Verdict {
+        if self.is_cancelled() {
+            return Verdict::Cancelled;
+        }
+        self.get_results()
+            .into_iter()
+            .map(|result| result.verdict)
+            .find(|verdict| *verdict != Verdict::Accepted)
+            .unwrap_or(Verdict::Accepted)
+    }
 }
 
 impl fmt::Debug for RunnerJobSharedData {
@@ -86,28 +148,53 @@ impl fmt::Debug for RunnerJobSharedData {
             .field("cnt_testcases", &self.get_cnt_testcases())
             .field("cnt_checked", &self.get_cnt_checked())
             .field("cnt_wrong_answer", &self.get_cnt_wrong_answer())
+            .field("cnt_runtime_error", &self.get_cnt_runtime_error())
+            .field(
+                "cnt_time_limit_exceeded",
+                &self.get_cnt_time_limit_exceeded(),
+            )
+            .field(
+                "cnt_memo_limit_exceeded",
+                &self.get_cnt_memo_limit_exceeded(),
+            )
             .field("mem_cost", &self.get_mem_cost())
             .field("time_cost", &self.get_time_cost())
+            .field("cancelled", &self.is_cancelled())
             .finish()
     }
 }
 
 impl RunnerJobSharedData {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         cnt_testcases: usize,
         defer: DeferFn,
         execuable_path: String,
-        command: Vec<CString>,
+        command: Vec<String>,
+        limits: ResourceLimits,
+        sandbox: Arc<dyn Sandbox>,
+        cancel: CancellationToken,
+        workspace: Arc<Workspace>,
+        events: Arc<EventBus>,
     ) -> RunnerJobSharedData {
         RunnerJobSharedData {
             cnt_testcases: AtomicUsize::new(cnt_testcases),
             cnt_checked: AtomicUsize::new(0usize),
             cnt_wrong_answer: AtomicUsize::new(0usize),
+            cnt_runtime_error: AtomicUsize::new(0usize),
+            cnt_time_limit_exceeded: AtomicUsize::new(0usize),
+            cnt_memo_limit_exceeded: AtomicUsize::new(0usize),
             mem_cost: AtomicU64::new(0u64),
             time_cost: AtomicU64::new(0u64),
             defer: Mutex::new(Some(defer)),
             executable_path: Mutex::new(execuable_path),
-            command: Mutex::new(command),
+            command,
+            limits,
+            sandbox,
+            cancel,
+            workspace,
+            events,
+            results: Mutex::new(vec![]),
         }
     }
 
@@ -136,6 +223,11 @@ pub struct RunnerJob {
 }
 
 impl RunnerJob {
+    /// The submission the testcase is run for, 0 for playground runs.
+    pub fn get_submission_id(&self) -> u64 {
+        self.shared_data.workspace.get_submission_id()
+    }
+
     fn new(testcase: Testcase, shared_data: Arc<RunnerJobSharedData>) -> RunnerJob {
         RunnerJob {
             testcase,
@@ -143,7 +235,63 @@ impl RunnerJob {
         }
     }
 
+    /// Shared by all the jobs of a submission, cancelling it kills the
+    /// running ones and skips the rest.
+    pub fn get_cancellation_token(&self) -> CancellationToken {
+        self.shared_data.cancel.clone()
+    }
+
     pub fn execute_once(self) -> Result<(), Error> {
+        if self.shared_data.is_cancelled() {
+            self.finish();
+            return Ok(());
+        }
+
+        let started_at = Instant::now();
+        let ret = self.judge();
+        METRICS
+            .run_duration
+            .observe(started_at.elapsed().as_secs_f64());
+        let result = match &ret {
+            Ok(result) => result.clone(),
+            // still recorded, a submission must finish even if a testcase can't be judged
+            Err(_) => Some(TestcaseResult {
+                testcase: self.testcase.input_file.get_name().to_string(),
+                verdict: Verdict::SystemError,
+                time_cost: 0,
+                mem_cost: 0,
+            }),
+        };
+        if let Some(result) = result {
+            METRICS.record_testcase(result.verdict);
+            if result.verdict != Verdict::Accepted {
+                self.shared_data.workspace.mark_failed();
+            }
+            let checked = {
+                let mut results = self.shared_data.results.lock().unwrap();
+                results.push(result.clone());
+                results.len()
+            };
+            self.shared_data.events.publish(
+                self.shared_data.workspace.get_submission_id(),
+                JudgeEvent::TestcaseFinished {
+                    testcase: result.testcase,
+                    verdict: result.verdict,
+                    time_cost: result.time_cost,
+                    mem_cost: result.mem_cost,
+                    checked,
+                    total: self.shared_data.get_cnt_testcases(),
+                },
+            );
+        }
+
+        self.finish();
+
+        ret.map(|_| ())
+    }
+
+    /// Runs the testcase, `None` if it was cancelled meanwhile.
+    fn judge(&self) -> Result<Option<TestcaseResult>, Error> {
         let exec_stdout_path = format!(
             "{}-{}-stdout",
             *self.shared_data.executable_path.lock().unwrap(),
@@ -161,161 +309,168 @@ impl RunnerJob {
         let testcase_input_path = input_file.get_path();
         let testcase_output_path = output_file.get_path();
 
-        match unsafe { fork() } {
-            Ok(ForkResult::Parent { child, .. }) => {
-                waitpid(child, None).unwrap();
-            }
-            Ok(ForkResult::Child) => {
-                let testcase_input_path = c_string!(testcase_input_path);
-                let exec_stdout_path = c_string!(exec_stdout_path.as_str());
-                let exec_stderr_path = c_string!(exec_stderr_path.as_str());
-                let r_mode = c_string!("r");
-                let w_mode = c_string!("w");
-
-                unsafe {
-                    let stdin = libc::fdopen(libc::STDIN_FILENO, r_mode.as_ptr());
-                    libc::freopen(testcase_input_path.as_ptr(), r_mode.as_ptr(), stdin);
-                    let stdout = libc::fdopen(libc::STDOUT_FILENO, w_mode.as_ptr());
-                    libc::freopen(exec_stdout_path.as_ptr(), w_mode.as_ptr(), stdout);
-                    let stderr = libc::fdopen(libc::STDERR_FILENO, w_mode.as_ptr());
-                    libc::freopen(exec_stderr_path.as_ptr(), w_mode.as_ptr(), stderr);
-                }
-
-                let command = self.shared_data.command.lock().unwrap();
-                let command = command.deref();
-                match unistd::execvp(&command[0], command) {
-                    Ok(_) => unreachable!(),
-                    Err(errno) => unistd::write(
-                        libc::STDERR_FILENO,
-                        format!(
-                            "Execvp error, errno = {:?}, testcase input file path: {:?}\n",
-                            errno, testcase_input_path
-                        )
-                        .as_bytes(),
-                    )
-                    .ok(),
-                };
-                unsafe {
-                    libc::exit(0);
-                }
+        let limits = self.shared_data.limits;
+        let command = SandboxCommand::new(self.shared_data.command.clone())
+            .stdin(testcase_input_path)
+            .stdout(&exec_stdout_path)
+            .stderr(&exec_stderr_path)
+            .limits(limits)
+            .cancel(self.shared_data.cancel.clone())
+            .workspace(&file::get_workspace_of(&exec_stdout_path));
+        let SandboxResult {
+            status,
+            usage,
+            killed,
+        } = self.shared_data.sandbox.run(&command).map_err(|e| {
+            METRICS.record_sandbox_failure(&e);
+            match e {
+                sandbox::Error::CommandEmptyError => Error::CommandEmptyError,
+                sandbox::Error::ForkFailed => Error::ForkFailed,
+                sandbox::Error::FileSystemError(_) => Error::FileSystemError,
             }
-            _ => panic!("Fork failed"),
-        }
+        })?;
 
-        let result = Comparer::new(testcase_output_path, &exec_stdout_path)
-            .compare()
-            .map_err(|e| match e {
-                comparer::Error::FileSystemError => Error::FileSystemError,
-                comparer::Error::IOError => Error::IOError,
-            })?;
-        // TODO do clean
+        let mem_used = usage.mem_cost.unwrap_or(0);
+        let time_elapsed = usage.time_cost;
 
-        if result != ComparerResult::Consistent {
+        let exceeded_cpu = status == ExitStatus::Signaled(libc::SIGXCPU)
+            || killed == Some(KillReason::WallTimeLimit);
+        let verdict = if killed == Some(KillReason::Cancelled) {
+            // the verdict no longer matters
+            return Ok(None);
+        } else if usage.oom_killed || mem_used > limits.memory_limit {
             self.shared_data
-                .cnt_wrong_answer
+                .cnt_memo_limit_exceeded
                 .fetch_add(1, Ordering::SeqCst);
-        }
-
-        // TODO mem and time
-        let mem_used = 1u64;
-        let time_elapsed = 1u64;
+            Verdict::MemoLimitExceeded
+        } else if exceeded_cpu || time_elapsed > limits.time_limit {
+            self.shared_data
+                .cnt_time_limit_exceeded
+                .fetch_add(1, Ordering::SeqCst);
+            Verdict::TimeLimitExceeded
+        } else if !status.success() {
+            self.shared_data
+                .cnt_runtime_error
+                .fetch_add(1, Ordering::SeqCst);
+            Verdict::RuntimeError
+        } else {
+            let result = Comparer::new(testcase_output_path, &exec_stdout_path)
+                .compare()
+                .map_err(|e| {
+                    METRICS.record_compare_failure(&e);
+                    match e {
+                        comparer::Error::FileSystemError => Error::FileSystemError,
+                        comparer::Error::IOError => Error::IOError,
+                    }
+                })?;
+
+            if result != ComparerResult::Consistent {
+                self.shared_data
+                    .cnt_wrong_answer
+                    .fetch_add(1, Ordering::SeqCst);
+                Verdict::WrongAnswer
+            } else {
+                Verdict::Accepted
+            }
+        };
 
         self.shared_data
             .mem_cost
-            .fetch_add(mem_used, Ordering::SeqCst);
+            .fetch_max(mem_used, Ordering::SeqCst);
         self.shared_data
             .time_cost
             .fetch_add(time_elapsed, Ordering::SeqCst);
 
-        self.shared_data.cnt_checked.fetch_add(1, Ordering::SeqCst);
+        Ok(Some(TestcaseResult {
+            testcase: input_file.get_name().to_string(),
+            verdict,
+            time_cost: time_elapsed,
+            mem_cost: mem_used,
+        }))
+    }
 
-        // dbg!(&result);
+    fn finish(&self) {
+        self.shared_data.cnt_checked.fetch_add(1, Ordering::SeqCst);
 
         if let Some(defer) = self.shared_data.is_complete() {
             defer(self.shared_data.clone());
         }
-
-        Ok(())
     }
 }
 
-impl Default for Runner {
-    fn default() -> Runner {
-        let mut recipe: BTreeMap<String, Option<Vec<String>>> = BTreeMap::new();
-
-        let config_text = match fs::read_to_string(CONFIG_PATH) {
-            Ok(s) => s,
-            Err(e) => panic!("config: `{CONFIG_PATH}` is missing: {e}"),
-        };
+impl Runner {
+    /// With the recipes of `[execute]` and the limits of `[limits]`, in
+    /// `sandbox`.
+    pub fn new(config: &Config, sandbox: Arc<dyn Sandbox>) -> Runner {
+        Runner {
+            running_recipe: Mutex::new(config.execute.clone()),
+            limits: config.limits,
+            sandbox,
+        }
+    }
 
-        let data: Table = match toml::from_str(&config_text) {
-            Ok(d) => d,
-            Err(e) => panic!("config: Can't read from `{CONFIG_PATH}`: {e}"),
-        };
+    pub fn get_limits(&self) -> ResourceLimits {
+        self.limits
+    }
 
-        let lang_lst = match data.get("languages") {
-            Some(v) => v,
-            _ => panic!("config: [languages] should be set correctly"),
-        };
-        let execution_commands = match data.get("execute") {
-            Some(v) => v,
-            _ => panic!("config: [execute] should be set correctly"),
-        };
+    /// The interpreters the recipes run, e.g. `python3`, not the compiled
+    /// `$target`s.
+    pub fn get_programs(&self) -> Vec<String> {
+        let running_recipe = self.running_recipe.lock().unwrap();
+        let mut programs: Vec<String> = running_recipe
+            .values()
+            .filter_map(|command_chain| command_chain.as_ref()?.first().cloned())
+            .filter(|program| !program.starts_with('$'))
+            .collect();
+        programs.sort();
+        programs.dedup();
+        programs
+    }
 
-        match lang_lst {
-            Value::Array(lang_lst) => {
-                for lang in lang_lst {
-                    if let Value::String(lang) = lang {
-                        recipe.insert(lang.clone(), None);
-                    } else {
-                        panic!("config: [languages] should be set correctly");
-                    }
-                }
-            }
-            _ => panic!("config: [languages] should be set correctly"),
-        };
+    pub fn get_sandbox(&self) -> Arc<dyn Sandbox> {
+        self.sandbox.clone()
+    }
 
-        match execution_commands {
-            Value::Table(execution_commands) => {
-                for (lang, val) in execution_commands.iter() {
-                    if !recipe.contains_key(lang) {
-                        continue;
-                    }
-                    let val = match val {
-                        Value::String(val) => val,
-                        _ => panic!("config: [execute] should be set correctly"),
-                    };
-                    let command_chain: Vec<String> = val
-                        .split_ascii_whitespace()
-                        .map(|s| s.to_string())
-                        .collect();
-                    *recipe.get_mut(lang).unwrap() = Some(command_chain);
-                }
+    /// Runs the program once with the given streams and limits, without
+    /// judging anything.
+    pub fn run_once(
+        &self,
+        executable_path: &str,
+        lang: &str,
+        stdin_path: &str,
+        stdout_path: &str,
+        stderr_path: &str,
+        limits: ResourceLimits,
+    ) -> Result<SandboxResult, Error> {
+        let command = self.generate_execution_command(executable_path, lang)?;
+        let command = SandboxCommand::new(command)
+            .stdin(stdin_path)
+            .stdout(stdout_path)
+            .stderr(stderr_path)
+            .limits(limits)
+            .workspace(&file::get_workspace_of(executable_path));
+        self.sandbox.run(&command).map_err(|e| {
+            METRICS.record_sandbox_failure(&e);
+            match e {
+                sandbox::Error::CommandEmptyError => Error::CommandEmptyError,
+                sandbox::Error::ForkFailed => Error::ForkFailed,
+                sandbox::Error::FileSystemError(_) => Error::FileSystemError,
             }
-            _ => panic!("config: [execute] should be set correctly"),
-        }
-
-        // dbg!(&recipe);
-
-        Runner {
-            running_recipe: Mutex::new(recipe),
-        }
+        })
     }
-}
 
-impl Runner {
     fn generate_execution_command(
         &self,
         executable_path: &str,
         lang: &str,
-    ) -> Result<Vec<CString>, Error> {
+    ) -> Result<Vec<String>, Error> {
         let running_recipe = self.running_recipe.lock().unwrap();
         let command_chain = match running_recipe.get(lang) {
             Some(Some(chain)) => chain,
             _ => return Err(Error::LanguageNotFoundError),
         };
 
-        let mut command = Vec::<CString>::new();
+        let mut command = Vec::<String>::new();
         for token in command_chain {
             let mut token: &str = token;
             if token.starts_with('$') {
@@ -325,7 +480,7 @@ impl Runner {
                     _ => { /* do nothing */ }
                 }
             }
-            command.push(c_string!(token));
+            command.push(token.to_string());
         }
 
         if command.is_empty() {
@@ -335,28 +490,34 @@ impl Runner {
         Ok(command)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn execute(
         &self,
         executable_path: String,
         lang: &str,
-        testcases: &Vec<Testcase>,
+        testcases: &[Testcase],
+        workspace: Arc<Workspace>,
+        cancel: CancellationToken,
+        events: Arc<EventBus>,
+        defer: DeferFn,
     ) -> Result<Vec<RunnerJob>, Error> {
         let command = self.generate_execution_command(&executable_path, lang)?;
         // dbg!(&*command);
 
-        let defer = Box::new(|shared_data: Arc<RunnerJobSharedData>| {
-            // dbg!(&*shared_data);
-            // TODO write into Database
-        });
         let shared_data = Arc::new(RunnerJobSharedData::new(
             testcases.len(),
             defer,
             executable_path,
             command,
+            self.limits,
+            self.sandbox.clone(),
+            cancel,
+            workspace,
+            events,
         ));
 
         let mut runner_jobs = vec![];
-        for (_, testcase) in testcases.iter().enumerate() {
+        for testcase in testcases.iter() {
             let runner_job = RunnerJob::new(testcase.clone(), shared_data.clone());
             runner_jobs.push(runner_job);
         }
@@ -364,3 +525,128 @@ impl Runner {
         Ok(runner_jobs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use crate::config::Config;
+    use crate::judge::{
+        cancel::CancellationToken,
+        consts::CONFIG_PATH,
+        events::EventBus,
+        file::{Testcase, TestcaseFile},
+        sandbox::{ExitStatus, FakeSandbox, SandboxResult},
+        workspace::{Workspace, WorkspaceManager},
+        Verdict,
+    };
+
+    use super::Runner;
+
+    fn prepare_testcases(
+        name: &str,
+        cases: &[(&str, &str)],
+    ) -> (String, Arc<Workspace>, Vec<Testcase>) {
+        let dir = std::env::temp_dir().join(format!("coj-runner-{name}-{}", std::process::id()));
+        let workspace = WorkspaceManager::new(&dir, false)
+            .unwrap()
+            .create(1)
+            .unwrap();
+        let dir = dir.display().to_string();
+
+        let mut testcases = vec![];
+        for (i, (input, output)) in cases.iter().enumerate() {
+            let input_path = format!("{dir}/{i}.in");
+            let output_path = format!("{dir}/{i}.out");
+            fs::write(&input_path, input).unwrap();
+            fs::write(&output_path, output).unwrap();
+            testcases.push(Testcase {
+                input_file: TestcaseFile::new(&format!("{i}.in"), &input_path),
+                output_file: TestcaseFile::new(&format!("{i}.out"), &output_path),
+            });
+        }
+
+        (dir, Arc::new(workspace), testcases)
+    }
+
+    #[test]
+    fn test_verdicts_with_fake_sandbox() {
+        let (dir, workspace, testcases) = prepare_testcases(
+            "verdicts",
+            &[
+                ("1\n", "1\n"),
+                ("2\n", "3\n"),
+                ("crash\n", ""),
+                ("slow\n", ""),
+            ],
+        );
+        let sandbox = FakeSandbox::new(|_, stdin| {
+            let result = match stdin {
+                "crash\n" => FakeSandbox::exited(139),
+                "slow\n" => SandboxResult {
+                    status: ExitStatus::Signaled(nix::libc::SIGXCPU),
+                    ..FakeSandbox::exited(0)
+                },
+                _ => FakeSandbox::exited(0),
+            };
+            (stdin.to_string(), result)
+        });
+        let runner = Runner::new(&Config::load(CONFIG_PATH), Arc::new(sandbox));
+
+        let jobs = runner
+            .execute(
+                workspace.file("main"),
+                "cpp",
+                &testcases,
+                workspace,
+                CancellationToken::new(),
+                Arc::new(EventBus::default()),
+                Box::new(|_| {}),
+            )
+            .unwrap();
+        let shared_data = jobs[0].shared_data.clone();
+        for job in jobs {
+            job.execute_once().unwrap();
+        }
+
+        assert_eq!(shared_data.get_cnt_checked(), 4);
+        assert_eq!(shared_data.get_cnt_wrong_answer(), 1);
+        assert_eq!(shared_data.get_cnt_runtime_error(), 1);
+        assert_eq!(shared_data.get_cnt_time_limit_exceeded(), 1);
+        assert_eq!(shared_data.get_cnt_memo_limit_exceeded(), 0);
+        assert_eq!(shared_data.get_verdict(), Verdict::WrongAnswer);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_jobs_are_skipped() {
+        let (dir, workspace, testcases) =
+            prepare_testcases("cancel", &[("1\n", "1\n"), ("2\n", "3\n")]);
+        let sandbox = Arc::new(FakeSandbox::default());
+        let runner = Runner::new(&Config::load(CONFIG_PATH), sandbox.clone());
+
+        let jobs = runner
+            .execute(
+                workspace.file("main"),
+                "cpp",
+                &testcases,
+                workspace,
+                CancellationToken::new(),
+                Arc::new(EventBus::default()),
+                Box::new(|_| {}),
+            )
+            .unwrap();
+        let shared_data = jobs[0].shared_data.clone();
+        jobs[0].get_cancellation_token().cancel();
+        for job in jobs {
+            job.execute_once().unwrap();
+        }
+
+        assert!(shared_data.is_cancelled());
+        assert_eq!(shared_data.get_verdict(), Verdict::Cancelled);
+        assert_eq!(shared_data.get_cnt_checked(), 2);
+        assert_eq!(shared_data.get_cnt_wrong_answer(), 0);
+        assert!(sandbox.history().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
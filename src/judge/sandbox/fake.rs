@@ -0,0 +1,93 @@
+use std::{fs, sync::Mutex};
+
+use nix::libc;
+
+use crate::judge::resource::ResourceUsage;
+
+use super::{Error, ExitStatus, KillReason, Sandbox, SandboxCommand, SandboxResult};
+
+type Handler = Box<dyn Fn(&SandboxCommand, &str) -> (String, SandboxResult) + Send + Sync>;
+
+/// Runs nothing, a handler maps the command and its stdin to what the
+/// program would have printed. Meant for tests without real toolchains.
+pub struct FakeSandbox {
+    handler: Handler,
+    history: Mutex<Vec<SandboxCommand>>,
+}
+
+impl Default for FakeSandbox {
+    /// Behaves like `cat`: stdin is echoed to stdout.
+    fn default() -> FakeSandbox {
+        FakeSandbox::new(|_, stdin| (stdin.to_string(), FakeSandbox::exited(0)))
+    }
+}
+
+impl FakeSandbox {
+    pub fn new<F>(handler: F) -> FakeSandbox
+    where
+        F: Fn(&SandboxCommand, &str) -> (String, SandboxResult) + Send + Sync + 'static,
+    {
+        FakeSandbox {
+            handler: Box::new(handler),
+            history: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn exited(code: i32) -> SandboxResult {
+        SandboxResult {
+            status: ExitStatus::Exited(code),
+            usage: ResourceUsage {
+                time_cost: 1,
+                mem_cost: Some(1),
+                oom_killed: false,
+            },
+            killed: None,
+        }
+    }
+
+    /// Commands run so far, in order.
+    pub fn history(&self) -> Vec<SandboxCommand> {
+        self.history.lock().unwrap().clone()
+    }
+}
+
+impl Sandbox for FakeSandbox {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn run(&self, command: &SandboxCommand) -> Result<SandboxResult, Error> {
+        if command.command.is_empty() {
+            return Err(Error::CommandEmptyError);
+        }
+        // recorded without its token, which would keep the submission cancellable
+        self.history.lock().unwrap().push(SandboxCommand {
+            cancel: None,
+            ..command.clone()
+        });
+        if command.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Ok(SandboxResult {
+                status: ExitStatus::Signaled(libc::SIGKILL),
+                killed: Some(KillReason::Cancelled),
+                ..FakeSandbox::exited(0)
+            });
+        }
+
+        let stdin = match &command.stdin {
+            Some(path) => {
+                fs::read_to_string(path).map_err(|e| Error::FileSystemError(format!("{e}")))?
+            }
+            None => String::new(),
+        };
+        let (output, result) = (self.handler)(command, &stdin);
+
+        for path in [&command.stdout, &command.stderr].into_iter().flatten() {
+            fs::write(path, "").map_err(|e| Error::FileSystemError(format!("{e}")))?;
+        }
+        if let Some(path) = &command.stdout {
+            fs::write(path, output).map_err(|e| Error::FileSystemError(format!("{e}")))?;
+        }
+
+        Ok(result)
+    }
+}
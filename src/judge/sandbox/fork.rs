@@ -0,0 +1,173 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use nix::libc;
+
+use crate::judge::{
+    cgroup::{Cgroup, CgroupController},
+    resource::ResourceUsage,
+};
+
+use super::{
+    spawn::SpawnPlan,
+    wait::{wait_child, Reaped},
+    Error, ExitStatus, Sandbox, SandboxCommand, SandboxResult,
+};
+
+/// Runs programs in a plain forked child, limited by a cgroup when one is
+/// available and by rlimits otherwise.
+pub struct ForkSandbox {
+    cgroup: Option<Arc<CgroupController>>,
+}
+
+impl ForkSandbox {
+    pub fn new(cgroup: Option<Arc<CgroupController>>) -> ForkSandbox {
+        ForkSandbox { cgroup }
+    }
+}
+
+impl Sandbox for ForkSandbox {
+    fn name(&self) -> &'static str {
+        "fork"
+    }
+
+    fn run(&self, command: &SandboxCommand) -> Result<SandboxResult, Error> {
+        run_forked(command, self.cgroup.as_deref(), || {})
+    }
+}
+
+fn create_cgroup(
+    command: &SandboxCommand,
+    controller: Option<&CgroupController>,
+) -> Option<Cgroup> {
+    let (controller, limits) = (controller?, command.limits.as_ref()?);
+    match controller.create(limits) {
+        Ok(cgroup) => Some(cgroup),
+        Err(e) => {
+            tracing::warn!("cgroup: can't create a cgroup for this run: {e:?}");
+            None
+        }
+    }
+}
+
+/// Spawns the command with its redirections and limits, calls `pre_exec`
+/// in the child right before `execve` and waits for it within its wall-clock
+/// limit.
+pub(super) fn run_forked<F>(
+    command: &SandboxCommand,
+    controller: Option<&CgroupController>,
+    pre_exec: F,
+) -> Result<SandboxResult, Error>
+where
+    F: Fn(),
+{
+    let plan = SpawnPlan::new(command)?;
+    let cgroup = create_cgroup(command, controller);
+    let deadline = command
+        .get_wall_time_limit()
+        .map(|millis| Instant::now() + Duration::from_millis(millis));
+    let child = plan.spawn(cgroup.as_ref(), pre_exec)?;
+    // the redirections are only needed by the child
+    drop(plan);
+
+    let Reaped {
+        status,
+        rusage,
+        killed,
+    } = wait_child(&child, deadline, command.cancel.as_ref());
+    let attached = child.attached;
+
+    let mut usage = ResourceUsage::from_rusage(&rusage);
+    if let (Some(cgroup), true) = (&cgroup, attached) {
+        match cgroup.usage() {
+            Ok(cgroup_usage) => {
+                usage = ResourceUsage {
+                    mem_cost: cgroup_usage.mem_cost.or(usage.mem_cost),
+                    ..cgroup_usage
+                }
+            }
+            Err(e) => tracing::warn!("cgroup: can't read usage: {e:?}"),
+        }
+    }
+
+    let status = if libc::WIFSIGNALED(status) {
+        ExitStatus::Signaled(libc::WTERMSIG(status))
+    } else {
+        ExitStatus::Exited(libc::WEXITSTATUS(status))
+    };
+
+    Ok(SandboxResult {
+        status,
+        usage,
+        killed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use nix::libc;
+
+    use crate::judge::{
+        resource::ResourceLimits,
+        sandbox::{spawn::EXEC_FAILED, ExitStatus, Sandbox, SandboxCommand},
+    };
+
+    use super::ForkSandbox;
+
+    #[test]
+    fn test_redirections() {
+        let dir = std::env::temp_dir().join(format!("coj-fork-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in").display().to_string();
+        let output = dir.join("out").display().to_string();
+        fs::write(&input, "hello\n").unwrap();
+
+        let command = SandboxCommand::new(vec!["cat".to_string()])
+            .stdin(&input)
+            .stdout(&output);
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "hello\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let dir = std::env::temp_dir().join(format!("coj-fork-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out").display().to_string();
+
+        let limits = ResourceLimits {
+            output_limit: Some(10),
+            ..Default::default()
+        };
+        let command = SandboxCommand::new(vec!["yes".to_string()])
+            .stdout(&output)
+            .limits(limits);
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+
+        assert_eq!(result.status, ExitStatus::Signaled(libc::SIGXFSZ));
+        assert_eq!(fs::metadata(&output).unwrap().len(), 10);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let command = SandboxCommand::new(vec!["sh".into(), "-c".into(), "exit 3".into()]);
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(3));
+
+        let command = SandboxCommand::new(vec!["sh".into(), "-c".into(), "kill -9 $$".into()]);
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+        assert_eq!(result.status, ExitStatus::Signaled(9));
+
+        let command = SandboxCommand::new(vec!["no-such-program".into()]);
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(EXEC_FAILED));
+    }
+}
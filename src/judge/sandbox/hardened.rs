@@ -0,0 +1,409 @@
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+    sync::Arc,
+};
+
+use nix::libc;
+
+use crate::judge::cgroup::CgroupController;
+
+use super::{
+    fork::run_forked,
+    rootfs::RootFs,
+    spawn::{abort_child, SETUP_FAILED},
+    Error, Sandbox, SandboxCommand, SandboxResult,
+};
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+/// Set in the numbers of x32 syscalls, which pass the architecture check of
+/// x86_64 but aren't in the list.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// Syscalls a submission or a compiler never needs, they fail with `EPERM`.
+static DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_open_tree,
+    libc::SYS_move_mount,
+    libc::SYS_fsopen,
+    libc::SYS_fsconfig,
+    libc::SYS_fsmount,
+    libc::SYS_fspick,
+    libc::SYS_mount_setattr,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_enter,
+    libc::SYS_io_uring_register,
+];
+
+/// Syscalls signalling the process or group their first argument names,
+/// they fail with `EPERM` for groups and broadcasts. Pids only name
+/// processes of the run in its PID namespace.
+static SIGNALLING_SYSCALLS: &[libc::c_long] = &[libc::SYS_kill, libc::SYS_tkill, libc::SYS_tgkill];
+
+/// Like [`super::ForkSandbox`], the child is additionally moved into fresh
+/// mount, network, IPC, UTS and PID namespaces, pivoted into a [`RootFs`]
+/// and confined by a seccomp filter. The program is pid 1 of its namespace,
+/// forked by the child, which waits for it outside and exits the same way,
+/// so that it can't signal the judge or other runs.
+pub struct HardenedSandbox {
+    cgroup: Option<Arc<CgroupController>>,
+    root: RootFs,
+    filter: Vec<libc::sock_filter>,
+    /// the judge's own ids, mapped when a user namespace is needed
+    uid_map: CString,
+    gid_map: CString,
+}
+
+impl HardenedSandbox {
+    /// Programs see the `read_only` paths and the workspace of their run,
+    /// one under `workspace_root`, nothing else of the host.
+    pub fn new(
+        cgroup: Option<Arc<CgroupController>>,
+        read_only: &[String],
+        workspace_root: &str,
+    ) -> Result<HardenedSandbox, Error> {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(HardenedSandbox {
+            cgroup,
+            root: RootFs::new(read_only, workspace_root)?,
+            filter: build_seccomp_filter(DENIED_SYSCALLS, SIGNALLING_SYSCALLS),
+            uid_map: CString::new(format!("{uid} {uid} 1")).unwrap(),
+            gid_map: CString::new(format!("{gid} {gid} 1")).unwrap(),
+        })
+    }
+}
+
+impl Sandbox for HardenedSandbox {
+    fn name(&self) -> &'static str {
+        "hardened"
+    }
+
+    fn run(&self, command: &SandboxCommand) -> Result<SandboxResult, Error> {
+        let program = libc::sock_fprog {
+            len: self.filter.len() as libc::c_ushort,
+            filter: self.filter.as_ptr() as *mut libc::sock_filter,
+        };
+        let workspace = match &command.workspace {
+            Some(workspace) => self.root.bind_workspace(workspace)?,
+            None => vec![],
+        };
+
+        run_forked(command, self.cgroup.as_deref(), || unsafe {
+            let namespaces = libc::CLONE_NEWNS
+                | libc::CLONE_NEWNET
+                | libc::CLONE_NEWIPC
+                | libc::CLONE_NEWUTS
+                | libc::CLONE_NEWPID;
+            // unprivileged judges need a user namespace to create the others,
+            // mounting needs their ids to be mapped in it
+            if libc::unshare(namespaces) != 0 {
+                if libc::unshare(libc::CLONE_NEWUSER | namespaces) != 0 {
+                    abort_child(b"sandbox: unshare failed\n", SETUP_FAILED);
+                }
+                if !write_file(c"/proc/self/setgroups", c"deny")
+                    || !write_file(c"/proc/self/uid_map", &self.uid_map)
+                    || !write_file(c"/proc/self/gid_map", &self.gid_map)
+                {
+                    abort_child(b"sandbox: mapping the user failed\n", SETUP_FAILED);
+                }
+            }
+            if let Err(message) = self.root.enter(&workspace) {
+                abort_child(message, SETUP_FAILED);
+            }
+            // only children join the PID namespace
+            match libc::fork() {
+                0 => {}
+                pid if pid > 0 => exit_like(pid),
+                _ => abort_child(b"sandbox: fork failed\n", SETUP_FAILED),
+            }
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) != 0 {
+                abort_child(b"sandbox: PR_SET_PDEATHSIG failed\n", SETUP_FAILED);
+            }
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                abort_child(b"sandbox: PR_SET_NO_NEW_PRIVS failed\n", SETUP_FAILED);
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            ) != 0
+            {
+                abort_child(b"sandbox: PR_SET_SECCOMP failed\n", SETUP_FAILED);
+            }
+        })
+    }
+}
+
+/// Waits for the program and exits with its status, or dies of its signal,
+/// for the judge to see. Async-signal-safe, for the child.
+unsafe fn exit_like(pid: libc::pid_t) -> ! {
+    let mut status = 0;
+    while libc::waitpid(pid, &mut status, 0) < 0 {
+        if nix::errno::errno() != libc::EINTR {
+            abort_child(b"sandbox: waitpid failed\n", SETUP_FAILED);
+        }
+    }
+    if !libc::WIFSIGNALED(status) {
+        libc::_exit(libc::WEXITSTATUS(status));
+    }
+
+    let signal = libc::WTERMSIG(status);
+    let mut set: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut set);
+    libc::sigaddset(&mut set, signal);
+    libc::signal(signal, libc::SIG_DFL);
+    libc::sigprocmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+    libc::kill(libc::getpid(), signal);
+    // not a terminating signal after all
+    libc::_exit(128 + signal)
+}
+
+/// Async-signal-safe, for the child.
+unsafe fn write_file(path: &CStr, contents: &CStr) -> bool {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return false;
+    }
+    let bytes = contents.to_bytes();
+    let written = libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len());
+    libc::close(fd);
+    written == bytes.len() as isize
+}
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Builds a classic BPF program returning `EPERM` for `denied` and x32
+/// syscalls and for `signalling` ones with a negative first argument,
+/// processes of a foreign architecture are killed.
+fn build_seccomp_filter(
+    denied: &[libc::c_long],
+    signalling: &[libc::c_long],
+) -> Vec<libc::sock_filter> {
+    // offsets in `struct seccomp_data`, the pid is the lower half of the
+    // first argument on little-endian architectures
+    let nr_offset = 0;
+    let arch_offset = 4;
+    let arg0_offset = 16;
+
+    let ld_abs = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let jeq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let jset = libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+
+    let mut filter = vec![
+        bpf_stmt(ld_abs, arch_offset),
+        bpf_jump(jeq, AUDIT_ARCH, 1, 0),
+        bpf_stmt(ret, SECCOMP_RET_KILL_PROCESS),
+        bpf_stmt(ld_abs, nr_offset),
+        bpf_jump(jset, X32_SYSCALL_BIT, 0, 1),
+        bpf_stmt(ret, SECCOMP_RET_ERRNO | libc::EPERM as u32),
+    ];
+    for syscall in signalling {
+        filter.push(bpf_jump(jeq, *syscall as u32, 0, 4));
+        filter.push(bpf_stmt(ld_abs, arg0_offset));
+        filter.push(bpf_jump(jset, 0x8000_0000, 0, 1));
+        filter.push(bpf_stmt(ret, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter.push(bpf_stmt(ret, SECCOMP_RET_ALLOW));
+    }
+    for syscall in denied {
+        filter.push(bpf_jump(jeq, *syscall as u32, 0, 1));
+        filter.push(bpf_stmt(ret, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+    }
+    filter.push(bpf_stmt(ret, SECCOMP_RET_ALLOW));
+
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::judge::sandbox::{
+        spawn::SETUP_FAILED, ExitStatus, Sandbox, SandboxCommand, SandboxConfig,
+    };
+
+    use super::HardenedSandbox;
+
+    /// `None` when namespaces aren't permitted in this environment.
+    fn sandbox(name: &str) -> Option<(HardenedSandbox, PathBuf)> {
+        let root = std::env::temp_dir().join(format!("coj-hardened-{name}-{}", std::process::id()));
+        let read_only = SandboxConfig::default().read_only;
+        let sandbox = HardenedSandbox::new(None, &read_only, root.to_str().unwrap()).unwrap();
+
+        let result = sandbox
+            .run(&SandboxCommand::new(vec!["true".to_string()]))
+            .unwrap();
+        if result.status == ExitStatus::Exited(SETUP_FAILED) {
+            fs::remove_dir_all(root).unwrap();
+            return None;
+        }
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        Some((sandbox, root))
+    }
+
+    fn sh(script: &str) -> SandboxCommand {
+        SandboxCommand::new(vec!["sh".to_string(), "-c".to_string(), script.to_string()])
+    }
+
+    fn python(script: &str) -> SandboxCommand {
+        SandboxCommand::new(vec![
+            "/usr/bin/python3".to_string(),
+            "-c".to_string(),
+            script.to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_hardened_denies_network() {
+        let Some((sandbox, root)) = sandbox("network") else {
+            return;
+        };
+
+        let result = sandbox
+            .run(&python("import socket; socket.socket()"))
+            .unwrap();
+        assert!(!result.status.success());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_hardened_hides_the_judge() {
+        let Some((sandbox, root)) = sandbox("root") else {
+            return;
+        };
+        let workspace = root.join("sub-1");
+        let other = root.join("sub-2");
+        fs::create_dir_all(&workspace).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join("main.cpp"), "int main() {}").unwrap();
+        let judge = std::env::current_dir().unwrap();
+        assert!(judge.join("config.toml").exists());
+
+        let hidden = format!("test ! -e {}/config.toml", judge.display());
+        let result = sandbox.run(&sh(&hidden)).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+
+        let written = format!("echo judged > {}/out", workspace.display());
+        let command = sh(&written).workspace(workspace.to_str().unwrap());
+        let result = sandbox.run(&command).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        assert_eq!(
+            fs::read_to_string(workspace.join("out")).unwrap(),
+            "judged\n"
+        );
+        // nor are the workspaces of other runs
+        let hidden = format!("test ! -e {}/main.cpp", other.display());
+        let command = sh(&hidden).workspace(workspace.to_str().unwrap());
+        let result = sandbox.run(&command).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        assert!(sandbox
+            .run(&sh("true").workspace(root.to_str().unwrap()))
+            .is_err());
+
+        let result = sandbox.run(&sh("touch /usr/out || touch /out")).unwrap();
+        assert!(!result.status.success());
+        let result = sandbox.run(&sh("echo scratch > /tmp/out")).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_hardened_cant_signal_the_judge() {
+        let Some((sandbox, root)) = sandbox("signal") else {
+            return;
+        };
+
+        let result = sandbox.run(&sh("test $$ -eq 1")).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        // the judge isn't in its PID namespace, other groups are denied
+        let script = format!(
+            "import errno, os, sys\n\
+             def kill(pid):\n\
+             \x20   try:\n\
+             \x20       os.kill(pid, 0)\n\
+             \x20   except OSError as e:\n\
+             \x20       return e.errno\n\
+             sys.exit(0 if (kill({}), kill(-1), kill(0)) == (errno.ESRCH, errno.EPERM, None) else 1)",
+            std::process::id()
+        );
+        let result = sandbox.run(&python(&script)).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(0));
+        // how it ended is passed on through the process waiting for it
+        let result = sandbox.run(&sh("exit 3")).unwrap();
+        assert_eq!(result.status, ExitStatus::Exited(3));
+        let result = sandbox.run(&python("import os; os.abort()")).unwrap();
+        assert!(matches!(result.status, ExitStatus::Signaled(_)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_hardened_denies_x32_and_io_uring() {
+        let Some((sandbox, root)) = sandbox("seccomp") else {
+            return;
+        };
+
+        // getpid of x32 and io_uring_setup, both fail with EPERM
+        for syscall in ["0x40000000 | 39", "425, 1, None"] {
+            let script = format!(
+                "import ctypes, sys\n\
+                 libc = ctypes.CDLL(None, use_errno=True)\n\
+                 failed = libc.syscall({syscall}) == -1\n\
+                 sys.exit(0 if failed and ctypes.get_errno() == 1 else 1)"
+            );
+            let result = sandbox.run(&python(&script)).unwrap();
+            assert_eq!(result.status, ExitStatus::Exited(0), "{syscall}");
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
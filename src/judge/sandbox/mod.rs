@@ -0,0 +1,240 @@
+pub mod fake;
+pub mod fork;
+pub mod hardened;
+pub mod rootfs;
+pub mod spawn;
+pub mod wait;
+
+use std::sync::Arc;
+
+use toml::{Table, Value};
+
+use super::{
+    cancel::CancellationToken,
+    cgroup::CgroupController,
+    consts::{DEFAULT_CGROUP_ROOT, DEFAULT_READ_ONLY_PATHS},
+    resource::{ResourceLimits, ResourceUsage},
+};
+
+pub use fake::FakeSandbox;
+pub use fork::ForkSandbox;
+pub use hardened::HardenedSandbox;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    CommandEmptyError,
+    ForkFailed,
+    FileSystemError(String),
+}
+
+/// A command to be run in a sandbox, redirections are file paths and
+/// `None` means `/dev/null`. The wall-clock limit is in milliseconds and
+/// defaults to [`ResourceLimits::wall_time_limit`] when limits are given.
+/// The hardened sandbox shows the program its `workspace` only, none of the
+/// other runs'.
+#[derive(Debug, Default, Clone)]
+pub struct SandboxCommand {
+    pub command: Vec<String>,
+    pub stdin: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub limits: Option<ResourceLimits>,
+    pub wall_time_limit: Option<u64>,
+    pub cancel: Option<CancellationToken>,
+    pub workspace: Option<String>,
+}
+
+impl SandboxCommand {
+    pub fn new(command: Vec<String>) -> SandboxCommand {
+        SandboxCommand {
+            command,
+            ..Default::default()
+        }
+    }
+
+    pub fn stdin(mut self, path: &str) -> SandboxCommand {
+        self.stdin = Some(path.to_string());
+        self
+    }
+
+    pub fn stdout(mut self, path: &str) -> SandboxCommand {
+        self.stdout = Some(path.to_string());
+        self
+    }
+
+    pub fn stderr(mut self, path: &str) -> SandboxCommand {
+        self.stderr = Some(path.to_string());
+        self
+    }
+
+    pub fn limits(mut self, limits: ResourceLimits) -> SandboxCommand {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn wall_time_limit(mut self, millis: u64) -> SandboxCommand {
+        self.wall_time_limit = Some(millis);
+        self
+    }
+
+    pub fn cancel(mut self, token: CancellationToken) -> SandboxCommand {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn workspace(mut self, path: &str) -> SandboxCommand {
+        self.workspace = Some(path.to_string());
+        self
+    }
+
+    pub fn get_wall_time_limit(&self) -> Option<u64> {
+        self.wall_time_limit
+            .or_else(|| self.limits.map(|limits| limits.wall_time_limit()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(i32),
+}
+
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        *self == ExitStatus::Exited(0)
+    }
+}
+
+/// Why the sandbox killed a program before it exited on its own.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KillReason {
+    WallTimeLimit,
+    Cancelled,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SandboxResult {
+    pub status: ExitStatus,
+    pub usage: ResourceUsage,
+    pub killed: Option<KillReason>,
+}
+
+pub trait Sandbox: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Runs the command to completion or until it's killed for exceeding
+    /// its wall-clock limit or being cancelled, the redirections are created
+    /// or truncated before the program starts.
+    fn run(&self, command: &SandboxCommand) -> Result<SandboxResult, Error>;
+}
+
+/// The sandbox backends, `[sandbox] backend` in the config.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Backend {
+    Fork,
+    /// namespaces and seccomp
+    Hardened,
+    /// runs nothing, for tests
+    Fake,
+}
+
+/// `[sandbox]` and `[cgroup]` in the config.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SandboxConfig {
+    pub backend: Backend,
+    /// where the cgroups of the runs go, `None` for rlimits only
+    pub cgroup_root: Option<String>,
+    /// host paths the hardened sandbox binds read-only
+    pub read_only: Vec<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> SandboxConfig {
+        SandboxConfig::from_config(&Table::new())
+    }
+}
+
+impl SandboxConfig {
+    pub fn from_config(data: &Table) -> SandboxConfig {
+        let sandbox = match data.get("sandbox") {
+            Some(Value::Table(sandbox)) => sandbox.clone(),
+            None => Table::new(),
+            _ => panic!("config: [sandbox] should be set correctly"),
+        };
+        let backend = match sandbox.get("backend") {
+            Some(Value::String(backend)) => backend.as_str(),
+            None => "fork",
+            _ => panic!("config: [sandbox] should be set correctly"),
+        };
+        let backend = match backend {
+            "fork" => Backend::Fork,
+            "hardened" => Backend::Hardened,
+            "fake" => Backend::Fake,
+            _ => panic!("config: unknown sandbox backend `{backend}`"),
+        };
+        let read_only = match sandbox.get("read_only") {
+            Some(Value::Array(paths)) => paths
+                .iter()
+                .map(|path| match path {
+                    Value::String(path) if path.starts_with('/') => path.clone(),
+                    _ => panic!("config: [sandbox] should be set correctly"),
+                })
+                .collect(),
+            None => DEFAULT_READ_ONLY_PATHS
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            _ => panic!("config: [sandbox] should be set correctly"),
+        };
+
+        let cgroup = match data.get("cgroup") {
+            Some(Value::Table(cgroup)) => cgroup.clone(),
+            None => Table::new(),
+            _ => panic!("config: [cgroup] should be set correctly"),
+        };
+        let enabled = match cgroup.get("enabled") {
+            Some(Value::Boolean(enabled)) => *enabled,
+            None => false,
+            _ => panic!("config: [cgroup] should be set correctly"),
+        };
+        let root = match cgroup.get("root") {
+            Some(Value::String(root)) => root.as_str(),
+            None => DEFAULT_CGROUP_ROOT,
+            _ => panic!("config: [cgroup] should be set correctly"),
+        };
+
+        SandboxConfig {
+            backend,
+            cgroup_root: enabled.then(|| root.to_string()),
+            read_only,
+        }
+    }
+}
+
+/// Builds the sandbox backend selected by `[sandbox]`, the hardened one
+/// lets programs write under `workspace_root` only. Panics when the hardened
+/// sandbox can't be prepared.
+pub fn from_config(config: &SandboxConfig, workspace_root: &str) -> Arc<dyn Sandbox> {
+    match config.backend {
+        Backend::Fork => Arc::new(ForkSandbox::new(cgroup_from_config(config))),
+        Backend::Hardened => {
+            let cgroup = cgroup_from_config(config);
+            match HardenedSandbox::new(cgroup, &config.read_only, workspace_root) {
+                Ok(sandbox) => Arc::new(sandbox),
+                Err(e) => panic!("sandbox: can't prepare the hardened sandbox: {e:?}"),
+            }
+        }
+        Backend::Fake => Arc::new(FakeSandbox::default()),
+    }
+}
+
+fn cgroup_from_config(config: &SandboxConfig) -> Option<Arc<CgroupController>> {
+    let root = config.cgroup_root.as_ref()?;
+    match CgroupController::detect(root) {
+        Ok(controller) => Some(Arc::new(controller)),
+        Err(e) => {
+            tracing::warn!("cgroup: unavailable, falling back to rlimits: {e:?}");
+            None
+        }
+    }
+}
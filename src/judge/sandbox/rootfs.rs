@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    ffi::{CStr, CString},
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use nix::{
+    libc,
+    sys::statvfs::{statvfs, FsFlags},
+};
+
+use super::Error;
+
+/// Devices programs expect, bound from the host when it has them.
+static DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+
+pub(super) enum Step {
+    Mkdir(CString),
+    /// an empty file for a device to be bound onto
+    Touch(CString),
+    Symlink {
+        target: CString,
+        link: CString,
+    },
+    Bind {
+        source: CString,
+        target: CString,
+        /// with the mounts under `source`
+        recursive: bool,
+        /// of the remount after binding, with the flags the host locked
+        flags: libc::c_ulong,
+    },
+    Tmpfs(CString),
+}
+
+/// The file system a hardened child sees after `pivot_root`: a read-only
+/// tmpfs holding the `read_only` paths, a few devices, an empty `/tmp` and
+/// the workspace of the run. The testcases, the database, the config of the
+/// judge and the other workspaces aren't in it.
+pub(super) struct RootFs {
+    workspace_root: PathBuf,
+    /// where the tmpfs is mounted before it becomes `/`
+    staging: CString,
+    steps: Vec<Step>,
+    /// created by `steps`, of the new root
+    dirs: BTreeSet<PathBuf>,
+    /// the working directory of the judge when the workspaces are under it,
+    /// so that relative workspace paths keep working, `/` otherwise
+    cwd: CString,
+}
+
+impl RootFs {
+    /// `read_only` paths missing on the host are left out, the workspace
+    /// root is created if needed.
+    pub fn new(read_only: &[String], workspace_root: &str) -> Result<RootFs, Error> {
+        let fs_err = |path: &Path, e: std::io::Error| {
+            Error::FileSystemError(format!("{}: {e}", path.display()))
+        };
+        let workspace_root = Path::new(workspace_root);
+        fs::create_dir_all(workspace_root).map_err(|e| fs_err(workspace_root, e))?;
+        let workspace_root = workspace_root
+            .canonicalize()
+            .map_err(|e| fs_err(workspace_root, e))?;
+        let staging = workspace_root.join(".root");
+        fs::create_dir_all(&staging).map_err(|e| fs_err(&staging, e))?;
+
+        let mut plan = Plan {
+            staging: staging.clone(),
+            steps: Vec::new(),
+            dirs: BTreeSet::new(),
+        };
+        for path in read_only {
+            plan.read_only(Path::new(path))?;
+        }
+        for device in DEVICES {
+            plan.device(Path::new(device))?;
+        }
+        plan.mkdirs(Path::new("/tmp"))?;
+        plan.steps
+            .push(Step::Tmpfs(plan.staged(Path::new("/tmp"))?));
+        // empty, the workspace of a run is bound into it
+        plan.mkdirs(&workspace_root)?;
+
+        let cwd = env::current_dir()
+            .and_then(|cwd| cwd.canonicalize())
+            .ok()
+            .filter(|cwd| workspace_root.starts_with(cwd))
+            .unwrap_or_else(|| PathBuf::from("/"));
+
+        Ok(RootFs {
+            workspace_root,
+            staging: c_path(&staging)?,
+            steps: plan.steps,
+            dirs: plan.dirs,
+            cwd: c_path(&cwd)?,
+        })
+    }
+
+    /// The steps binding `workspace`, a directory under the workspace root,
+    /// writable into the root, for [`RootFs::enter`].
+    pub fn bind_workspace(&self, workspace: &str) -> Result<Vec<Step>, Error> {
+        let path = Path::new(workspace);
+        let workspace = path
+            .canonicalize()
+            .map_err(|e| Error::FileSystemError(format!("{}: {e}", path.display())))?;
+        if workspace == self.workspace_root || !workspace.starts_with(&self.workspace_root) {
+            return Err(Error::FileSystemError(format!(
+                "{}: not a workspace under {}",
+                workspace.display(),
+                self.workspace_root.display()
+            )));
+        }
+
+        let mut plan = Plan {
+            staging: self.workspace_root.join(".root"),
+            steps: Vec::new(),
+            dirs: self.dirs.clone(),
+        };
+        plan.mkdirs(&workspace)?;
+        plan.steps.push(Step::Bind {
+            source: c_path(&workspace)?,
+            target: plan.staged(&workspace)?,
+            recursive: false,
+            flags: libc::MS_NOSUID | libc::MS_NODEV | locked_flags(&workspace),
+        });
+        Ok(plan.steps)
+    }
+
+    /// Builds the root with the `workspace` steps and pivots into it, in a
+    /// child that already has a mount namespace of its own. Only
+    /// async-signal-safe calls, the error is the message to abort the child
+    /// with.
+    pub unsafe fn enter(&self, workspace: &[Step]) -> Result<(), &'static [u8]> {
+        let null = ptr::null::<libc::c_char>();
+        if libc::mount(
+            null,
+            c"/".as_ptr(),
+            null,
+            libc::MS_REC | libc::MS_PRIVATE,
+            ptr::null(),
+        ) != 0
+        {
+            return Err(b"sandbox: making the mounts private failed\n");
+        }
+        if !mount_tmpfs(&self.staging, c"mode=0755") {
+            return Err(b"sandbox: mounting the root failed\n");
+        }
+
+        for step in self.steps.iter().chain(workspace) {
+            let done = match step {
+                Step::Mkdir(path) => {
+                    libc::mkdir(path.as_ptr(), 0o755) == 0 || nix::errno::errno() == libc::EEXIST
+                }
+                Step::Touch(path) => {
+                    let fd = libc::open(
+                        path.as_ptr(),
+                        libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                        0o644,
+                    );
+                    fd >= 0 && libc::close(fd) == 0
+                }
+                Step::Symlink { target, link } => {
+                    libc::symlink(target.as_ptr(), link.as_ptr()) == 0
+                }
+                Step::Bind {
+                    source,
+                    target,
+                    recursive,
+                    flags,
+                } => {
+                    let bind = libc::MS_BIND | if *recursive { libc::MS_REC } else { 0 };
+                    let remount = libc::MS_REMOUNT | libc::MS_BIND | flags;
+                    libc::mount(source.as_ptr(), target.as_ptr(), null, bind, ptr::null()) == 0
+                        && libc::mount(null, target.as_ptr(), null, remount, ptr::null()) == 0
+                }
+                Step::Tmpfs(path) => mount_tmpfs(path, c"mode=1777"),
+            };
+            if !done {
+                return Err(b"sandbox: building the root failed\n");
+            }
+        }
+
+        // the old root ends up stacked under the new one and is detached
+        if libc::chdir(self.staging.as_ptr()) != 0
+            || libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) != 0
+            || libc::umount2(c".".as_ptr(), libc::MNT_DETACH) != 0
+        {
+            return Err(b"sandbox: pivot_root failed\n");
+        }
+        let read_only =
+            libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+        if libc::mount(null, c"/".as_ptr(), null, read_only, ptr::null()) != 0 {
+            return Err(b"sandbox: making the root read-only failed\n");
+        }
+        if libc::chdir(self.cwd.as_ptr()) != 0 && libc::chdir(c"/".as_ptr()) != 0 {
+            return Err(b"sandbox: chdir failed\n");
+        }
+
+        Ok(())
+    }
+}
+
+/// The steps under construction, paths are of the new root.
+struct Plan {
+    staging: PathBuf,
+    steps: Vec<Step>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl Plan {
+    fn staged(&self, path: &Path) -> Result<CString, Error> {
+        c_path(&self.staging.join(path.strip_prefix("/").unwrap_or(path)))
+    }
+
+    /// `path` and its ancestors.
+    fn mkdirs(&mut self, path: &Path) -> Result<(), Error> {
+        let mut ancestors: Vec<&Path> = path.ancestors().collect();
+        ancestors.reverse();
+        for dir in ancestors.into_iter().filter(|dir| dir.parent().is_some()) {
+            if self.dirs.insert(dir.to_path_buf()) {
+                self.steps.push(Step::Mkdir(self.staged(dir)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn mkdirs_parent(&mut self, path: &Path) -> Result<(), Error> {
+        match path.parent() {
+            Some(parent) => self.mkdirs(parent),
+            None => Ok(()),
+        }
+    }
+
+    fn read_only(&mut self, path: &Path) -> Result<(), Error> {
+        if !path.is_absolute() {
+            return Err(Error::FileSystemError(format!(
+                "{}: read-only paths should be absolute",
+                path.display()
+            )));
+        }
+        let Ok(metadata) = path.symlink_metadata() else {
+            return Ok(());
+        };
+
+        if metadata.file_type().is_symlink() {
+            // e.g. `/bin -> usr/bin` on merged-/usr systems
+            let target = fs::read_link(path)
+                .map_err(|e| Error::FileSystemError(format!("{}: {e}", path.display())))?;
+            self.mkdirs_parent(path)?;
+            self.steps.push(Step::Symlink {
+                target: c_path(&target)?,
+                link: self.staged(path)?,
+            });
+            return Ok(());
+        }
+        if metadata.is_dir() {
+            self.mkdirs(path)?;
+        } else {
+            self.mkdirs_parent(path)?;
+            self.steps.push(Step::Touch(self.staged(path)?));
+        }
+        self.steps.push(Step::Bind {
+            source: c_path(path)?,
+            target: self.staged(path)?,
+            recursive: true,
+            flags: libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | locked_flags(path),
+        });
+        Ok(())
+    }
+
+    fn device(&mut self, path: &Path) -> Result<(), Error> {
+        if !path.exists() {
+            return Ok(());
+        }
+        self.mkdirs_parent(path)?;
+        self.steps.push(Step::Touch(self.staged(path)?));
+        self.steps.push(Step::Bind {
+            source: c_path(path)?,
+            target: self.staged(path)?,
+            recursive: false,
+            flags: libc::MS_NOSUID | locked_flags(path),
+        });
+        Ok(())
+    }
+}
+
+fn c_path(path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::FileSystemError(format!("{}: nul byte in path", path.display())))
+}
+
+/// Flags of the mount holding `path` that a remount in a user namespace
+/// mustn't clear.
+fn locked_flags(path: &Path) -> libc::c_ulong {
+    let Ok(stat) = statvfs(path) else {
+        return 0;
+    };
+    let flags = stat.flags();
+    [
+        (FsFlags::ST_RDONLY, libc::MS_RDONLY),
+        (FsFlags::ST_NOSUID, libc::MS_NOSUID),
+        (FsFlags::ST_NODEV, libc::MS_NODEV),
+        (FsFlags::ST_NOEXEC, libc::MS_NOEXEC),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .fold(0, |acc, (_, ms)| acc | ms)
+}
+
+unsafe fn mount_tmpfs(target: &CStr, options: &CStr) -> bool {
+    libc::mount(
+        c"tmpfs".as_ptr(),
+        target.as_ptr(),
+        c"tmpfs".as_ptr(),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        options.as_ptr() as *const libc::c_void,
+    ) == 0
+}
@@ -0,0 +1,284 @@
+use std::{
+    env,
+    ffi::CString,
+    fs::{File, OpenOptions},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, fs::PermissionsExt},
+    },
+    path::PathBuf,
+    ptr,
+};
+
+use nix::{libc, unistd::Pid};
+
+use crate::judge::{cgroup::Cgroup, resource::ResourceLimits};
+
+use super::{Error, SandboxCommand};
+
+/// Exit code of a child whose sandbox couldn't be set up.
+pub const SETUP_FAILED: i32 = 126;
+/// Exit code of a child whose program couldn't be executed.
+pub const EXEC_FAILED: i32 = 127;
+
+/// `CLONE_PIDFD`, Linux 5.2.
+const CLONE_PIDFD: u64 = 0x1000;
+/// `CLONE_INTO_CGROUP`, Linux 5.7, not exported by `libc` yet.
+const CLONE_INTO_CGROUP: u64 = 0x2_0000_0000;
+
+/// `struct clone_args` of `clone3(2)`, `CLONE_ARGS_SIZE_VER2`.
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
+}
+
+/// Everything the child needs, prepared before the process is split so
+/// that the child only makes async-signal-safe calls.
+pub(super) struct SpawnPlan {
+    program: CString,
+    _argv: Vec<CString>,
+    argv_ptrs: Vec<*const libc::c_char>,
+    _envp: Vec<CString>,
+    envp_ptrs: Vec<*const libc::c_char>,
+    stdin: File,
+    stdout: File,
+    stderr: Option<File>,
+    limits: Option<ResourceLimits>,
+}
+
+pub(super) struct SpawnedChild {
+    pub pid: Pid,
+    /// whether the child was born into the cgroup
+    pub attached: bool,
+    /// `None` on kernels older than 5.3, the child is polled then
+    pub pidfd: Option<OwnedFd>,
+}
+
+impl SpawnPlan {
+    pub fn new(command: &SandboxCommand) -> Result<SpawnPlan, Error> {
+        if command.command.is_empty() {
+            return Err(Error::CommandEmptyError);
+        }
+
+        let to_c_string = |bytes: &[u8]| {
+            CString::new(bytes).map_err(|_| Error::FileSystemError("nul byte in command".into()))
+        };
+        let program = to_c_string(resolve_program(&command.command[0]).as_os_str().as_bytes())?;
+        let argv = command
+            .command
+            .iter()
+            .map(|arg| to_c_string(arg.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let envp = env::vars_os()
+            .map(|(k, v)| to_c_string(&[k.as_bytes(), b"=", v.as_bytes()].concat()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let null_terminated = |strings: &Vec<CString>| {
+            let mut ptrs: Vec<*const libc::c_char> = strings.iter().map(|s| s.as_ptr()).collect();
+            ptrs.push(ptr::null());
+            ptrs
+        };
+
+        let open_err =
+            |path: &str, e: std::io::Error| Error::FileSystemError(format!("{path}: {e}"));
+        let dev_null = "/dev/null".to_string();
+        let stdin_path = command.stdin.as_ref().unwrap_or(&dev_null);
+        let stdin = File::open(stdin_path).map_err(|e| open_err(stdin_path, e))?;
+        let create = |path: &String| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .map_err(|e| open_err(path, e))
+        };
+        let stdout = create(command.stdout.as_ref().unwrap_or(&dev_null))?;
+        // the same file for both streams shares one description, like `2>&1`
+        let stderr = match &command.stderr {
+            Some(path) if command.stdout.as_ref() == Some(path) => None,
+            path => Some(create(path.as_ref().unwrap_or(&dev_null))?),
+        };
+
+        Ok(SpawnPlan {
+            program,
+            argv_ptrs: null_terminated(&argv),
+            _argv: argv,
+            envp_ptrs: null_terminated(&envp),
+            _envp: envp,
+            stdin,
+            stdout,
+            stderr,
+            limits: command.limits,
+        })
+    }
+
+    /// Starts the child, `pre_exec` runs in the child right before `execve`
+    /// and must be async-signal-safe as well.
+    pub fn spawn<F>(&self, cgroup: Option<&Cgroup>, pre_exec: F) -> Result<SpawnedChild, Error>
+    where
+        F: Fn(),
+    {
+        let cgroup_dir = cgroup.and_then(|cgroup| File::open(cgroup.get_path()).ok());
+
+        if let Some(cgroup_dir) = &cgroup_dir {
+            match self.clone3(Some(cgroup_dir), &pre_exec) {
+                Ok(child) => return Ok(child),
+                Err(libc::ENOSYS) => return self.fork(&pre_exec),
+                // e.g. a kernel older than 5.7, retry without the cgroup
+                Err(_) => {}
+            }
+        }
+        match self.clone3(None, &pre_exec) {
+            Ok(child) => Ok(child),
+            Err(libc::ENOSYS) => self.fork(&pre_exec),
+            Err(_) => Err(Error::ForkFailed),
+        }
+    }
+
+    fn clone3<F>(&self, cgroup_dir: Option<&File>, pre_exec: &F) -> Result<SpawnedChild, i32>
+    where
+        F: Fn(),
+    {
+        let mut pidfd: libc::c_int = -1;
+        let mut args = CloneArgs {
+            flags: CLONE_PIDFD,
+            pidfd: &mut pidfd as *mut libc::c_int as u64,
+            exit_signal: libc::SIGCHLD as u64,
+            ..Default::default()
+        };
+        if let Some(cgroup_dir) = cgroup_dir {
+            args.flags |= CLONE_INTO_CGROUP;
+            args.cgroup = cgroup_dir.as_raw_fd() as u64;
+        }
+
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_clone3,
+                &mut args as *mut CloneArgs,
+                std::mem::size_of::<CloneArgs>(),
+            )
+        };
+        match ret {
+            0 => unsafe { self.exec_child(cgroup_dir.is_some(), pre_exec) },
+            pid if pid > 0 => Ok(SpawnedChild {
+                pid: Pid::from_raw(pid as libc::pid_t),
+                attached: cgroup_dir.is_some(),
+                pidfd: Some(unsafe { OwnedFd::from_raw_fd(pidfd) }),
+            }),
+            _ => Err(nix::errno::errno()),
+        }
+    }
+
+    fn fork<F>(&self, pre_exec: &F) -> Result<SpawnedChild, Error>
+    where
+        F: Fn(),
+    {
+        match unsafe { libc::fork() } {
+            0 => unsafe { self.exec_child(false, pre_exec) },
+            pid if pid > 0 => Ok(SpawnedChild {
+                pid: Pid::from_raw(pid),
+                attached: false,
+                pidfd: pidfd_open(pid),
+            }),
+            _ => Err(Error::ForkFailed),
+        }
+    }
+
+    /// Runs in the child: no allocation, no locks, only async-signal-safe calls.
+    unsafe fn exec_child<F>(&self, attached: bool, pre_exec: &F) -> !
+    where
+        F: Fn(),
+    {
+        let stdout = self.stdout.as_raw_fd();
+        let stderr = self.stderr.as_ref().map_or(stdout, |f| f.as_raw_fd());
+        if libc::dup2(self.stdin.as_raw_fd(), libc::STDIN_FILENO) < 0
+            || libc::dup2(stdout, libc::STDOUT_FILENO) < 0
+            || libc::dup2(stderr, libc::STDERR_FILENO) < 0
+        {
+            abort_child(b"sandbox: dup2 failed\n", SETUP_FAILED);
+        }
+        // a group of its own, so that whatever it forks can be killed with it
+        libc::setpgid(0, 0);
+
+        if let Some(limits) = &self.limits {
+            // memory is accounted by the cgroup if attached, RLIMIT_AS otherwise
+            limits.apply_rlimits(!attached);
+        }
+
+        pre_exec();
+
+        libc::execve(
+            self.program.as_ptr(),
+            self.argv_ptrs.as_ptr(),
+            self.envp_ptrs.as_ptr(),
+        );
+        abort_child(b"sandbox: execve failed\n", EXEC_FAILED);
+    }
+}
+
+fn pidfd_open(pid: libc::pid_t) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        None
+    } else {
+        Some(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+    }
+}
+
+/// Writes `message` to stderr and exits, usable in the child after the split.
+pub(super) unsafe fn abort_child(message: &[u8], code: i32) -> ! {
+    libc::write(
+        libc::STDERR_FILENO,
+        message.as_ptr() as *const libc::c_void,
+        message.len(),
+    );
+    libc::_exit(code)
+}
+
+/// Looks `program` up in `PATH` like `execvp` would.
+fn resolve_program(program: &str) -> PathBuf {
+    if program.contains('/') {
+        return PathBuf::from(program);
+    }
+    let Some(paths) = env::var_os("PATH") else {
+        return PathBuf::from(program);
+    };
+    for dir in env::split_paths(&paths) {
+        let candidate = dir.join(program);
+        let executable = candidate
+            .metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false);
+        if executable {
+            return candidate;
+        }
+    }
+    PathBuf::from(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::resolve_program;
+
+    #[test]
+    fn test_resolve_program() {
+        assert_eq!(resolve_program("./a.out"), PathBuf::from("./a.out"));
+        assert!(resolve_program("sh").is_absolute());
+        assert_eq!(
+            resolve_program("no-such-program"),
+            PathBuf::from("no-such-program")
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    os::fd::AsRawFd,
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::libc;
+
+use crate::judge::cancel::CancellationToken;
+
+use super::{spawn::SpawnedChild, KillReason};
+
+/// How often a waiting worker looks at its cancellation token.
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What `wait4` reported for a reaped child.
+pub(super) struct Reaped {
+    pub status: libc::c_int,
+    pub rusage: libc::rusage,
+    pub killed: Option<KillReason>,
+}
+
+/// Waits for the child to exit, killing it (and its process group) once the
+/// wall-clock `deadline` has passed or `cancel` is triggered. The worker is
+/// never blocked for longer than [`CANCEL_CHECK_INTERVAL`] at a time.
+pub(super) fn wait_child(
+    child: &SpawnedChild,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> Reaped {
+    let mut killed = None;
+    loop {
+        if let Some(reason) = kill_reason(deadline, cancel) {
+            kill_child(child);
+            killed = Some(reason);
+            break;
+        }
+
+        let timeout = match deadline {
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .min(CANCEL_CHECK_INTERVAL),
+            None => CANCEL_CHECK_INTERVAL,
+        };
+        if has_exited(child, timeout) {
+            break;
+        }
+    }
+
+    let mut status: libc::c_int = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        let ret = unsafe { libc::wait4(child.pid.as_raw(), &mut status, 0, &mut rusage) };
+        if ret >= 0 || nix::errno::errno() != libc::EINTR {
+            break;
+        }
+    }
+
+    Reaped {
+        status,
+        rusage,
+        killed,
+    }
+}
+
+fn kill_reason(
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> Option<KillReason> {
+    if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+        Some(KillReason::Cancelled)
+    } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        Some(KillReason::WallTimeLimit)
+    } else {
+        None
+    }
+}
+
+/// Blocks for at most `timeout`, true once the child can be reaped.
+fn has_exited(child: &SpawnedChild, timeout: Duration) -> bool {
+    match &child.pidfd {
+        Some(pidfd) => {
+            let mut poll_fd = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ret = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+            ret > 0
+        }
+        None => {
+            // no pidfd, peek without reaping so that `wait4` still gets the rusage
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let ret = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    child.pid.as_raw() as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+                )
+            };
+            if ret == 0 && unsafe { info.si_pid() } != 0 {
+                return true;
+            }
+            thread::sleep(timeout);
+            false
+        }
+    }
+}
+
+fn kill_child(child: &SpawnedChild) {
+    let pid = child.pid.as_raw();
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+        libc::kill(pid, libc::SIGKILL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::judge::{
+        cancel::CancellationToken,
+        sandbox::{ForkSandbox, KillReason, Sandbox, SandboxCommand},
+    };
+
+    #[test]
+    fn test_wall_time_limit() {
+        let command = SandboxCommand::new(vec!["sleep".into(), "10".into()]).wall_time_limit(200);
+        let start = Instant::now();
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+
+        assert_eq!(result.killed, Some(KillReason::WallTimeLimit));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let command = SandboxCommand::new(vec!["sleep".into(), "10".into()]).cancel(token.clone());
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+        let start = Instant::now();
+        let result = ForkSandbox::new(None).run(&command).unwrap();
+        canceller.join().unwrap();
+
+        assert_eq!(result.killed, Some(KillReason::Cancelled));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,77 +1,307 @@
 use std::{fs, sync::Arc};
 
+use crate::metrics::METRICS;
+use crate::store::Store;
+use crate::webhook::{WebhookPayload, Webhooks};
+
 use super::{
+    cancel::CancellationToken,
     compiler,
-    file::{self, get_pairwise_testcase_files, TestcaseFile},
-    runner::{self, RunnerJob},
-    JudgeStatus,
+    events::{EventBus, JudgeEvent},
+    file,
+    runner::{self, DeferFn, RunnerJob, TestcaseResult},
+    workspace::WorkspaceManager,
+    JudgeStatus, Verdict,
 };
 
+/// Playground runs go by it as well, they aren't submissions.
+const UNASSIGNED_SUBMISSION_ID: u64 = 0;
+
 pub struct Task {
-    #[allow(dead_code)]
+    submission_id: u64,
     problem_id: u64,
     testcases_path: String,
     lang: String,
     source_code: String,
+    cancel: CancellationToken,
+    callback_url: Option<String>,
+    owner: Option<String>,
 }
 
 impl Task {
+    /// The task is given a submission id once it's queued, see
+    /// [`crate::thread_pool::ThreadPool::enqueue_task`].
     pub fn new(problem_id: u64, testcases_path: &str, lang: &str, source_code: &str) -> Task {
+        Task::with_submission_id(
+            UNASSIGNED_SUBMISSION_ID,
+            problem_id,
+            testcases_path,
+            lang,
+            source_code,
+        )
+    }
+
+    /// A task for a submission that already has an id, e.g. one recovered
+    /// from the store.
+    pub fn with_submission_id(
+        submission_id: u64,
+        problem_id: u64,
+        testcases_path: &str,
+        lang: &str,
+        source_code: &str,
+    ) -> Task {
         Task {
+            submission_id,
             problem_id,
             testcases_path: String::from(testcases_path),
             lang: String::from(lang),
             source_code: String::from(source_code),
+            cancel: CancellationToken::new(),
+            callback_url: None,
+            owner: None,
         }
     }
 
+    /// The result is POSTed to `callback_url` once judged, instead of the
+    /// globally configured webhook url.
+    pub fn with_callback_url(mut self, callback_url: &str) -> Task {
+        self.callback_url = Some(callback_url.to_string());
+        self
+    }
+
+    pub fn get_submission_id(&self) -> u64 {
+        self.submission_id
+    }
+
+    pub fn has_submission_id(&self) -> bool {
+        self.submission_id != UNASSIGNED_SUBMISSION_ID
+    }
+
+    pub(crate) fn set_submission_id(&mut self, submission_id: u64) {
+        self.submission_id = submission_id;
+    }
+
+    pub fn get_problem_id(&self) -> u64 {
+        self.problem_id
+    }
+
+    pub fn get_lang(&self) -> &str {
+        &self.lang
+    }
+
+    pub fn get_source_code(&self) -> &str {
+        &self.source_code
+    }
+
+    pub fn get_callback_url(&self) -> Option<&str> {
+        self.callback_url.as_deref()
+    }
+
+    /// Who submitted it, recorded in the store.
+    pub fn with_owner(mut self, owner: &str) -> Task {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn get_owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Cancelling it before the task is executed skips it, afterwards the
+    /// compiler or the running testcases are killed and the rest skipped.
+    pub fn get_cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Compiles the submission and prepares a job per testcase. With a store,
+    /// the submission is marked as judging and its results are written once
+    /// the last job is done, or right away if it doesn't get that far. Its
+    /// progress is published to `events` along the way.
     pub fn execute(
         self,
         compiler: Arc<compiler::Compiler>,
         runner: Arc<runner::Runner>,
+        workspaces: Arc<WorkspaceManager>,
+        store: Option<Arc<Store>>,
+        webhooks: Option<Arc<Webhooks>>,
+        events: Arc<EventBus>,
     ) -> Result<Vec<RunnerJob>, JudgeStatus> {
+        let submission_id = self.submission_id;
+        let finisher = Finisher {
+            submission_id,
+            problem_id: self.problem_id,
+            lang: self.lang.clone(),
+            cancel: self.cancel.clone(),
+            callback_url: self.callback_url.clone(),
+            store: store.clone(),
+            webhooks,
+            events: events.clone(),
+        };
+        if self.cancel.is_cancelled() {
+            finisher.finish(Verdict::Cancelled, "", &[]);
+            return Err(JudgeStatus::Cancelled);
+        }
+        if let Some(store) = &store {
+            if let Err(e) = store.start_judging(submission_id) {
+                tracing::warn!("store: can't update submission {submission_id}: {e:?}");
+            }
+        }
+
+        let finisher = Arc::new(finisher);
+        let defer_finisher = finisher.clone();
+        let defer: DeferFn = Box::new(move |shared_data| {
+            defer_finisher.finish(shared_data.get_verdict(), "", &shared_data.get_results());
+        });
+
+        let ret = self.prepare(compiler, runner, workspaces, store.clone(), events, defer);
+        let failure = match &ret {
+            Ok(jobs) if jobs.is_empty() => Some((Verdict::SystemError, "no testcases".to_string())),
+            Ok(_) => None,
+            Err(JudgeStatus::CompilationError(msg)) => {
+                if let Some(store) = &store {
+                    if let Err(e) = store.set_compile_log(submission_id, msg) {
+                        tracing::warn!("store: can't update submission {submission_id}: {e:?}");
+                    }
+                }
+                Some((Verdict::CompilationError, msg.clone()))
+            }
+            Err(JudgeStatus::Cancelled) => Some((Verdict::Cancelled, String::new())),
+            Err(e) => Some((Verdict::SystemError, format!("{:?}", e))),
+        };
+        if let Some((verdict, info)) = failure {
+            finisher.finish(verdict, &info, &[]);
+        }
+
+        ret
+    }
+
+    fn prepare(
+        self,
+        compiler: Arc<compiler::Compiler>,
+        runner: Arc<runner::Runner>,
+        workspaces: Arc<WorkspaceManager>,
+        store: Option<Arc<Store>>,
+        events: Arc<EventBus>,
+        defer: DeferFn,
+    ) -> Result<Vec<RunnerJob>, JudgeStatus> {
+        // 0. everything of this submission lives in its own workspace, it's
+        // removed once the last job referring to it is gone
+        let workspace = match workspaces.create(self.submission_id) {
+            Ok(w) => Arc::new(w),
+            Err(e) => return Err(JudgeStatus::UnknownError(format!("{:?}", e))),
+        };
+
         // 1. save source code to file
-        let save_ret = match file::save_source_code(&self.source_code, &self.lang) {
+        let save_ret = match file::save_source_code(
+            self.submission_id,
+            &self.source_code,
+            &self.lang,
+            &workspace,
+        ) {
             Ok(s) => s,
-            Err(e) => return Err(JudgeStatus::UnknownError(format!("{:?}", e))),
+            Err(e) => {
+                workspace.mark_failed();
+                return Err(JudgeStatus::UnknownError(format!("{:?}", e)));
+            }
         };
 
         // 2. compile
-        let executable_path = match compiler.compile(&save_ret, &self.lang) {
+        events.publish(self.submission_id, JudgeEvent::Compiling);
+        let executable_path = match compiler.compile(&save_ret, &self.lang, &self.cancel) {
             Ok(s) => s,
+            Err(compiler::Error::Cancelled) => return Err(JudgeStatus::Cancelled),
             Err(e) => {
-                return match e {
-                    compiler::Error::CompilationError(msg) => {
-                        Err(JudgeStatus::CompilationError(msg))
+                workspace.mark_failed();
+                let log = match &e {
+                    compiler::Error::CompilationError(msg) => msg.clone(),
+                    compiler::Error::TimedOut(limit) => {
+                        format!("compilation timed out after {limit} ms")
                     }
-                    compiler::Error::LanguageNotFoundError
-                    | compiler::Error::ForkFailed
-                    | compiler::Error::NoCompilationLogError => {
-                        Err(JudgeStatus::CompilationError(format!("{:?}", e)))
-                    }
-                }
+                    e => format!("{:?}", e),
+                };
+                events.publish(
+                    self.submission_id,
+                    JudgeEvent::Compiled {
+                        success: false,
+                        log: log.clone(),
+                    },
+                );
+                return Err(JudgeStatus::CompilationError(log));
             }
         };
 
-        // 3. run (runner.execute)
-        let lst_read_dir = fs::read_dir(&self.testcases_path);
-        let mut testcase_files: Vec<TestcaseFile> = vec![];
-        if let Ok(lst_read_dir) = lst_read_dir {
-            for dir in lst_read_dir {
-                let path = format!("{}", dir.unwrap().path().display());
-                let sp = path.split_at(&self.testcases_path.len() + 1);
-                testcase_files.push(TestcaseFile::new(sp.1, &path));
+        // warnings are worth keeping as well
+        let log_path = compiler::Compiler::get_log_path(&executable_path);
+        let log = fs::read_to_string(log_path).ok();
+        if let (Some(store), Some(log)) = (&store, &log) {
+            if let Err(e) = store.set_compile_log(self.submission_id, log) {
+                tracing::warn!(
+                    "store: can't update submission {}: {e:?}",
+                    self.submission_id
+                );
             }
         }
-        let testcases = get_pairwise_testcase_files(testcase_files);
+        events.publish(
+            self.submission_id,
+            JudgeEvent::Compiled {
+                success: true,
+                log: log.unwrap_or_default(),
+            },
+        );
+
+        // 3. run (runner.execute)
+        let testcases = file::list_testcases(&self.testcases_path);
 
         runner
-            .execute(executable_path.clone(), &self.lang, &testcases)
-            .map_err(|e| JudgeStatus::UnknownError(format!("{:?}", e)))
+            .execute(
+                executable_path.clone(),
+                &self.lang,
+                &testcases,
+                workspace.clone(),
+                self.cancel.clone(),
+                events,
+                defer,
+            )
+            .map_err(|e| {
+                workspace.mark_failed();
+                JudgeStatus::UnknownError(format!("{:?}", e))
+            })
+    }
+}
 
-        // 4. remove compilation intermediate files (runner.clean)
+/// Hands the final verdict of a submission to everyone waiting for it: the
+/// store, the event subscribers and the webhook receiver.
+struct Finisher {
+    submission_id: u64,
+    problem_id: u64,
+    lang: String,
+    cancel: CancellationToken,
+    callback_url: Option<String>,
+    store: Option<Arc<Store>>,
+    webhooks: Option<Arc<Webhooks>>,
+    events: Arc<EventBus>,
+}
 
-        // TODO defer.1. update database (db.update(id, xxx))
-        // do defer in thread pool
+impl Finisher {
+    fn finish(&self, verdict: Verdict, info: &str, results: &[TestcaseResult]) {
+        let submission_id = self.submission_id;
+        if self.cancel.is_interrupted() {
+            // not a verdict, the pool puts it back to pending
+            self.events.publish(submission_id, JudgeEvent::Requeued);
+            return;
+        }
+        if let Some(store) = &self.store {
+            if let Err(e) = store.finish_submission(submission_id, verdict, info, results) {
+                tracing::warn!("store: can't finish submission {submission_id}: {e:?}");
+            }
+        }
+        METRICS.record_submission(&self.lang, verdict);
+        self.events
+            .publish(submission_id, JudgeEvent::Finished { verdict });
+        if let Some(webhooks) = &self.webhooks {
+            let payload =
+                WebhookPayload::new(submission_id, self.problem_id, verdict, info, results);
+            webhooks.notify(self.callback_url.as_deref(), &payload);
+        }
     }
 }
@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use nix::sys::statfs::{statfs, TMPFS_MAGIC};
+use toml::{Table, Value};
+
+use super::consts::{DEFAULT_TMPFS_WORKSPACE_ROOT, DEFAULT_WORKSPACE_ROOT};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    FileSystemError(String),
+}
+
+/// Hands out a fresh directory under `root` to every submission.
+#[derive(Debug)]
+pub struct WorkspaceManager {
+    root: PathBuf,
+    keep_failed: bool,
+    seq: AtomicU64,
+    run_seq: AtomicU64,
+}
+
+/// `[workspace]` in the config.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WorkspaceConfig {
+    pub root: String,
+    /// whether `root` is meant to be on a tmpfs, only warned about
+    pub tmpfs: bool,
+    /// non-accepted submissions keep their workspace
+    pub keep_failed: bool,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> WorkspaceConfig {
+        WorkspaceConfig::from_config(&Table::new())
+    }
+}
+
+impl WorkspaceConfig {
+    pub fn from_config(data: &Table) -> WorkspaceConfig {
+        let workspace = match data.get("workspace") {
+            Some(Value::Table(workspace)) => workspace.clone(),
+            None => Table::new(),
+            _ => panic!("config: [workspace] should be set correctly"),
+        };
+        let get_bool = |key: &str| match workspace.get(key) {
+            Some(Value::Boolean(val)) => *val,
+            None => false,
+            _ => panic!("config: [workspace] should be set correctly"),
+        };
+        let tmpfs = get_bool("tmpfs");
+        let keep_failed = get_bool("keep_failed");
+        let root = match workspace.get("root") {
+            Some(Value::String(root)) => root.as_str(),
+            None if tmpfs => DEFAULT_TMPFS_WORKSPACE_ROOT,
+            None => DEFAULT_WORKSPACE_ROOT,
+            _ => panic!("config: [workspace] should be set correctly"),
+        };
+
+        WorkspaceConfig {
+            root: root.to_string(),
+            tmpfs,
+            keep_failed,
+        }
+    }
+}
+
+impl WorkspaceManager {
+    /// Panics when `root` can't be created.
+    pub fn from_config(config: &WorkspaceConfig) -> WorkspaceManager {
+        let root = &config.root;
+        let manager = WorkspaceManager::new(root, config.keep_failed)
+            .unwrap_or_else(|e| panic!("workspace: can't use `{root}`: {e:?}"));
+        if config.tmpfs && !manager.is_on_tmpfs() {
+            tracing::warn!("workspace: `{root}` is not on a tmpfs");
+        }
+        manager
+    }
+
+    pub fn new<P: AsRef<Path>>(root: P, keep_failed: bool) -> Result<WorkspaceManager, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|e| Error::FileSystemError(format!("{e}")))?;
+        Ok(WorkspaceManager {
+            root,
+            keep_failed,
+            seq: AtomicU64::new(0),
+            run_seq: AtomicU64::new(0),
+        })
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_on_tmpfs(&self) -> bool {
+        statfs(&self.root)
+            .map(|stat| stat.filesystem_type() == TMPFS_MAGIC)
+            .unwrap_or(false)
+    }
+
+    /// Creates `sub-<submission_id>`, suffixed if a kept workspace of an
+    /// earlier attempt of the same submission is still there.
+    pub fn create(&self, submission_id: u64) -> Result<Workspace, Error> {
+        self.create_dir(&format!("sub-{submission_id}"), submission_id)
+    }
+
+    /// Creates `run-<n>` for a playground run, which has no submission id.
+    pub fn create_for_run(&self) -> Result<Workspace, Error> {
+        let run = self.run_seq.fetch_add(1, Ordering::SeqCst);
+        self.create_dir(&format!("run-{run}"), 0)
+    }
+
+    fn create_dir(&self, name: &str, submission_id: u64) -> Result<Workspace, Error> {
+        let mut path = self.root.join(name);
+        while let Err(e) = fs::create_dir(&path) {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                return Err(Error::FileSystemError(format!("{e}")));
+            }
+            let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+            path = self.root.join(format!("{name}-{seq}"));
+        }
+        Ok(Workspace {
+            submission_id,
+            path,
+            keep_failed: self.keep_failed,
+            failed: AtomicBool::new(false),
+        })
+    }
+}
+
+/// The directory holding everything a single submission produces: source,
+/// executable, compilation log and outputs. It's removed on drop, unless
+/// the run failed and failed workspaces are kept for debugging.
+#[derive(Debug)]
+pub struct Workspace {
+    /// 0 for playground runs
+    submission_id: u64,
+    path: PathBuf,
+    keep_failed: bool,
+    failed: AtomicBool,
+}
+
+impl Workspace {
+    pub fn get_submission_id(&self) -> u64 {
+        self.submission_id
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Full path of `name` inside the workspace.
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).display().to_string()
+    }
+
+    pub fn mark_failed(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if self.keep_failed && self.is_failed() {
+            tracing::info!("workspace: keeping `{}`", self.path.display());
+            return;
+        }
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            tracing::warn!("workspace: can't remove `{}`: {e}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::WorkspaceManager;
+
+    #[test]
+    fn test_workspace_cleanup() {
+        let root = std::env::temp_dir().join(format!("coj-ws-{}", std::process::id()));
+        let manager = WorkspaceManager::new(&root, true).unwrap();
+
+        let passed = manager.create(1).unwrap();
+        let failed = manager.create(1).unwrap();
+        assert_ne!(passed.get_path(), failed.get_path());
+        fs::write(passed.file("main.cpp"), "").unwrap();
+        fs::write(failed.file("main.cpp"), "").unwrap();
+        let (passed_path, failed_path) =
+            (passed.get_path().to_owned(), failed.get_path().to_owned());
+
+        failed.mark_failed();
+        drop(passed);
+        drop(failed);
+        assert!(!passed_path.exists());
+        assert!(failed_path.exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+pub mod config;
 pub mod judge;
+pub mod metrics;
 pub mod server;
+pub mod store;
 pub mod thread_pool;
+pub mod webhook;
@@ -1,23 +1,77 @@
-use coffee_oj_judge::judge;
-use coffee_oj_judge::server::{startup::WebApp, utils};
+use clap::Parser;
+use coffee_oj_judge::config::Config;
+use coffee_oj_judge::judge::{self, consts::CONFIG_PATH};
+use coffee_oj_judge::server::{listener::ListenerConfig, startup::WebApp, utils};
 use once_cell::sync::Lazy;
 
+/// Flags take precedence over `[server]` in `config.toml`.
+#[derive(Parser, Debug)]
+#[command(about = "CoffeeOJ judge server")]
+struct Cli {
+    /// Address to listen on
+    #[arg(long)]
+    host: Option<String>,
+    /// Port to listen on, 0 picks a free one
+    #[arg(long)]
+    port: Option<u16>,
+    /// HTTP workers, 0 for one per core
+    #[arg(long)]
+    workers: Option<usize>,
+    /// Also listen on this Unix domain socket
+    #[arg(long)]
+    unix_socket: Option<String>,
+    /// PEM certificate chain, serves TLS with --tls-key
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<String>,
+    /// PEM private key
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<String>,
+}
+
+impl Cli {
+    fn apply(self, config: &mut ListenerConfig) {
+        if let Some(host) = self.host {
+            config.host = host;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(workers) = self.workers {
+            config.workers = workers;
+        }
+        if self.unix_socket.is_some() {
+            config.unix_socket = self.unix_socket;
+        }
+        if self.tls_cert.is_some() {
+            config.tls_cert = self.tls_cert;
+            config.tls_key = self.tls_key;
+        }
+    }
+}
+
 fn init_lazy() {
     Lazy::force(&judge::consts::LANG_EXTENSIONS);
 }
 
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
+    let cli = Cli::parse();
     init_lazy();
     utils::telemetry::setup_log("coj_judge", "info", std::io::stdout);
-    let web_app = WebApp::new().await?;
+    let mut config = Config::load(CONFIG_PATH);
+    cli.apply(&mut config.server);
+    let web_app = WebApp::new(&config).await?;
     web_app.run().await?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use coffee_oj_judge::{judge::task, thread_pool::thread_pool_builder::ThreadPoolBuilder};
+    use coffee_oj_judge::{
+        config::Config,
+        judge::{consts::CONFIG_PATH, task},
+        thread_pool::thread_pool_builder::ThreadPoolBuilder,
+    };
 
     use crate::init_lazy;
 
@@ -25,7 +79,7 @@ mod tests {
     fn manual_main_test() {
         init_lazy();
 
-        let thread_pool = ThreadPoolBuilder::new().build();
+        let thread_pool = ThreadPoolBuilder::new(&Config::load(CONFIG_PATH)).build();
 
         let a_task = task::Task::new(
             1,
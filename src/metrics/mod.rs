@@ -0,0 +1,214 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::judge::{comparer, compiler, sandbox, Verdict};
+use crate::thread_pool::ThreadPool;
+
+/// The metrics scraped from `GET /metrics`.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+const COMPILE_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+const RUN_BUCKETS: &[f64] = &[0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub struct Metrics {
+    registry: Registry,
+    /// finished submissions, by `lang` and `verdict`
+    pub submissions: IntCounterVec,
+    /// judged testcases, by `verdict`
+    pub testcases: IntCounterVec,
+    /// compilations, by `lang`
+    pub compile_duration: HistogramVec,
+    /// a testcase, from running it to comparing its output
+    pub run_duration: Histogram,
+    /// commands the sandbox couldn't run, by `kind`
+    pub sandbox_failures: IntCounterVec,
+    /// outputs that couldn't be compared, by `kind`
+    pub compare_failures: IntCounterVec,
+    /// compilations that left no log, by `kind`
+    pub compile_failures: IntCounterVec,
+    /// requests and submissions turned away, by `kind`
+    pub throttled: IntCounterVec,
+    /// submissions queued or being judged
+    pub queued_submissions: IntGauge,
+    /// jobs waiting for a worker, a submission has one per testcase
+    pub queued_jobs: IntGauge,
+    /// workers by `state`: `max`, `alive`, `active` or `panicked`
+    pub workers: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let opts = |name: &str, help: &str| Opts::new(name, help).namespace("coffee_oj");
+        let metrics = Metrics {
+            registry: Registry::new(),
+            submissions: IntCounterVec::new(
+                opts("submissions_total", "Finished submissions"),
+                &["lang", "verdict"],
+            )
+            .unwrap(),
+            testcases: IntCounterVec::new(
+                opts("testcases_total", "Judged testcases"),
+                &["verdict"],
+            )
+            .unwrap(),
+            compile_duration: HistogramVec::new(
+                HistogramOpts::from(opts("compile_duration_seconds", "Compilation time"))
+                    .buckets(COMPILE_BUCKETS.to_vec()),
+                &["lang"],
+            )
+            .unwrap(),
+            run_duration: Histogram::with_opts(
+                HistogramOpts::from(opts("run_duration_seconds", "Testcase run time"))
+                    .buckets(RUN_BUCKETS.to_vec()),
+            )
+            .unwrap(),
+            sandbox_failures: IntCounterVec::new(
+                opts(
+                    "sandbox_failures_total",
+                    "Commands the sandbox couldn't run",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+            compare_failures: IntCounterVec::new(
+                opts(
+                    "compare_failures_total",
+                    "Outputs that couldn't be compared",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+            compile_failures: IntCounterVec::new(
+                opts("compile_failures_total", "Compilations that left no log"),
+                &["kind"],
+            )
+            .unwrap(),
+            throttled: IntCounterVec::new(
+                opts(
+                    "throttled_total",
+                    "Requests and submissions over the rate limits",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+            queued_submissions: IntGauge::with_opts(opts(
+                "queued_submissions",
+                "Submissions queued or being judged",
+            ))
+            .unwrap(),
+            queued_jobs: IntGauge::with_opts(opts("queued_jobs", "Jobs waiting for a worker"))
+                .unwrap(),
+            workers: IntGaugeVec::new(opts("workers", "Judge workers by state"), &["state"])
+                .unwrap(),
+        };
+
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.submissions.clone()),
+            Box::new(metrics.testcases.clone()),
+            Box::new(metrics.compile_duration.clone()),
+            Box::new(metrics.run_duration.clone()),
+            Box::new(metrics.sandbox_failures.clone()),
+            Box::new(metrics.compare_failures.clone()),
+            Box::new(metrics.compile_failures.clone()),
+            Box::new(metrics.throttled.clone()),
+            Box::new(metrics.queued_submissions.clone()),
+            Box::new(metrics.queued_jobs.clone()),
+            Box::new(metrics.workers.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+        metrics
+    }
+
+    pub fn record_submission(&self, lang: &str, verdict: Verdict) {
+        self.submissions
+            .with_label_values(&[lang, verdict.as_str()])
+            .inc();
+    }
+
+    pub fn record_testcase(&self, verdict: Verdict) {
+        self.testcases.with_label_values(&[verdict.as_str()]).inc();
+    }
+
+    pub fn record_sandbox_failure(&self, e: &sandbox::Error) {
+        let kind = match e {
+            sandbox::Error::CommandEmptyError => "command_empty",
+            sandbox::Error::ForkFailed => "fork_failed",
+            sandbox::Error::FileSystemError(_) => "file_system",
+        };
+        self.sandbox_failures.with_label_values(&[kind]).inc();
+    }
+
+    /// Outputs of a run that can't be compared.
+    pub fn record_compare_failure(&self, e: &comparer::Error) {
+        let kind = match e {
+            comparer::Error::FileSystemError => "file_system",
+            comparer::Error::IOError => "io",
+        };
+        self.compare_failures.with_label_values(&[kind]).inc();
+    }
+
+    /// Compilations whose log wasn't written.
+    pub fn record_compile_failure(&self, e: &compiler::Error) {
+        if matches!(e, compiler::Error::NoCompilationLogError) {
+            self.compile_failures.with_label_values(&["no_log"]).inc();
+        }
+    }
+
+    /// `kind` is `request`, `submission` or `auth`, an IP over `max_auth_failures`.
+    pub fn record_throttled(&self, kind: &str) {
+        self.throttled.with_label_values(&[kind]).inc();
+    }
+
+    /// The pool is read when scraped rather than on every change.
+    pub fn observe_pool(&self, thread_pool: &ThreadPool) {
+        self.queued_submissions
+            .set(thread_pool.queued_task_count() as i64);
+        self.queued_jobs.set(thread_pool.queued_job_count() as i64);
+        let workers = [
+            ("max", thread_pool.max_thread_count()),
+            ("alive", thread_pool.alive_thread_count()),
+            ("active", thread_pool.active_thread_count()),
+            ("panicked", thread_pool.panic_thread_count()),
+        ];
+        for (state, count) in workers {
+            self.workers.with_label_values(&[state]).set(count as i64);
+        }
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::METRICS;
+    use crate::judge::{comparer, compiler, sandbox, Verdict};
+
+    #[test]
+    fn test_render() {
+        METRICS.record_submission("cpp", Verdict::WrongAnswer);
+        METRICS.record_sandbox_failure(&sandbox::Error::ForkFailed);
+        METRICS.record_compare_failure(&comparer::Error::IOError);
+        METRICS.record_compile_failure(&compiler::Error::NoCompilationLogError);
+        METRICS.run_duration.observe(0.02);
+
+        let text = METRICS.render();
+        assert!(text.contains(r#"coffee_oj_submissions_total{lang="cpp",verdict="WrongAnswer"}"#));
+        assert!(text.contains(r#"coffee_oj_sandbox_failures_total{kind="fork_failed"}"#));
+        assert!(text.contains(r#"coffee_oj_compare_failures_total{kind="io"}"#));
+        assert!(text.contains(r#"coffee_oj_compile_failures_total{kind="no_log"}"#));
+        assert!(!text.contains(r#"coffee_oj_sandbox_failures_total{kind="io"}"#));
+        assert!(text.contains(r#"coffee_oj_run_duration_seconds_bucket{le="0.05"}"#));
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    future::{ready, Ready},
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error, HttpMessage, ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+
+use super::{ApiTokens, Role};
+use crate::server::error::ApiError;
+use crate::server::rate_limit::{peer_client, RateLimiter};
+use crate::server::utils::route::route_pattern;
+
+/// The role a request needs by the pattern of its route, `None` if it's
+/// public: the frontend, which is the default service, probes, metrics and
+/// the OpenAPI spec are. Routes missing here are for admins.
+fn required_role(pattern: Option<&str>) -> Option<Role> {
+    match pattern {
+        None | Some("/healthz" | "/readyz" | "/metrics" | "/api/v1/openapi.json") => None,
+        Some(
+            "/api/v1/submit"
+            | "/api/v1/run"
+            | "/api/v1/status/{id}"
+            | "/api/v1/submissions"
+            | "/api/v1/submissions/batch"
+            | "/api/v1/submissions/{id}"
+            | "/api/v1/submissions/{id}/events",
+        ) => Some(Role::Submitter),
+        Some(_) => Some(Role::Admin),
+    }
+}
+
+/// Checks the `Authorization: Bearer <token>` of every request against the
+/// API tokens, and puts the [`super::Principal`] into the request extensions.
+/// IPs sending too many invalid tokens are throttled, see [`RateLimiter::check_auth`].
+pub struct Authentication {
+    tokens: Arc<ApiTokens>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Authentication {
+    pub fn new(tokens: Arc<ApiTokens>, limiter: Arc<RateLimiter>) -> Authentication {
+        Authentication { tokens, limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service,
+            tokens: self.tokens.clone(),
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: S,
+    tokens: Arc<ApiTokens>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let required = required_role(route_pattern(&req).as_deref());
+        if let (true, Some(required)) = (self.tokens.is_enabled(), required) {
+            let client = peer_client(req.request());
+            let token = req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            let rejection = match token.map(|token| {
+                self.limiter
+                    .check_auth(&client)
+                    .map(|()| self.tokens.authenticate(token.trim()))
+            }) {
+                None => Some(ApiError::MissingToken),
+                Some(Err(retry_after)) => {
+                    tracing::info!("auth: throttled `{client}`");
+                    Some(ApiError::RateLimited { retry_after })
+                }
+                Some(Ok(None)) => {
+                    self.limiter.record_auth_failure(&client);
+                    Some(ApiError::InvalidToken)
+                }
+                Some(Ok(Some(principal))) if principal.role < required => {
+                    Some(ApiError::AdminRequired)
+                }
+                Some(Ok(Some(principal))) => {
+                    req.extensions_mut().insert(principal);
+                    None
+                }
+            };
+            if let Some(e) = rejection {
+                let res = req.into_response(e.error_response());
+                return Box::pin(ready(Ok(res.map_into_right_body())));
+            }
+        }
+
+        let res = self.service.call(req);
+        Box::pin(async move { Ok(res.await?.map_into_left_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{
+        http::{header, Method, StatusCode},
+        test, web, App, HttpResponse,
+    };
+    use utoipa::OpenApi;
+
+    use super::Authentication;
+    use crate::server::auth::{hash_token, ApiTokens, Role};
+    use crate::server::openapi::ApiDoc;
+    use crate::server::rate_limit::{RateLimitConfig, RateLimiter};
+
+    /// `(method, pattern, a path it matches, role needed)` of every route of
+    /// [`ApiDoc`].
+    const ROUTES: &[(&str, &str, &str, Option<Role>)] = &[
+        ("GET", "/healthz", "/healthz", None),
+        ("GET", "/readyz", "/readyz", None),
+        ("GET", "/metrics", "/metrics", None),
+        ("GET", "/api/v1/openapi.json", "/api/v1/openapi.json", None),
+        (
+            "POST",
+            "/api/v1/submit",
+            "/api/v1/submit",
+            Some(Role::Submitter),
+        ),
+        ("POST", "/api/v1/run", "/api/v1/run", Some(Role::Submitter)),
+        (
+            "GET",
+            "/api/v1/status/{id}",
+            "/api/v1/status/1",
+            Some(Role::Submitter),
+        ),
+        (
+            "GET",
+            "/api/v1/submissions",
+            "/api/v1/submissions",
+            Some(Role::Submitter),
+        ),
+        (
+            "POST",
+            "/api/v1/submissions/batch",
+            "/api/v1/submissions/batch",
+            Some(Role::Submitter),
+        ),
+        (
+            "GET",
+            "/api/v1/submissions/{id}",
+            "/api/v1/submissions/1",
+            Some(Role::Submitter),
+        ),
+        (
+            "DELETE",
+            "/api/v1/submissions/{id}",
+            "/api/v1/submissions/1",
+            Some(Role::Submitter),
+        ),
+        (
+            "GET",
+            "/api/v1/submissions/{id}/events",
+            "/api/v1/submissions/1/events",
+            Some(Role::Submitter),
+        ),
+        (
+            "POST",
+            "/api/v1/rejudge",
+            "/api/v1/rejudge",
+            Some(Role::Admin),
+        ),
+        (
+            "POST",
+            "/api/v1/rejudge/submissions/{id}",
+            "/api/v1/rejudge/submissions/1",
+            Some(Role::Admin),
+        ),
+        (
+            "POST",
+            "/api/v1/rejudge/problems/{problem_id}",
+            "/api/v1/rejudge/problems/1",
+            Some(Role::Admin),
+        ),
+        (
+            "GET",
+            "/api/v1/rejudges/{id}",
+            "/api/v1/rejudges/1",
+            Some(Role::Admin),
+        ),
+        (
+            "GET",
+            "/api/v1/admin/pool",
+            "/api/v1/admin/pool",
+            Some(Role::Admin),
+        ),
+    ];
+
+    /// Statuses of `(method, path, token, IP)` requests in order, to the
+    /// routes above answering 200 and a default service answering 404.
+    async fn statuses(
+        requests: &[(&str, &str, Option<&str>, &str)],
+        max_auth_failures: u64,
+    ) -> Vec<StatusCode> {
+        let tokens = ApiTokens::new()
+            .with_token("alice", Role::Submitter, &hash_token("alice-token"))
+            .with_token("root", Role::Admin, &hash_token("root-token"));
+        let limiter = RateLimiter::new(RateLimitConfig {
+            max_auth_failures,
+            ..Default::default()
+        });
+        let mut app = App::new()
+            .wrap(Authentication::new(Arc::new(tokens), Arc::new(limiter)))
+            .default_service(web::to(HttpResponse::NotFound));
+        for (method, pattern, _, _) in ROUTES {
+            let method = Method::from_bytes(method.as_bytes()).unwrap();
+            app = app.route(pattern, web::method(method).to(HttpResponse::Ok));
+        }
+        let app = test::init_service(app).await;
+
+        let mut statuses = vec![];
+        for (method, path, token, ip) in requests {
+            let mut req = test::TestRequest::default()
+                .method(Method::from_bytes(method.as_bytes()).unwrap())
+                .uri(path)
+                .peer_addr(format!("{ip}:40000").parse().unwrap());
+            if let Some(token) = token {
+                req = req.insert_header((header::AUTHORIZATION, format!("Bearer {token}")));
+            }
+            statuses.push(test::call_service(&app, req.to_request()).await.status());
+        }
+        statuses
+    }
+
+    #[actix_web::test]
+    async fn test_every_route_is_listed() {
+        let openapi = ApiDoc::openapi();
+        let mut documented = vec![];
+        for (path, item) in &openapi.paths.paths {
+            let methods = [
+                ("GET", &item.get),
+                ("POST", &item.post),
+                ("PUT", &item.put),
+                ("PATCH", &item.patch),
+                ("DELETE", &item.delete),
+            ];
+            for (method, operation) in methods {
+                if operation.is_some() {
+                    documented.push((method, path.as_str()));
+                }
+            }
+        }
+        let mut listed: Vec<_> = ROUTES
+            .iter()
+            .map(|(method, pattern, _, _)| (*method, *pattern))
+            .collect();
+        documented.sort();
+        listed.sort();
+        assert_eq!(documented, listed);
+    }
+
+    #[actix_web::test]
+    async fn test_roles_per_route() {
+        for (method, _, path, role) in ROUTES {
+            let requests: Vec<_> = [
+                None,
+                Some("wrong-token"),
+                Some("alice-token"),
+                Some("root-token"),
+            ]
+            .into_iter()
+            .map(|token| (*method, *path, token, "10.0.0.1"))
+            .collect();
+            let expected = match role {
+                None => [StatusCode::OK; 4],
+                Some(Role::Submitter) => [
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::OK,
+                    StatusCode::OK,
+                ],
+                Some(Role::Admin) => [
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::FORBIDDEN,
+                    StatusCode::OK,
+                ],
+            };
+            assert_eq!(statuses(&requests, 0).await, expected, "{method} {path}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_encoded_paths_need_the_same_role() {
+        let submitter = Some("alice-token");
+        let requests = [
+            ("POST", "/api/v1/%72ejudge", submitter, "10.0.0.1"),
+            ("POST", "/%61pi/v1/rejudge", submitter, "10.0.0.1"),
+            ("GET", "/api/v1/admin/%70ool", submitter, "10.0.0.1"),
+            ("GET", "/%61pi/v1/submissions", None, "10.0.0.1"),
+            // an encoded slash isn't one, nothing is routed there
+            ("GET", "/api/v1/admin%2Fpool", submitter, "10.0.0.1"),
+        ];
+        assert_eq!(
+            statuses(&requests, 0).await,
+            [
+                StatusCode::FORBIDDEN,
+                StatusCode::FORBIDDEN,
+                StatusCode::FORBIDDEN,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::NOT_FOUND,
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_tokens_are_throttled_by_ip() {
+        let requests = [
+            ("GET", "/api/v1/submissions", Some("guess-1"), "10.0.0.1"),
+            ("GET", "/api/v1/submissions", Some("guess-2"), "10.0.0.1"),
+            // even a right guess isn't told apart
+            (
+                "GET",
+                "/api/v1/submissions",
+                Some("alice-token"),
+                "10.0.0.1",
+            ),
+            (
+                "GET",
+                "/api/v1/submissions",
+                Some("alice-token"),
+                "10.0.0.2",
+            ),
+            // public routes aren't authenticated
+            ("GET", "/healthz", Some("guess-3"), "10.0.0.1"),
+        ];
+        assert_eq!(
+            statuses(&requests, 2).await,
+            [
+                StatusCode::UNAUTHORIZED,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::OK,
+                StatusCode::OK,
+            ]
+        );
+    }
+}
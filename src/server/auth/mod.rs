@@ -0,0 +1,207 @@
+pub mod middleware;
+
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use crate::server::error::ApiError;
+use crate::store::SubmissionRecord;
+
+pub use middleware::Authentication;
+
+/// Admins can do everything submitters can.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Role {
+    /// submits and reads their own submissions
+    Submitter,
+    /// rejudges, manages problems and the judge itself, reads everything
+    Admin,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Option<Role> {
+        match s {
+            "submitter" => Some(Role::Submitter),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Whoever the request was authenticated as, in the request extensions.
+/// Handlers get it as `Option<web::ReqData<Principal>>`, `None` when
+/// authentication is disabled.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub role: Role,
+}
+
+impl Principal {
+    /// Admins read everything, submitters only what they submitted.
+    pub fn can_read(&self, owner: Option<&str>) -> bool {
+        self.role == Role::Admin || owner == Some(self.name.as_str())
+    }
+}
+
+/// Whether `principal`, if any, may read the submission `record`.
+pub fn check_can_read(
+    principal: Option<&Principal>,
+    record: &SubmissionRecord,
+) -> Result<(), ApiError> {
+    match principal {
+        Some(principal) if !principal.can_read(record.owner.as_deref()) => {
+            Err(ApiError::NotYourSubmission(record.id))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Submissions of others are hidden from submitters, `None` if anything
+/// can be listed.
+pub fn owner_filter(principal: Option<&Principal>) -> Option<String> {
+    match principal {
+        Some(principal) if principal.role != Role::Admin => Some(principal.name.clone()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ApiToken {
+    name: String,
+    role: Role,
+    /// hex SHA-256 of the token, the token itself is never stored
+    hash: String,
+}
+
+/// The API tokens of `[auth]` in the config.
+#[derive(Debug, Clone)]
+pub struct ApiTokens {
+    enabled: bool,
+    tokens: Vec<ApiToken>,
+}
+
+impl Default for ApiTokens {
+    fn default() -> ApiTokens {
+        ApiTokens::disabled()
+    }
+}
+
+impl ApiTokens {
+    /// `[auth]` and its `[[auth.tokens]]`.
+    pub fn from_config(data: &Table) -> ApiTokens {
+        let auth = match data.get("auth") {
+            Some(Value::Table(auth)) => auth,
+            None => return ApiTokens::disabled(),
+            _ => panic!("config: [auth] should be set correctly"),
+        };
+        let enabled = match auth.get("enabled") {
+            Some(Value::Boolean(val)) => *val,
+            None => false,
+            _ => panic!("config: [auth] should be set correctly"),
+        };
+        let tokens = match auth.get("tokens") {
+            Some(Value::Array(tokens)) => tokens
+                .iter()
+                .map(|token| {
+                    let get = |key: &str| match token.get(key) {
+                        Some(Value::String(val)) => val.clone(),
+                        _ => panic!("config: [[auth.tokens]] should be set correctly"),
+                    };
+                    let role = Role::parse(&get("role")).unwrap_or_else(|| {
+                        panic!("config: [[auth.tokens]] should be set correctly")
+                    });
+                    ApiToken {
+                        name: get("name"),
+                        role,
+                        hash: get("hash").to_ascii_lowercase(),
+                    }
+                })
+                .collect(),
+            None => vec![],
+            _ => panic!("config: [[auth.tokens]] should be set correctly"),
+        };
+        if enabled && tokens.is_empty() {
+            tracing::warn!("auth: enabled without tokens, every API request is rejected");
+        }
+
+        ApiTokens { enabled, tokens }
+    }
+}
+
+impl ApiTokens {
+    /// Lets every request through.
+    pub fn disabled() -> ApiTokens {
+        ApiTokens {
+            enabled: false,
+            tokens: vec![],
+        }
+    }
+
+    pub fn new() -> ApiTokens {
+        ApiTokens {
+            enabled: true,
+            tokens: vec![],
+        }
+    }
+
+    /// Adds a token by its hash, see [`hash_token`].
+    pub fn with_token(mut self, name: &str, role: Role, hash: &str) -> ApiTokens {
+        self.tokens.push(ApiToken {
+            name: name.to_string(),
+            role,
+            hash: hash.to_ascii_lowercase(),
+        });
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The principal the bearer `token` belongs to.
+    pub fn authenticate(&self, token: &str) -> Option<Principal> {
+        let hash = hash_token(token);
+        self.tokens
+            .iter()
+            .find(|t| constant_time_eq(t.hash.as_bytes(), hash.as_bytes()))
+            .map(|t| Principal {
+                name: t.name.clone(),
+                role: t.role,
+            })
+    }
+}
+
+/// What `[[auth.tokens]] hash` holds for `token`, i.e. `sha256sum` of it.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_token, ApiTokens, Principal, Role};
+
+    #[test]
+    fn test_authenticate() {
+        let tokens = ApiTokens::new()
+            .with_token("alice", Role::Submitter, &hash_token("alice-token"))
+            .with_token("root", Role::Admin, &hash_token("root-token"));
+
+        let alice = tokens.authenticate("alice-token").unwrap();
+        assert_eq!(
+            alice,
+            Principal {
+                name: "alice".to_string(),
+                role: Role::Submitter
+            }
+        );
+        assert!(tokens.authenticate("alice-token ").is_none());
+        assert!(alice.can_read(Some("alice")));
+        assert!(!alice.can_read(Some("bob")));
+        assert!(!alice.can_read(None));
+        assert!(tokens.authenticate("root-token").unwrap().can_read(None));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fmt, time::Duration};
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::server::models::SubmissionError;
+use crate::store;
+use crate::thread_pool;
+
+/// Every error the API answers with. The body is
+/// `{"code": "...", "message": "..."}`, `code` is stable and meant to be
+/// branched on, `message` is for humans and may change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// malformed JSON, query string or path
+    InvalidRequest(String),
+    /// a body that isn't UTF-8
+    InvalidEncoding,
+    PayloadTooLarge {
+        limit: usize,
+    },
+    UnsupportedLanguage(String),
+    InvalidProblemId(String),
+    ProblemNotFound(u64),
+    NoTestcases(u64),
+    LanguageNotAllowed {
+        lang: String,
+        problem_id: u64,
+    },
+    SourceTooLarge {
+        limit: u64,
+    },
+    InvalidCallbackUrl,
+    InvalidVerdict(String),
+    /// a batch with invalid submissions, by their position in the batch
+    InvalidBatch(Vec<(usize, ApiError)>),
+    InvalidBatchSize {
+        max: usize,
+    },
+    SubmissionNotFound(u64),
+    RejudgeNotFound(u64),
+    /// cancelling a submission that has a final verdict
+    AlreadyFinished(u64),
+    /// rejudging a submission that's still queued or being judged
+    StillJudging(u64),
+    MissingToken,
+    InvalidToken,
+    AdminRequired,
+    NotYourSubmission(u64),
+    RateLimited {
+        retry_after: Duration,
+    },
+    TooManyPending {
+        max: u64,
+    },
+    QueueFull,
+    ShuttingDown,
+    Internal,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct BatchErrorRet {
+    /// position of the submission in the batch
+    index: usize,
+    code: String,
+    message: String,
+}
+
+/// The body of every error.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ApiErrorRet {
+    #[schema(example = "unsupported_language")]
+    code: String,
+    message: String,
+    /// only for `invalid_batch`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<BatchErrorRet>>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::InvalidEncoding => "invalid_encoding",
+            ApiError::PayloadTooLarge { .. } => "payload_too_large",
+            ApiError::UnsupportedLanguage(_) => "unsupported_language",
+            ApiError::InvalidProblemId(_) => "invalid_problem_id",
+            ApiError::ProblemNotFound(_) => "problem_not_found",
+            ApiError::NoTestcases(_) => "no_testcases",
+            ApiError::LanguageNotAllowed { .. } => "language_not_allowed",
+            ApiError::SourceTooLarge { .. } => "source_too_large",
+            ApiError::InvalidCallbackUrl => "invalid_callback_url",
+            ApiError::InvalidVerdict(_) => "invalid_verdict",
+            ApiError::InvalidBatch(_) => "invalid_batch",
+            ApiError::InvalidBatchSize { .. } => "invalid_batch_size",
+            ApiError::SubmissionNotFound(_) => "submission_not_found",
+            ApiError::RejudgeNotFound(_) => "rejudge_not_found",
+            ApiError::AlreadyFinished(_) => "already_finished",
+            ApiError::StillJudging(_) => "still_judging",
+            ApiError::MissingToken => "missing_token",
+            ApiError::InvalidToken => "invalid_token",
+            ApiError::AdminRequired => "admin_required",
+            ApiError::NotYourSubmission(_) => "not_your_submission",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::TooManyPending { .. } => "too_many_pending",
+            ApiError::QueueFull => "queue_full",
+            ApiError::ShuttingDown => "shutting_down",
+            ApiError::Internal => "internal_error",
+        }
+    }
+
+    /// `lang` and `problem_id` are those of the invalid submission.
+    pub fn from_submission_error(e: SubmissionError, lang: &str, problem_id: &str) -> ApiError {
+        match e {
+            SubmissionError::UnsupportedLanguage => ApiError::UnsupportedLanguage(lang.to_string()),
+            SubmissionError::WrongProblemId => ApiError::InvalidProblemId(problem_id.to_string()),
+            SubmissionError::InvalidCallbackUrl => ApiError::InvalidCallbackUrl,
+            SubmissionError::SourceTooLarge { limit } => ApiError::SourceTooLarge { limit },
+            SubmissionError::ProblemNotFound(id) => ApiError::ProblemNotFound(id),
+            SubmissionError::NoTestcases(id) => ApiError::NoTestcases(id),
+            SubmissionError::LanguageNotAllowed(problem_id) => ApiError::LanguageNotAllowed {
+                lang: lang.to_string(),
+                problem_id,
+            },
+        }
+    }
+
+    /// How long to wait before trying again, if that's all it takes.
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::RateLimited { retry_after } => Some(*retry_after),
+            // judging one of the pending ones takes about as long
+            ApiError::TooManyPending { .. } | ApiError::QueueFull | ApiError::ShuttingDown => {
+                Some(Duration::from_secs(5))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidRequest(info) => write!(f, "Invalid request: {info}"),
+            ApiError::InvalidEncoding => write!(f, "The body isn't valid UTF-8"),
+            ApiError::PayloadTooLarge { limit } => {
+                write!(f, "The body is larger than {limit} bytes")
+            }
+            ApiError::UnsupportedLanguage(lang) => write!(f, "Unsupported language `{lang}`"),
+            ApiError::InvalidProblemId(id) => write!(f, "Invalid problem id `{id}`"),
+            ApiError::ProblemNotFound(id) => write!(f, "Problem {id} doesn't exist"),
+            ApiError::NoTestcases(id) => write!(f, "Problem {id} has no testcases"),
+            ApiError::LanguageNotAllowed { lang, problem_id } => {
+                write!(f, "`{lang}` isn't allowed for problem {problem_id}")
+            }
+            ApiError::SourceTooLarge { limit } => {
+                write!(f, "The source is larger than {limit} bytes")
+            }
+            ApiError::InvalidCallbackUrl => write!(f, "The callback url isn't http(s)"),
+            ApiError::InvalidVerdict(verdict) => write!(f, "Unknown verdict `{verdict}`"),
+            ApiError::InvalidBatch(errors) => {
+                write!(f, "{} submissions of the batch are invalid", errors.len())
+            }
+            ApiError::InvalidBatchSize { max } => {
+                write!(f, "A batch holds 1 to {max} submissions")
+            }
+            ApiError::SubmissionNotFound(id) => write!(f, "Submission {id} doesn't exist"),
+            ApiError::RejudgeNotFound(id) => write!(f, "Rejudge {id} doesn't exist"),
+            ApiError::AlreadyFinished(id) => write!(f, "Submission {id} is already finished"),
+            ApiError::StillJudging(id) => write!(f, "Submission {id} is still being judged"),
+            ApiError::MissingToken => write!(f, "Missing bearer token"),
+            ApiError::InvalidToken => write!(f, "Unknown token"),
+            ApiError::AdminRequired => write!(f, "Admin role required"),
+            ApiError::NotYourSubmission(id) => write!(f, "Submission {id} isn't yours"),
+            ApiError::RateLimited { .. } => write!(f, "Rate limit exceeded"),
+            ApiError::TooManyPending { max } => {
+                write!(f, "At most {max} submissions may be pending")
+            }
+            ApiError::QueueFull => write!(f, "The judge queue is full"),
+            ApiError::ShuttingDown => write!(f, "The judge is shutting down"),
+            ApiError::Internal => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_)
+            | ApiError::InvalidEncoding
+            | ApiError::UnsupportedLanguage(_)
+            | ApiError::LanguageNotAllowed { .. }
+            | ApiError::InvalidProblemId(_)
+            | ApiError::InvalidCallbackUrl
+            | ApiError::InvalidVerdict(_)
+            | ApiError::InvalidBatch(_)
+            | ApiError::InvalidBatchSize { .. } => StatusCode::BAD_REQUEST,
+            ApiError::SourceTooLarge { .. } | ApiError::PayloadTooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            ApiError::NoTestcases(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::ProblemNotFound(_)
+            | ApiError::SubmissionNotFound(_)
+            | ApiError::RejudgeNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::AlreadyFinished(_) | ApiError::StillJudging(_) => StatusCode::CONFLICT,
+            ApiError::MissingToken | ApiError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ApiError::AdminRequired | ApiError::NotYourSubmission(_) => StatusCode::FORBIDDEN,
+            ApiError::RateLimited { .. } | ApiError::TooManyPending { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ApiError::QueueFull | ApiError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let details = match self {
+            ApiError::InvalidBatch(errors) => Some(
+                errors
+                    .iter()
+                    .map(|(index, e)| BatchErrorRet {
+                        index: *index,
+                        code: e.code().to_string(),
+                        message: e.to_string(),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+        let ret = ApiErrorRet {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            details,
+        };
+
+        let mut res = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            res.insert_header(("WWW-Authenticate", "Bearer"));
+        }
+        if let Some(retry_after) = self.retry_after() {
+            // in whole seconds, rounded up
+            let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            res.insert_header(("Retry-After", seconds.to_string()));
+        }
+        res.json(ret)
+    }
+}
+
+/// Store failures are logged, the client only learns that something broke.
+impl From<store::Error> for ApiError {
+    fn from(e: store::Error) -> ApiError {
+        tracing::error!("store: {e:?}");
+        ApiError::Internal
+    }
+}
+
+impl From<thread_pool::Error> for ApiError {
+    fn from(e: thread_pool::Error) -> ApiError {
+        match e {
+            thread_pool::Error::QueueFull => ApiError::QueueFull,
+            thread_pool::Error::ShuttingDown => ApiError::ShuttingDown,
+            thread_pool::Error::StoreError(e) => {
+                tracing::error!("queue: can't persist the submission: {e:?}");
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+
+    use super::ApiError;
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let e = ApiError::UnsupportedLanguage("cobol".to_string());
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(e.error_response().into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            r#"{"code":"unsupported_language","message":"Unsupported language `cobol`"}"#
+        );
+
+        let e = ApiError::InvalidBatch(vec![(2, ApiError::InvalidCallbackUrl)]);
+        let body = to_bytes(e.error_response().into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["details"][0]["index"], 2);
+        assert_eq!(body["details"][0]["code"], "invalid_callback_url");
+
+        let res = ApiError::QueueFull.error_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get("Retry-After").unwrap(), "5");
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+use toml::{Table, Value};
+
+use crate::judge::consts::{DEFAULT_CORS_MAX_AGE, DEFAULT_WEB_DIST};
+
+#[cfg(feature = "embed-web")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "web/dist"]
+struct EmbeddedAssets;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontendConfig {
+    /// the built frontend, unless it's embedded
+    pub dist: String,
+    /// origins of separately hosted frontends, `*` for any
+    pub cors_origins: Vec<String>,
+    /// seconds browsers may cache a preflight
+    pub cors_max_age: usize,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> FrontendConfig {
+        FrontendConfig::from_config(&Table::new())
+    }
+}
+
+impl FrontendConfig {
+    pub fn from_config(data: &Table) -> FrontendConfig {
+        let web = match data.get("web") {
+            Some(Value::Table(web)) => web.clone(),
+            None => Table::new(),
+            _ => panic!("config: [web] should be set correctly"),
+        };
+        let dist = match web.get("dist") {
+            Some(Value::String(val)) => val.clone(),
+            None => DEFAULT_WEB_DIST.to_string(),
+            _ => panic!("config: [web] should be set correctly"),
+        };
+        let cors_origins = match web.get("cors_origins") {
+            Some(Value::Array(origins)) => origins
+                .iter()
+                .map(|origin| match origin {
+                    Value::String(origin) => origin.clone(),
+                    _ => panic!("config: [web] should be set correctly"),
+                })
+                .collect(),
+            None => vec![],
+            _ => panic!("config: [web] should be set correctly"),
+        };
+        let cors_max_age = match web.get("cors_max_age") {
+            Some(Value::Integer(val)) if *val >= 0 => *val as usize,
+            None => DEFAULT_CORS_MAX_AGE,
+            _ => panic!("config: [web] should be set correctly"),
+        };
+
+        FrontendConfig {
+            dist,
+            cors_origins,
+            cors_max_age,
+        }
+    }
+}
+
+impl FrontendConfig {
+    pub fn is_cors_enabled(&self) -> bool {
+        !self.cors_origins.is_empty()
+    }
+
+    /// Only meant to wrap the app when [`FrontendConfig::is_cors_enabled`],
+    /// it rejects every cross-origin request otherwise.
+    pub fn cors(&self) -> Cors {
+        let mut cors = Cors::default()
+            .allowed_methods([Method::GET, Method::POST, Method::DELETE])
+            .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+            .max_age(self.cors_max_age);
+        for origin in &self.cors_origins {
+            cors = match origin.as_str() {
+                "*" => cors.allow_any_origin(),
+                origin => cors.allowed_origin(origin),
+            };
+        }
+        cors
+    }
+}
+
+/// A file of the frontend.
+pub struct Asset {
+    pub content: Cow<'static, [u8]>,
+    pub content_type: String,
+    /// vite puts a hash into the names of everything under `assets/`
+    pub immutable: bool,
+}
+
+/// The built frontend, read from `dist` or embedded into the binary with the
+/// `embed-web` feature.
+#[derive(Debug)]
+pub struct Frontend {
+    #[cfg_attr(feature = "embed-web", allow(dead_code))]
+    dist: PathBuf,
+}
+
+impl Default for Frontend {
+    fn default() -> Frontend {
+        Frontend::new(&FrontendConfig::default().dist)
+    }
+}
+
+impl Frontend {
+    pub fn new(dist: &str) -> Frontend {
+        Frontend {
+            dist: PathBuf::from(dist),
+        }
+    }
+
+    /// The file at `path`, or `index.html` for paths of the SPA router, i.e.
+    /// paths without an extension.
+    pub fn get(&self, path: &str) -> Option<Asset> {
+        let path = path.trim_start_matches('/');
+        if path.split('/').any(|segment| segment == "..") {
+            return None;
+        }
+        if !path.is_empty() {
+            if let Some(asset) = self.read(path) {
+                return Some(asset);
+            }
+            if Path::new(path).extension().is_some() {
+                return None;
+            }
+        }
+        self.read("index.html")
+    }
+
+    fn read(&self, path: &str) -> Option<Asset> {
+        let content = self.read_bytes(path)?;
+        Some(Asset {
+            content,
+            content_type: mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+            immutable: path.starts_with("assets/"),
+        })
+    }
+
+    #[cfg(feature = "embed-web")]
+    fn read_bytes(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        EmbeddedAssets::get(path).map(|file| file.data)
+    }
+
+    #[cfg(not(feature = "embed-web"))]
+    fn read_bytes(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        let path = self.dist.join(path);
+        if !path.is_file() {
+            return None;
+        }
+        fs::read(path).ok().map(Cow::Owned)
+    }
+}
+
+#[cfg(all(test, not(feature = "embed-web")))]
+mod tests {
+    use std::fs;
+
+    use super::Frontend;
+
+    #[test]
+    fn test_spa_fallback() {
+        let dist = "/tmp/coffee_oj_frontend_test";
+        let _ = fs::remove_dir_all(dist);
+        fs::create_dir_all(format!("{dist}/assets")).unwrap();
+        fs::write(format!("{dist}/index.html"), "<div id=app>").unwrap();
+        fs::write(format!("{dist}/assets/index-4f2a.js"), "app()").unwrap();
+        let frontend = Frontend::new(dist);
+
+        let asset = frontend.get("/assets/index-4f2a.js").unwrap();
+        assert_eq!(asset.content.as_ref(), b"app()");
+        assert_eq!(asset.content_type, "text/javascript");
+        assert!(asset.immutable);
+
+        for path in ["/", "/submissions/42"] {
+            let asset = frontend.get(path).unwrap();
+            assert_eq!(asset.content.as_ref(), b"<div id=app>");
+            assert_eq!(asset.content_type, "text/html");
+            assert!(!asset.immutable);
+        }
+        assert!(frontend.get("/assets/missing.js").is_none());
+        assert!(frontend.get("/../config.toml").is_none());
+        fs::remove_dir_all(dist).unwrap();
+    }
+}
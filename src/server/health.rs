@@ -0,0 +1,213 @@
+use std::{
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use toml::{Table, Value};
+
+use crate::judge::consts::{DEFAULT_MAX_PANICS, DEFAULT_TOOLCHAIN_CHECK_INTERVAL};
+use crate::thread_pool::ThreadPool;
+
+/// How long `<toolchain> --version` may take.
+const TOOLCHAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthConfig {
+    /// panicked workers before the judge isn't ready any more
+    pub max_panics: u64,
+    /// seconds a toolchain check is trusted
+    pub toolchain_check_interval: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> HealthConfig {
+        HealthConfig::from_config(&Table::new())
+    }
+}
+
+impl HealthConfig {
+    pub fn from_config(data: &Table) -> HealthConfig {
+        let health = match data.get("health") {
+            Some(Value::Table(health)) => health.clone(),
+            None => Table::new(),
+            _ => panic!("config: [health] should be set correctly"),
+        };
+        let get_u64 = |key: &str, default: u64| match health.get(key) {
+            Some(Value::Integer(val)) if *val >= 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [health] should be set correctly"),
+        };
+
+        HealthConfig {
+            max_panics: get_u64("max_panics", DEFAULT_MAX_PANICS),
+            toolchain_check_interval: get_u64(
+                "toolchain_check_interval",
+                DEFAULT_TOOLCHAIN_CHECK_INTERVAL,
+            ),
+        }
+    }
+}
+
+/// The outcome of a single readiness check, `info` tells what's wrong.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub info: String,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<(), String>) -> Check {
+        Check {
+            name,
+            ok: result.is_ok(),
+            info: result.err().unwrap_or_default(),
+        }
+    }
+}
+
+/// Decides whether the judge can take submissions. Toolchains are only
+/// run every `toolchain_check_interval` seconds, probes come more often.
+#[derive(Debug)]
+pub struct Readiness {
+    config: HealthConfig,
+    toolchains: Mutex<Option<(Instant, Result<(), String>)>>,
+    /// the config was loaded and the server built with it
+    started: AtomicBool,
+}
+
+impl Default for Readiness {
+    fn default() -> Readiness {
+        Readiness::new(HealthConfig::default())
+    }
+}
+
+impl Readiness {
+    pub fn new(config: HealthConfig) -> Readiness {
+        Readiness {
+            config,
+            toolchains: Mutex::new(None),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Passes the `config` check from now on, called once startup is done.
+    pub fn mark_started(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    /// Runs every check, blocking while toolchains are run.
+    pub fn check(&self, thread_pool: &ThreadPool) -> Vec<Check> {
+        vec![
+            Check::new("config", self.check_config()),
+            Check::new("toolchains", self.check_toolchains(thread_pool)),
+            Check::new("workers", self.check_workers(thread_pool)),
+        ]
+    }
+
+    /// The config is only read at startup, which panics if it's malformed.
+    fn check_config(&self) -> Result<(), String> {
+        if !self.started.load(Ordering::SeqCst) {
+            return Err("the server is still starting".to_string());
+        }
+        Ok(())
+    }
+
+    fn check_toolchains(&self, thread_pool: &ThreadPool) -> Result<(), String> {
+        let interval = Duration::from_secs(self.config.toolchain_check_interval);
+        let mut cached = self.toolchains.lock().unwrap();
+        if let Some((checked_at, result)) = cached.as_ref() {
+            if checked_at.elapsed() < interval {
+                return result.clone();
+            }
+        }
+        let result = thread_pool
+            .get_toolchains()
+            .iter()
+            .try_for_each(|program| check_toolchain(program));
+        *cached = Some((Instant::now(), result.clone()));
+        result
+    }
+
+    fn check_workers(&self, thread_pool: &ThreadPool) -> Result<(), String> {
+        if thread_pool.alive_thread_count() == 0 {
+            return Err("no worker is alive".to_string());
+        }
+        let panics = thread_pool.panic_thread_count() as u64;
+        if panics >= self.config.max_panics {
+            return Err(format!("{panics} workers panicked"));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `<program> --version` exits successfully in time.
+fn check_toolchain(program: &str) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("can't run `{program}`: {e}"))?;
+
+    let deadline = Instant::now() + TOOLCHAIN_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("`{program} --version` failed: {status}")),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("`{program}` doesn't respond"));
+            }
+            Err(e) => return Err(format!("can't wait for `{program}`: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{check_toolchain, Check, HealthConfig, Readiness};
+    use crate::config::Config;
+    use crate::judge::{consts::CONFIG_PATH, sandbox::FakeSandbox};
+    use crate::thread_pool::thread_pool_builder::ThreadPoolBuilder;
+
+    #[test]
+    fn test_readiness() {
+        assert!(check_toolchain("true").is_ok());
+        assert!(check_toolchain("false").is_err());
+        assert!(check_toolchain("coj-no-such-compiler").is_err());
+
+        let readiness = Readiness::new(HealthConfig {
+            max_panics: 1,
+            toolchain_check_interval: 60,
+        });
+        let thread_pool = ThreadPoolBuilder::new(&Config::load(CONFIG_PATH))
+            .set_pool_size(2)
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .build();
+        let config_ok = |checks: &[Check]| {
+            checks
+                .iter()
+                .any(|check| check.name == "config" && check.ok)
+        };
+        assert!(!config_ok(&readiness.check(&thread_pool)));
+
+        readiness.mark_started();
+        let checks = readiness.check(&thread_pool);
+        let workers = checks.iter().find(|check| check.name == "workers").unwrap();
+        assert!(workers.ok, "{}", workers.info);
+        assert!(config_ok(&checks));
+        // idle workers spin until stopped
+        thread_pool.stop_all(true);
+    }
+}
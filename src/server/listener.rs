@@ -0,0 +1,210 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    net::TcpListener,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+
+use toml::{Table, Value};
+
+use crate::judge::consts::{DEFAULT_HOST, DEFAULT_PORT, DEFAULT_SHUTDOWN_TIMEOUT};
+
+/// Where the API listens, read from `[server]` and overridden by the CLI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerConfig {
+    pub host: String,
+    /// 0 picks a free port
+    pub port: u16,
+    /// HTTP workers, 0 for one per core
+    pub workers: usize,
+    /// also listens on this Unix domain socket, without TLS
+    pub unix_socket: Option<String>,
+    /// PEM certificate chain and private key, TLS is served when both are set
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    /// seconds open connections and started submissions get on shutdown
+    pub shutdown_timeout: u64,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> ListenerConfig {
+        ListenerConfig::from_config(&Table::new())
+    }
+}
+
+impl ListenerConfig {
+    pub fn from_config(data: &Table) -> ListenerConfig {
+        let server = match data.get("server") {
+            Some(Value::Table(server)) => server.clone(),
+            None => Table::new(),
+            _ => panic!("config: [server] should be set correctly"),
+        };
+        let get_str = |key: &str| match server.get(key) {
+            Some(Value::String(val)) if !val.is_empty() => Some(val.clone()),
+            Some(Value::String(_)) | None => None,
+            _ => panic!("config: [server] should be set correctly"),
+        };
+        let port = match server.get("port") {
+            Some(Value::Integer(val)) => match u16::try_from(*val) {
+                Ok(port) => port,
+                Err(_) => panic!("config: [server] should be set correctly"),
+            },
+            None => DEFAULT_PORT,
+            _ => panic!("config: [server] should be set correctly"),
+        };
+        let get_u64 = |key: &str, default: u64| match server.get(key) {
+            Some(Value::Integer(val)) if *val >= 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [server] should be set correctly"),
+        };
+
+        let config = ListenerConfig {
+            host: get_str("host").unwrap_or_else(|| DEFAULT_HOST.to_string()),
+            port,
+            workers: get_u64("workers", 0) as usize,
+            unix_socket: get_str("unix_socket"),
+            tls_cert: get_str("tls_cert"),
+            tls_key: get_str("tls_key"),
+            shutdown_timeout: get_u64("shutdown_timeout", DEFAULT_SHUTDOWN_TIMEOUT),
+        };
+        if config.tls_cert.is_some() != config.tls_key.is_some() {
+            panic!("config: [server] tls_cert and tls_key should be set together");
+        }
+        config
+    }
+}
+
+/// The bound sockets, ready to be served.
+pub struct Listeners {
+    pub tcp: TcpListener,
+    pub unix: Option<UnixListener>,
+    pub tls: Option<rustls::ServerConfig>,
+    pub workers: usize,
+    pub shutdown_timeout: u64,
+}
+
+impl Listeners {
+    pub fn bind(config: &ListenerConfig) -> io::Result<Listeners> {
+        let tls = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => Some(load_tls(cert, key)?),
+            _ => None,
+        };
+        let tcp = TcpListener::bind((config.host.as_str(), config.port))?;
+        let unix = match &config.unix_socket {
+            Some(path) => Some(bind_unix(path)?),
+            None => None,
+        };
+        Ok(Listeners {
+            tcp,
+            unix,
+            tls,
+            workers: config.workers,
+            shutdown_timeout: config.shutdown_timeout,
+        })
+    }
+
+    /// The port actually bound, which differs from the configured one for 0.
+    pub fn get_port(&self) -> u16 {
+        self.tcp.local_addr().unwrap().port()
+    }
+}
+
+/// A socket left behind by a previous run, which refuses connections, is
+/// replaced. One still served, e.g. by another judge, and anything else at
+/// `path` are errors.
+fn bind_unix(path: &str) -> io::Result<UnixListener> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("`{path}` exists and isn't a socket"),
+            ));
+        }
+        match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("`{path}` is served by another process"),
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    UnixListener::bind(path)
+}
+
+fn load_tls(cert_path: &str, key_path: &str) -> io::Result<rustls::ServerConfig> {
+    let invalid = |info: String| io::Error::new(io::ErrorKind::InvalidData, info);
+
+    let certs: Vec<rustls::Certificate> =
+        rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+    if certs.is_empty() {
+        return Err(invalid(format!("no certificate in `{cert_path}`")));
+    }
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(key_path)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid(format!("no private key in `{key_path}`")))?;
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(format!("TLS: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, os::unix::net::UnixStream};
+
+    use super::{ListenerConfig, Listeners};
+
+    #[test]
+    fn test_bind() {
+        let dir = "/tmp/coffee_oj_listener_test";
+        let _ = fs::remove_dir_all(dir);
+        let socket = format!("{dir}/judge.sock");
+        let mut config = ListenerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            workers: 1,
+            unix_socket: Some(socket.clone()),
+            tls_cert: None,
+            tls_key: None,
+            shutdown_timeout: 1,
+        };
+
+        let listeners = Listeners::bind(&config).unwrap();
+        assert_ne!(listeners.get_port(), 0);
+        assert!(UnixStream::connect(&socket).is_ok());
+        // a socket still served is left alone
+        let e = Listeners::bind(&config).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::AddrInUse);
+        assert!(UnixStream::connect(&socket).is_ok());
+        drop(listeners);
+        // the stale socket is replaced
+        assert!(Listeners::bind(&config).is_ok());
+
+        config.unix_socket = None;
+        config.tls_cert = Some(format!("{dir}/missing.pem"));
+        config.tls_key = Some(format!("{dir}/missing.key"));
+        assert!(Listeners::bind(&config).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
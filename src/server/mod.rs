@@ -1,4 +1,11 @@
+pub mod auth;
+pub mod error;
+pub mod frontend;
+pub mod health;
+pub mod listener;
 pub mod models;
+pub mod openapi;
+pub mod rate_limit;
 pub mod routes;
 pub mod startup;
 pub mod utils;
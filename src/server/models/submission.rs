@@ -1,4 +1,18 @@
+use std::{borrow::Cow, collections::BTreeMap, path::Path};
+
 use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+use utoipa::{
+    openapi::{schema::Type, ObjectBuilder, RefOr, Schema},
+    PartialSchema, ToSchema,
+};
+
+use crate::judge::{
+    consts::{DEFAULT_MAX_SOURCE_SIZE, LANG_EXTENSIONS},
+    file,
+    task::Task,
+    Verdict,
+};
 
 #[derive(Clone, Copy)]
 pub enum SubmissionStatus {
@@ -9,13 +23,197 @@ pub enum SubmissionStatus {
     TimeLimitExceeded,
     MemoLimitExceeded,
     UnknownError,
+    Pending,
+    Judging,
+    Cancelled,
+}
+
+impl From<Verdict> for SubmissionStatus {
+    fn from(verdict: Verdict) -> SubmissionStatus {
+        match verdict {
+            Verdict::Pending => SubmissionStatus::Pending,
+            Verdict::Judging => SubmissionStatus::Judging,
+            Verdict::Accepted => SubmissionStatus::Accepted,
+            Verdict::WrongAnswer => SubmissionStatus::WrongAnswer,
+            Verdict::CompilationError => SubmissionStatus::CompilationError,
+            Verdict::RuntimeError => SubmissionStatus::RuntimeError,
+            Verdict::TimeLimitExceeded => SubmissionStatus::TimeLimitExceeded,
+            Verdict::MemoLimitExceeded => SubmissionStatus::MemoLimitExceeded,
+            Verdict::SystemError => SubmissionStatus::UnknownError,
+            Verdict::Cancelled => SubmissionStatus::Cancelled,
+        }
+    }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// Answered as the number of the variant.
+impl PartialSchema for SubmissionStatus {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Integer)
+            .enum_values(Some(
+                SubmissionStatus::Accepted as i16..=SubmissionStatus::Cancelled as i16,
+            ))
+            .description(Some(
+                "0 Accepted, 1 WrongAnswer, 2 CompilationError, 3 RuntimeError, \
+                 4 TimeLimitExceeded, 5 MemoLimitExceeded, 6 UnknownError, 7 Pending, \
+                 8 Judging, 9 Cancelled",
+            ))
+            .into()
+    }
+}
+
+impl ToSchema for SubmissionStatus {}
+
+#[derive(Default, Debug, Serialize, Deserialize, ToSchema)]
 pub struct Submission {
     pub source: String,
+    #[schema(example = "cpp")]
     pub lang: String,
+    /// a problem directory, digits only
+    #[schema(example = "1")]
     pub problem_id: String,
+    /// overrides `[webhook] url` for this submission
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SubmissionError {
+    UnsupportedLanguage,
+    WrongProblemId,
+    InvalidCallbackUrl,
+    SourceTooLarge {
+        limit: u64,
+    },
+    ProblemNotFound(u64),
+    /// the problem exists but has no `.in`/`.out` pair
+    NoTestcases(u64),
+    LanguageNotAllowed(u64),
+}
+
+/// Bytes of source per language, `[max_source_size]` in the config.
+#[derive(Debug, Clone)]
+pub struct SourceLimits {
+    default: u64,
+    by_lang: BTreeMap<String, u64>,
+}
+
+impl Default for SourceLimits {
+    fn default() -> SourceLimits {
+        SourceLimits::from_config(&Table::new())
+    }
+}
+
+impl SourceLimits {
+    pub fn from_config(data: &Table) -> SourceLimits {
+        let mut limits = SourceLimits::new(DEFAULT_MAX_SOURCE_SIZE);
+        match data.get("max_source_size") {
+            Some(Value::Table(table)) => {
+                for (key, val) in table {
+                    let limit = match val {
+                        Value::Integer(val) if *val > 0 => *val as u64,
+                        _ => panic!("config: [max_source_size] should be set correctly"),
+                    };
+                    limits = match key.as_str() {
+                        "default" => SourceLimits {
+                            default: limit,
+                            ..limits
+                        },
+                        lang => limits.with_lang(lang, limit),
+                    };
+                }
+            }
+            None => {}
+            _ => panic!("config: [max_source_size] should be set correctly"),
+        }
+        limits
+    }
+}
+
+impl SourceLimits {
+    pub fn new(default: u64) -> SourceLimits {
+        SourceLimits {
+            default,
+            by_lang: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_lang(mut self, lang: &str, limit: u64) -> SourceLimits {
+        self.by_lang.insert(lang.to_string(), limit);
+        self
+    }
+
+    pub fn get(&self, lang: &str) -> u64 {
+        self.by_lang.get(lang).copied().unwrap_or(self.default)
+    }
+
+    /// Bytes of a request body holding a single source at most, even if
+    /// every byte of it is escaped as `\u00XX` in the JSON.
+    pub fn body_limit(&self) -> usize {
+        let largest = self.by_lang.values().copied().fold(self.default, u64::max);
+        largest as usize * 6 + 64 * 1024
+    }
+}
+
+/// Strips a byte order mark and turns CRLF line endings into LF.
+pub fn normalize_source(source: &str) -> Cow<'_, str> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    if source.contains("\r\n") {
+        Cow::Owned(source.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+impl Submission {
+    /// Checks the submission, returns its problem id.
+    pub fn validate(&self, limits: &SourceLimits) -> Result<u64, SubmissionError> {
+        if !LANG_EXTENSIONS.contains_key(self.lang.as_str()) {
+            return Err(SubmissionError::UnsupportedLanguage);
+        }
+        if let Some(callback_url) = &self.callback_url {
+            if !callback_url.starts_with("http://") && !callback_url.starts_with("https://") {
+                return Err(SubmissionError::InvalidCallbackUrl);
+            }
+        }
+        let limit = limits.get(&self.lang);
+        if normalize_source(&self.source).len() as u64 > limit {
+            return Err(SubmissionError::SourceTooLarge { limit });
+        }
+
+        let problem_id = self
+            .problem_id
+            .parse::<u64>()
+            .map_err(|_| SubmissionError::WrongProblemId)?;
+        let testcases_path = file::get_testcases_path(problem_id);
+        if !Path::new(&testcases_path).is_dir() {
+            return Err(SubmissionError::ProblemNotFound(problem_id));
+        }
+        if file::list_testcases(&testcases_path).is_empty() {
+            return Err(SubmissionError::NoTestcases(problem_id));
+        }
+        if let Some(languages) = file::get_allowed_languages(&testcases_path) {
+            if !languages.contains(&self.lang) {
+                return Err(SubmissionError::LanguageNotAllowed(problem_id));
+            }
+        }
+        Ok(problem_id)
+    }
+
+    /// Checks the submission and turns it into a task with a fresh id, its
+    /// source normalized.
+    pub fn to_task(&self, limits: &SourceLimits) -> Result<Task, SubmissionError> {
+        let problem_id = self.validate(limits)?;
+
+        // the source is saved into the submission's own workspace by the worker
+        let testcase_path = file::get_testcases_path(problem_id);
+        let source = normalize_source(&self.source);
+        let task = Task::new(problem_id, &testcase_path, &self.lang, &source);
+        Ok(match &self.callback_url {
+            Some(callback_url) => task.with_callback_url(callback_url),
+            None => task,
+        })
+    }
 }
 
 // impl Submission {
@@ -27,3 +225,59 @@ pub struct Submission {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_source, SourceLimits, Submission, SubmissionError};
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(normalize_source("\u{feff}a\r\nb\n"), "a\nb\n");
+
+        let limits = SourceLimits::new(16).with_lang("python", 4);
+        let submission = |lang: &str, problem_id: &str, source: &str| Submission {
+            source: source.to_string(),
+            lang: lang.to_string(),
+            problem_id: problem_id.to_string(),
+            callback_url: None,
+        };
+
+        assert_eq!(
+            submission("cpp", "1", "int main() {}").validate(&limits),
+            Ok(1)
+        );
+        // the BOM and carriage returns don't count
+        assert_eq!(
+            submission("python", "1", "\u{feff}1\r\n2\r\n").validate(&limits),
+            Ok(1)
+        );
+        assert_eq!(
+            submission("python", "1", "print(1)").validate(&limits),
+            Err(SubmissionError::SourceTooLarge { limit: 4 })
+        );
+        assert_eq!(
+            submission("cobol", "1", "").validate(&limits),
+            Err(SubmissionError::UnsupportedLanguage)
+        );
+        assert_eq!(
+            submission("cpp", "p1001", "").validate(&limits),
+            Err(SubmissionError::WrongProblemId)
+        );
+        assert_eq!(
+            submission("cpp", "404404404", "").validate(&limits),
+            Err(SubmissionError::ProblemNotFound(404404404))
+        );
+        let callback = |url: &str| Submission {
+            callback_url: Some(url.to_string()),
+            ..submission("cpp", "1", "int main() {}")
+        };
+        assert_eq!(
+            callback("https://example.com/hook").validate(&limits),
+            Ok(1)
+        );
+        assert_eq!(
+            callback("file:///etc/passwd").validate(&limits),
+            Err(SubmissionError::InvalidCallbackUrl)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use actix_web::{http::Method, web};
+use once_cell::sync::Lazy;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::server::routes::{api::*, *};
+
+/// Serves every handler listed at the path and method of its
+/// `#[utoipa::path]`, and documents it in [`ApiDoc`]: a handler without
+/// docs doesn't compile, and nothing is documented that isn't served.
+macro_rules! api_routes {
+    ($($handler:ident),* $(,)?) => {
+        /// The spec served at `GET /api/v1/openapi.json`, of every route but
+        /// the frontend.
+        #[derive(OpenApi)]
+        #[openapi(
+            info(
+                title = "CoffeeOJ judge",
+                description = "Judges submissions of the CoffeeOJ online judge.",
+                license(name = "GPL-3.0", identifier = "GPL-3.0-only")
+            ),
+            paths($($handler),*),
+            modifiers(&BearerToken),
+            security(("token" = [])),
+            tags(
+                (name = "submissions", description = "Submitting and following submissions"),
+                (name = "playground", description = "Running code without a problem"),
+                (name = "rejudge", description = "Judging finished submissions again, admins only"),
+                (name = "admin", description = "Admins only"),
+                (name = "probes", description = "Health checks and metrics, no token needed"),
+            )
+        )]
+        pub struct ApiDoc;
+
+        /// Registers the routes of [`ApiDoc`], for `App::configure`.
+        pub fn configure(cfg: &mut web::ServiceConfig) {
+            $(
+                let (path, method) = documented_at(stringify!($handler));
+                cfg.route(&path, web::method(method).to($handler));
+            )*
+        }
+    };
+}
+
+api_routes![
+    healthz,
+    readyz,
+    metrics,
+    openapi,
+    submit,
+    run,
+    status,
+    list_submissions,
+    submit_batch,
+    get_submission,
+    cancel_submission,
+    submission_events,
+    rejudge,
+    rejudge_submission,
+    rejudge_problem,
+    get_rejudge,
+    pool,
+];
+
+static OPENAPI: Lazy<utoipa::openapi::OpenApi> = Lazy::new(ApiDoc::openapi);
+
+/// The path and method of the operation `handler` is documented as, its id
+/// is the name of the handler.
+fn documented_at(handler: &str) -> (String, Method) {
+    for (path, item) in &OPENAPI.paths.paths {
+        let operations = [
+            (Method::GET, &item.get),
+            (Method::POST, &item.post),
+            (Method::PUT, &item.put),
+            (Method::PATCH, &item.patch),
+            (Method::DELETE, &item.delete),
+        ];
+        for (method, operation) in operations {
+            let Some(operation) = operation else {
+                continue;
+            };
+            if operation.operation_id.as_deref() == Some(handler) {
+                return (path.clone(), method);
+            }
+        }
+    }
+    unreachable!("`{handler}` is one of the paths of `ApiDoc`")
+}
+
+/// Rendered once, it doesn't change while running.
+pub static OPENAPI_JSON: Lazy<String> = Lazy::new(|| OPENAPI.to_json().unwrap());
+
+/// `Authorization: Bearer <token>`, needed when `[auth] enabled` is set.
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{Method, StatusCode},
+        test, web, App, HttpResponse,
+    };
+
+    use super::{configure, OPENAPI};
+
+    #[actix_web::test]
+    async fn test_documented_routes_are_served() {
+        let app = test::init_service(
+            App::new()
+                .configure(configure)
+                .default_service(web::to(HttpResponse::NotImplemented)),
+        )
+        .await;
+
+        let mut operations = 0;
+        for (path, item) in &OPENAPI.paths.paths {
+            let methods = [
+                (Method::GET, &item.get),
+                (Method::POST, &item.post),
+                (Method::DELETE, &item.delete),
+            ];
+            let path = path.replace("{id}", "1").replace("{problem_id}", "1");
+            for (method, _) in methods.into_iter().filter(|(_, op)| op.is_some()) {
+                let req = test::TestRequest::default()
+                    .method(method.clone())
+                    .uri(&path)
+                    .to_request();
+                // answered by its handler, whatever it makes of the request
+                let res = test::call_service(&app, req).await;
+                assert_ne!(
+                    res.status(),
+                    StatusCode::NOT_IMPLEMENTED,
+                    "{method} {path} isn't served"
+                );
+                operations += 1;
+            }
+        }
+        assert!(operations > 10);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    any::Any,
+    future::{ready, Ready},
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Extensions, Service, ServiceRequest, ServiceResponse, Transform},
+    rt::net::UnixStream,
+    Error, HttpMessage, HttpRequest, ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+
+use super::RateLimiter;
+use crate::server::auth::Principal;
+use crate::server::error::ApiError;
+use crate::server::utils::route::route_pattern;
+
+/// The process at the other end of a unix socket connection, which has no
+/// IP to tell clients apart by.
+#[derive(Debug, Clone, Copy)]
+pub struct UnixPeer {
+    uid: u32,
+    pid: Option<i32>,
+}
+
+impl UnixPeer {
+    /// For `HttpServer::on_connect`, puts the peer of unix socket
+    /// connections into their connection data.
+    pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+        let Some(stream) = conn.downcast_ref::<UnixStream>() else {
+            return;
+        };
+        match stream.peer_cred() {
+            Ok(cred) => {
+                data.insert(UnixPeer {
+                    uid: cred.uid(),
+                    pid: cred.pid(),
+                });
+            }
+            Err(e) => tracing::warn!("rate limit: can't tell the unix socket peer: {e}"),
+        }
+    }
+}
+
+/// The bucket of a request without a token, by the IP it came from or, over
+/// the unix socket, by the process.
+pub fn peer_client(req: &HttpRequest) -> String {
+    if let Some(addr) = req.peer_addr() {
+        return format!("ip:{}", addr.ip());
+    }
+    match req.conn_data::<UnixPeer>() {
+        Some(UnixPeer {
+            uid,
+            pid: Some(pid),
+        }) => format!("unix:{uid}:{pid}"),
+        Some(UnixPeer { uid, pid: None }) => format!("unix:{uid}"),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Whether requests to the route of `pattern` are throttled: all but the
+/// frontend, which is the default service, probes and metrics are.
+fn is_limited(pattern: Option<&str>) -> bool {
+    !matches!(pattern, None | Some("/healthz" | "/readyz" | "/metrics"))
+}
+
+/// Throttles API requests per API token, or per IP without one. Must be
+/// wrapped inside [`crate::server::auth::Authentication`] to see the token.
+pub struct RateLimiting {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimiting {
+    pub fn new(limiter: Arc<RateLimiter>) -> RateLimiting {
+        RateLimiting { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitingMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitingMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if self.limiter.is_enabled() && is_limited(route_pattern(&req).as_deref()) {
+            let client = match req.extensions().get::<Principal>() {
+                Some(principal) => format!("token:{}", principal.name),
+                None => peer_client(req.request()),
+            };
+            if let Err(retry_after) = self.limiter.check(&client) {
+                tracing::info!("rate limit: throttled `{client}`");
+                let res = req.into_response(ApiError::RateLimited { retry_after }.error_response());
+                return Box::pin(ready(Ok(res.map_into_right_body())));
+            }
+        }
+
+        let res = self.service.call(req);
+        Box::pin(async move { Ok(res.await?.map_into_left_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+    };
+
+    use std::sync::Arc;
+
+    use actix_web::{
+        http::StatusCode, rt::task, test, web, App, HttpRequest, HttpResponse, HttpServer,
+    };
+
+    use super::{peer_client, RateLimiting, UnixPeer};
+    use crate::server::rate_limit::{RateLimitConfig, RateLimiter};
+
+    #[actix_web::test]
+    async fn test_encoded_paths_are_limited() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            rate: 0.001,
+            burst: 1,
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiting::new(Arc::new(limiter)))
+                .route("/healthz", web::get().to(HttpResponse::Ok))
+                .route("/api/v1/submissions", web::get().to(HttpResponse::Ok))
+                .default_service(web::to(HttpResponse::NotFound)),
+        )
+        .await;
+
+        let mut statuses = vec![];
+        for path in [
+            "/api/v1/submissions",
+            "/%61pi/v1/submissions",
+            "/api/v1/%73ubmissions",
+            "/healthz",
+            "/",
+        ] {
+            let req = test::TestRequest::get()
+                .uri(path)
+                .peer_addr("10.0.0.1:40000".parse().unwrap())
+                .to_request();
+            statuses.push(test::call_service(&app, req).await.status());
+        }
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::OK,
+                StatusCode::NOT_FOUND,
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_unix_socket_peers_have_buckets_of_their_own() {
+        let path = std::env::temp_dir().join(format!("coj-peer-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/",
+                web::get().to(|req: HttpRequest| async move { peer_client(&req) }),
+            )
+        })
+        .on_connect(UnixPeer::on_connect)
+        .workers(1)
+        // as the judge binds, `bind_uds` skips `on_connect`
+        .listen_uds(UnixListener::bind(&path).unwrap())
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let socket = path.clone();
+        let response = task::spawn_blocking(move || {
+            let mut stream = UnixStream::connect(socket).unwrap();
+            stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap();
+        // which removes the socket
+        handle.stop(false).await;
+        assert!(!path.exists());
+
+        let uid = nix::unistd::getuid().as_raw();
+        let client = format!("unix:{uid}:{}", std::process::id());
+        assert!(response.ends_with(&client), "{response}");
+    }
+}
@@ -0,0 +1,436 @@
+pub mod middleware;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use toml::{Table, Value};
+
+use crate::judge::consts::{
+    DEFAULT_MAX_AUTH_FAILURES, DEFAULT_MAX_PENDING, DEFAULT_RATE_LIMIT_BURST,
+    DEFAULT_RATE_LIMIT_RATE,
+};
+use crate::metrics::METRICS;
+use crate::server::auth::Principal;
+use crate::server::error::ApiError;
+use crate::store::Store;
+
+pub use middleware::{peer_client, RateLimiting, UnixPeer};
+
+/// Clients whose buckets are kept, the least recently seen one is dropped
+/// for a new one.
+const MAX_BUCKETS: usize = 10000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// requests per second, on average
+    pub rate: f64,
+    /// requests at once after idling
+    pub burst: u64,
+    /// pending submissions per submitter, 0 for no cap
+    pub max_pending: u64,
+    /// invalid tokens per IP per minute, 0 for no cap; applies whenever
+    /// authentication is enabled, even if rate limiting isn't
+    pub max_auth_failures: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig::from_config(&Table::new())
+    }
+}
+
+impl RateLimitConfig {
+    pub fn from_config(data: &Table) -> RateLimitConfig {
+        let rate_limit = match data.get("rate_limit") {
+            Some(Value::Table(rate_limit)) => rate_limit.clone(),
+            None => Table::new(),
+            _ => panic!("config: [rate_limit] should be set correctly"),
+        };
+        let enabled = match rate_limit.get("enabled") {
+            Some(Value::Boolean(val)) => *val,
+            None => false,
+            _ => panic!("config: [rate_limit] should be set correctly"),
+        };
+        let rate = match rate_limit.get("rate") {
+            Some(Value::Float(val)) if *val > 0.0 => *val,
+            Some(Value::Integer(val)) if *val > 0 => *val as f64,
+            None => DEFAULT_RATE_LIMIT_RATE,
+            _ => panic!("config: [rate_limit] should be set correctly"),
+        };
+        let get_u64 = |key: &str, default: u64| match rate_limit.get(key) {
+            Some(Value::Integer(val)) if *val >= 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [rate_limit] should be set correctly"),
+        };
+
+        RateLimitConfig {
+            enabled,
+            rate,
+            burst: get_u64("burst", DEFAULT_RATE_LIMIT_BURST).max(1),
+            max_pending: get_u64("max_pending", DEFAULT_MAX_PENDING),
+            max_auth_failures: get_u64("max_auth_failures", DEFAULT_MAX_AUTH_FAILURES),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// its key in [`Clients::by_last_seen`]
+    seen: u64,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated_at = now;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Clients {
+    buckets: HashMap<String, TokenBucket>,
+    /// clients by when they were last seen, the least recently first
+    by_last_seen: BTreeMap<u64, String>,
+    /// counts every time a client is seen
+    seen: u64,
+}
+
+/// A token bucket per client, refilled at `rate` per second up to `burst`.
+/// Past `capacity` clients, the least recently seen one is forgotten.
+#[derive(Debug)]
+struct Buckets {
+    rate: f64,
+    burst: f64,
+    capacity: usize,
+    clients: Mutex<Clients>,
+}
+
+impl Buckets {
+    fn new(rate: f64, burst: u64) -> Buckets {
+        Buckets {
+            rate,
+            burst: burst as f64,
+            capacity: MAX_BUCKETS,
+            clients: Mutex::new(Clients::default()),
+        }
+    }
+
+    /// Whether `client` has a token, taken if `take`, or how long until
+    /// there's one.
+    fn check_at(&self, client: &str, now: Instant, take: bool) -> Result<(), Duration> {
+        let (rate, burst) = (self.rate, self.burst);
+        let mut clients = self.clients.lock().unwrap();
+        let Clients {
+            buckets,
+            by_last_seen,
+            seen,
+        } = &mut *clients;
+        if !buckets.contains_key(client) {
+            if !take {
+                return Ok(());
+            }
+            if buckets.len() >= self.capacity {
+                if let Some((_, oldest)) = by_last_seen.pop_first() {
+                    buckets.remove(&oldest);
+                }
+            }
+            buckets.insert(
+                client.to_string(),
+                TokenBucket {
+                    tokens: burst,
+                    updated_at: now,
+                    seen: 0,
+                },
+            );
+        }
+        let bucket = buckets.get_mut(client).unwrap();
+        *seen += 1;
+        by_last_seen.remove(&bucket.seen);
+        by_last_seen.insert(*seen, client.to_string());
+        bucket.seen = *seen;
+
+        bucket.refill(rate, burst, now);
+        if bucket.tokens >= 1.0 {
+            if take {
+                bucket.tokens -= 1.0;
+            }
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+}
+
+/// Token buckets of requests per client and of invalid tokens per IP, and
+/// the submissions let through the pending cap but maybe not stored yet.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    requests: Buckets,
+    auth_failures: Buckets,
+    /// by submitter, see [`RateLimiter::reserve_pending`]
+    reserved: Mutex<HashMap<String, u64>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        let max_auth_failures = config.max_auth_failures.max(1);
+        RateLimiter {
+            config,
+            requests: Buckets::new(config.rate, config.burst),
+            auth_failures: Buckets::new(max_auth_failures as f64 / 60.0, max_auth_failures),
+            reserved: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Takes a token of `client`, or tells how long until there's one.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        self.requests
+            .check_at(client, now, true)
+            .inspect_err(|_| METRICS.record_throttled("request"))
+    }
+
+    /// Whether the IP `client` may try a token, or how long until it may
+    /// again after `max_auth_failures` invalid ones. Checked before the
+    /// token, so that a locked out client can't tell a right guess.
+    pub fn check_auth(&self, client: &str) -> Result<(), Duration> {
+        self.check_auth_at(client, Instant::now())
+    }
+
+    fn check_auth_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if self.config.max_auth_failures == 0 {
+            return Ok(());
+        }
+        self.auth_failures
+            .check_at(client, now, false)
+            .inspect_err(|_| METRICS.record_throttled("auth"))
+    }
+
+    /// Counts an invalid token sent by the IP `client`.
+    pub fn record_auth_failure(&self, client: &str) {
+        self.record_auth_failure_at(client, Instant::now());
+    }
+
+    fn record_auth_failure_at(&self, client: &str, now: Instant) {
+        if self.config.max_auth_failures > 0 {
+            let _ = self.auth_failures.check_at(client, now, true);
+        }
+    }
+
+    /// Lets `count` more submissions of `submitter` through if they don't
+    /// exceed the pending cap, counting the `stored` unfinished ones and the
+    /// ones let through before that aren't stored yet. `stored` is read
+    /// under the lock of the latter, so concurrent requests can't both pass.
+    /// Always lets through when disabled.
+    pub fn reserve_pending<F>(
+        &self,
+        submitter: &str,
+        count: u64,
+        stored: F,
+    ) -> Result<PendingReservation<'_>, ApiError>
+    where
+        F: FnOnce() -> Result<u64, ApiError>,
+    {
+        let max_pending = self.config.max_pending;
+        if !self.config.enabled || max_pending == 0 {
+            return Ok(PendingReservation::none(self));
+        }
+        let mut reserved = self.reserved.lock().unwrap();
+        let pending = stored()? + reserved.get(submitter).copied().unwrap_or(0);
+        if pending + count > max_pending {
+            METRICS.record_throttled("submission");
+            return Err(ApiError::TooManyPending { max: max_pending });
+        }
+        *reserved.entry(submitter.to_string()).or_default() += count;
+        Ok(PendingReservation {
+            limiter: self,
+            submitter: Some(submitter.to_string()),
+            count,
+        })
+    }
+}
+
+/// Submissions let through by [`RateLimiter::reserve_pending`], to be held
+/// until they're stored. Counted twice for a moment after that, which errs
+/// on the side of the cap.
+pub struct PendingReservation<'a> {
+    limiter: &'a RateLimiter,
+    /// `None` when nothing is reserved
+    submitter: Option<String>,
+    count: u64,
+}
+
+impl PendingReservation<'_> {
+    fn none(limiter: &RateLimiter) -> PendingReservation<'_> {
+        PendingReservation {
+            limiter,
+            submitter: None,
+            count: 0,
+        }
+    }
+}
+
+impl Drop for PendingReservation<'_> {
+    fn drop(&mut self) {
+        let Some(submitter) = &self.submitter else {
+            return;
+        };
+        let mut reserved = self.limiter.reserved.lock().unwrap();
+        if let Some(left) = reserved.get_mut(submitter) {
+            *left -= self.count;
+            if *left == 0 {
+                reserved.remove(submitter);
+            }
+        }
+    }
+}
+
+/// Lets `count` more submissions of `principal` through the pending cap,
+/// see [`RateLimiter::reserve_pending`]. Without authentication there's no
+/// submitter to cap.
+pub fn reserve_pending<'a>(
+    limiter: &'a RateLimiter,
+    store: &Store,
+    principal: Option<&Principal>,
+    count: u64,
+) -> Result<PendingReservation<'a>, ApiError> {
+    let Some(principal) = principal else {
+        return Ok(PendingReservation::none(limiter));
+    };
+    limiter.reserve_pending(&principal.name, count, || {
+        Ok(store.count_unfinished(&principal.name)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{Buckets, RateLimitConfig, RateLimiter};
+    use crate::server::error::ApiError;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            rate: 2.0,
+            burst: 3,
+            max_pending: 2,
+            max_auth_failures: 2,
+        });
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("alice", now).is_ok());
+        }
+        let retry_after = limiter.check_at("alice", now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+        // others have their own bucket
+        assert!(limiter.check_at("bob", now).is_ok());
+        assert!(limiter
+            .check_at("alice", now + Duration::from_millis(500))
+            .is_ok());
+
+        // two invalid tokens a minute, whatever the requests rate
+        assert!(limiter.check_auth_at("ip:10.0.0.1", now).is_ok());
+        limiter.record_auth_failure_at("ip:10.0.0.1", now);
+        limiter.record_auth_failure_at("ip:10.0.0.1", now);
+        let retry_after = limiter.check_auth_at("ip:10.0.0.1", now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(30));
+        assert!(limiter.check_auth_at("ip:10.0.0.2", now).is_ok());
+        assert!(limiter
+            .check_auth_at("ip:10.0.0.1", now + Duration::from_secs(30))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_least_recently_seen_is_forgotten() {
+        let buckets = Buckets {
+            capacity: 2,
+            ..Buckets::new(1.0, 1)
+        };
+        let now = Instant::now();
+        assert!(buckets.check_at("alice", now, true).is_ok());
+        assert!(buckets.check_at("bob", now, true).is_ok());
+        // alice is seen again, bob is forgotten for carol
+        assert!(buckets.check_at("alice", now, true).is_err());
+        assert!(buckets.check_at("carol", now, true).is_ok());
+
+        assert!(buckets.check_at("alice", now, true).is_err());
+        assert!(buckets.check_at("bob", now, true).is_ok());
+        assert_eq!(buckets.clients.lock().unwrap().buckets.len(), 2);
+    }
+
+    #[test]
+    fn test_pending_reservations() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            max_pending: 3,
+            ..Default::default()
+        });
+        let stored = || Ok(1);
+
+        let first = limiter.reserve_pending("alice", 1, stored).unwrap();
+        // one stored and one on its way
+        assert!(matches!(
+            limiter.reserve_pending("alice", 2, stored),
+            Err(ApiError::TooManyPending { max: 3 })
+        ));
+        assert!(limiter.reserve_pending("bob", 2, stored).is_ok());
+        drop(first);
+        assert!(limiter.reserve_pending("alice", 2, stored).is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_reservations_keep_under_the_cap() {
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            max_pending: 4,
+            ..Default::default()
+        }));
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (limiter, barrier) = (limiter.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    let reservation = limiter.reserve_pending("alice", 1, || Ok(0));
+                    let admitted = reservation.is_ok();
+                    // held until every thread has tried
+                    barrier.wait();
+                    admitted
+                })
+            })
+            .collect();
+        let admitted = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|admitted| *admitted)
+            .count();
+        assert_eq!(admitted, 4);
+    }
+}
@@ -0,0 +1,50 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::server::error::ApiError;
+use crate::thread_pool::ThreadPool;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct RunningRet {
+    worker: usize,
+    submission_id: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct PoolRet {
+    max_thread_count: usize,
+    alive_thread_count: usize,
+    active_thread_count: usize,
+    queued_job_count: usize,
+    queued_submission_count: usize,
+    panic_thread_count: usize,
+    running: Vec<RunningRet>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/pool",
+    tag = "admin",
+    responses((status = 200, description = "Workers and queues", body = PoolRet))
+)]
+#[tracing::instrument(name = "Inspect pool", skip(thread_pool))]
+pub async fn pool(thread_pool: web::Data<ThreadPool>) -> Result<HttpResponse, ApiError> {
+    let ret = PoolRet {
+        max_thread_count: thread_pool.max_thread_count(),
+        alive_thread_count: thread_pool.alive_thread_count(),
+        active_thread_count: thread_pool.active_thread_count(),
+        queued_job_count: thread_pool.queued_job_count(),
+        queued_submission_count: thread_pool.queued_task_count(),
+        panic_thread_count: thread_pool.panic_thread_count(),
+        running: thread_pool
+            .running_submissions()
+            .into_iter()
+            .map(|(worker, submission_id)| RunningRet {
+                worker,
+                submission_id,
+            })
+            .collect(),
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
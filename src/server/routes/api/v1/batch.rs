@@ -0,0 +1,197 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::server::auth::Principal;
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::server::models::{self, SourceLimits};
+use crate::server::rate_limit::{self, RateLimiter};
+use crate::server::utils::body;
+use crate::store::Store;
+use crate::thread_pool::{queue::Priority, ThreadPool};
+
+/// Submissions per batch at most.
+pub const MAX_BATCH_SIZE: usize = 1000;
+/// Bytes of a batch request body at most.
+pub const MAX_BATCH_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    #[schema(max_items = 1000)]
+    submissions: Vec<models::Submission>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct BatchRet {
+    /// in the order of the request
+    submission_ids: Vec<u64>,
+}
+
+/// Queues many submissions at a lower weight than live ones, either all of
+/// them or, if any is invalid, none with the errors of the invalid ones as
+/// `details`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/submissions/batch",
+    tag = "submissions",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "All of them queued", body = BatchRet),
+        (status = 400, description = "Invalid submissions or batch size", body = ApiErrorRet),
+        (status = 413, description = "Body too large", body = ApiErrorRet),
+        (status = 429, description = "Rate limited", body = ApiErrorRet),
+        (status = 503, description = "Queue full or shutting down", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(
+    name = "Submit batch",
+    skip(payload, thread_pool, store, limiter, source_limits, principal),
+    fields(size)
+)]
+pub async fn submit_batch(
+    payload: web::Payload,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+    limiter: web::Data<RateLimiter>,
+    source_limits: web::Data<SourceLimits>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let form: BatchRequest = body::read_json(payload, MAX_BATCH_BODY_SIZE).await?;
+    let submissions = &form.submissions;
+    tracing::Span::current().record("size", submissions.len());
+    if submissions.is_empty() || submissions.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::InvalidBatchSize {
+            max: MAX_BATCH_SIZE,
+        });
+    }
+
+    let mut tasks = Vec::with_capacity(submissions.len());
+    let mut errors = vec![];
+    for (index, submission) in submissions.iter().enumerate() {
+        match submission.to_task(&source_limits) {
+            Ok(task) => tasks.push(match &principal {
+                Some(principal) => task.with_owner(&principal.name),
+                None => task,
+            }),
+            Err(e) => errors.push((
+                index,
+                ApiError::from_submission_error(e, &submission.lang, &submission.problem_id),
+            )),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::InvalidBatch(errors));
+    }
+    // held until the batch is stored
+    let _reservation =
+        rate_limit::reserve_pending(&limiter, &store, principal.as_deref(), tasks.len() as u64)?;
+
+    let submission_ids = thread_pool.enqueue_tasks_with_priority(Priority::Batch, tasks)?;
+    Ok(HttpResponse::Ok().json(BatchRet { submission_ids }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde_json::{json, Value};
+
+    use crate::config::Config;
+    use crate::judge::{consts::CONFIG_PATH, sandbox::FakeSandbox};
+    use crate::server::{models::SourceLimits, rate_limit::RateLimiter};
+    use crate::store::{Store, SubmissionFilter};
+    use crate::thread_pool::{queue::Priority, thread_pool_builder::ThreadPoolBuilder};
+
+    use super::submit_batch;
+
+    /// Status and body of a batch of `submissions`, given a queue of
+    /// `max_queued` that's never worked on.
+    async fn post_batch(
+        store: &Arc<Store>,
+        max_queued: u64,
+        submissions: Value,
+    ) -> (StatusCode, Value) {
+        let thread_pool = ThreadPoolBuilder::new(&Config::load(CONFIG_PATH))
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(store.clone())
+            .set_max_queued(max_queued)
+            .build();
+        let thread_pool = web::Data::new(thread_pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(thread_pool.clone())
+                .app_data(web::Data::from(store.clone()))
+                .app_data(web::Data::new(RateLimiter::default()))
+                .app_data(web::Data::new(SourceLimits::default()))
+                .route("/api/v1/submissions/batch", web::post().to(submit_batch)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/submissions/batch")
+            .set_json(json!({ "submissions": submissions }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        // idle workers spin until they're stopped
+        thread_pool.stop_all(true);
+        (status, test::read_body_json(res).await)
+    }
+
+    fn submission(lang: &str) -> Value {
+        json!({ "problem_id": "1", "lang": lang, "source": "int main() {}" })
+    }
+
+    fn stored(store: &Store) -> Vec<(u64, Priority)> {
+        let records = store
+            .list_submissions(&SubmissionFilter::default())
+            .unwrap();
+        records
+            .iter()
+            .map(|record| (record.id, record.priority))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_batch_is_queued() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let (status, body) =
+            post_batch(&store, 0, json!([submission("cpp"), submission("python")])).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["submission_ids"], json!([1, 2]));
+        assert_eq!(
+            stored(&store),
+            vec![(2, Priority::Batch), (1, Priority::Batch)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_batch_queues_none() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let batch = json!([submission("cpp"), submission("cobol"), submission("cpp")]);
+        let (status, body) = post_batch(&store, 0, batch).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_batch");
+        assert_eq!(body["details"][0]["index"], 1);
+        assert_eq!(body["details"][0]["code"], "unsupported_language");
+        assert_eq!(body["details"].as_array().unwrap().len(), 1);
+        assert!(stored(&store).is_empty());
+
+        let (status, body) = post_batch(&store, 0, json!([])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_batch_size");
+    }
+
+    #[actix_web::test]
+    async fn test_batch_over_the_queue_queues_none() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let (status, body) =
+            post_batch(&store, 1, json!([submission("cpp"), submission("cpp")])).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "queue_full");
+        assert!(stored(&store).is_empty());
+    }
+}
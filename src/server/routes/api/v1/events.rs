@@ -0,0 +1,129 @@
+use actix_web::{web, HttpResponse};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::judge::{events::JudgeEvent, Verdict};
+use crate::server::auth::{self, Principal};
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::store::Store;
+use crate::thread_pool::ThreadPool;
+
+/// The `data` of a server-sent event, its name is the `event` field.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum EventRet {
+    Queued,
+    Compiling,
+    Compiled {
+        success: bool,
+        log: String,
+    },
+    Testcase {
+        testcase: String,
+        verdict: String,
+        time_cost: u64,
+        mem_cost: u64,
+        checked: usize,
+        total: usize,
+    },
+    Finished {
+        verdict: String,
+    },
+    /// The judge shut down before a verdict, it's judged again after the
+    /// restart
+    Requeued,
+}
+
+impl From<JudgeEvent> for EventRet {
+    fn from(event: JudgeEvent) -> EventRet {
+        match event {
+            JudgeEvent::Queued => EventRet::Queued,
+            JudgeEvent::Compiling => EventRet::Compiling,
+            JudgeEvent::Compiled { success, log } => EventRet::Compiled { success, log },
+            JudgeEvent::TestcaseFinished {
+                testcase,
+                verdict,
+                time_cost,
+                mem_cost,
+                checked,
+                total,
+            } => EventRet::Testcase {
+                testcase,
+                verdict: verdict.as_str().to_string(),
+                time_cost,
+                mem_cost,
+                checked,
+                total,
+            },
+            JudgeEvent::Finished { verdict } => EventRet::Finished {
+                verdict: verdict.as_str().to_string(),
+            },
+            JudgeEvent::Requeued => EventRet::Requeued,
+        }
+    }
+}
+
+fn to_sse(event: JudgeEvent) -> web::Bytes {
+    let ret = EventRet::from(event);
+    let data = serde_json::to_value(&ret).unwrap_or_default();
+    let name = data["event"].as_str().unwrap_or_default().to_string();
+    web::Bytes::from(format!("event: {name}\ndata: {data}\n\n"))
+}
+
+/// Streams the progress of a submission as server-sent events, from the
+/// events so far to `finished`, or `requeued` on shutdown, after which the
+/// stream ends.
+#[utoipa::path(
+    get,
+    path = "/api/v1/submissions/{id}/events",
+    tag = "submissions",
+    params(("id" = u64, Path, description = "Submission ID")),
+    responses(
+        (
+            status = 200,
+            description = "Server-sent events, each `data` is one of these",
+            body = EventRet,
+            content_type = "text/event-stream"
+        ),
+        (status = 403, description = "Someone else's submission", body = ApiErrorRet),
+        (status = 404, description = "No such submission", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Stream submission events", skip(thread_pool, store, principal))]
+pub async fn submission_events(
+    path: web::Path<u64>,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let submission_id = path.into_inner();
+
+    // subscribed first, so that nothing happens unseen in between
+    let subscription = thread_pool.events().subscribe(submission_id);
+    let record = store
+        .get_submission(submission_id)?
+        .ok_or(ApiError::SubmissionNotFound(submission_id))?;
+    auth::check_can_read(principal.as_deref(), &record)?;
+
+    let events = match subscription {
+        Some((history, receiver)) => {
+            let live = stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|event| (event, receiver))
+            });
+            stream::iter(history).chain(live).boxed()
+        }
+        // nothing to wait for, it's done or not judged by this process
+        None if record.status.is_final() => stream::iter(vec![JudgeEvent::Finished {
+            verdict: record.status,
+        }])
+        .boxed(),
+        None if record.status == Verdict::Pending => stream::iter(vec![JudgeEvent::Queued]).boxed(),
+        None => stream::empty().boxed(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events.map(|event| Ok::<_, actix_web::Error>(to_sse(event)))))
+}
@@ -1,2 +1,18 @@
+pub mod admin;
+pub mod batch;
+pub mod events;
+pub mod openapi;
+pub mod rejudge;
+pub mod run;
+pub mod status;
+pub mod submissions;
 pub mod submit;
+pub use admin::*;
+pub use batch::*;
+pub use events::*;
+pub use openapi::*;
+pub use rejudge::*;
+pub use run::*;
+pub use status::*;
+pub use submissions::*;
 pub use submit::*;
@@ -0,0 +1,17 @@
+use actix_web::HttpResponse;
+
+use crate::server::openapi::OPENAPI_JSON;
+
+/// This spec.
+#[utoipa::path(
+    get,
+    path = "/api/v1/openapi.json",
+    tag = "probes",
+    security(()),
+    responses((status = 200, description = "OpenAPI 3.1", content_type = "application/json"))
+)]
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(OPENAPI_JSON.as_str())
+}
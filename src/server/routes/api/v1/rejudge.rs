@@ -0,0 +1,193 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::judge::Verdict;
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::store::{RejudgeItem, Store, SubmissionFilter, SubmissionRecord};
+use crate::thread_pool::ThreadPool;
+
+/// Selects the submissions of a bulk rejudge, `status` is a verdict name
+/// like `Accepted` and `since` is a Unix timestamp.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RejudgeRequest {
+    problem_id: Option<u64>,
+    lang: Option<String>,
+    status: Option<String>,
+    since: Option<u64>,
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct RejudgeRet {
+    rejudge_id: u64,
+    queued: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct RejudgeItemRet {
+    submission_id: u64,
+    problem_id: u64,
+    old_verdict: String,
+    /// `None` until the submission is judged again
+    new_verdict: Option<String>,
+}
+
+impl From<&RejudgeItem> for RejudgeItemRet {
+    fn from(item: &RejudgeItem) -> RejudgeItemRet {
+        RejudgeItemRet {
+            submission_id: item.submission_id,
+            problem_id: item.problem_id,
+            old_verdict: item.old_status.as_str().to_string(),
+            new_verdict: item.new_status.map(|status| status.as_str().to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct RejudgeReportRet {
+    rejudge_id: u64,
+    reason: String,
+    created_at: u64,
+    total: usize,
+    finished: bool,
+    /// only the submissions whose verdict changed
+    changed: Vec<RejudgeItemRet>,
+}
+
+fn rejudge_records(
+    thread_pool: &ThreadPool,
+    reason: &str,
+    records: Vec<SubmissionRecord>,
+) -> Result<HttpResponse, ApiError> {
+    let (rejudge_id, queued) = thread_pool.rejudge(reason, records)?;
+    Ok(HttpResponse::Accepted().json(RejudgeRet { rejudge_id, queued }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/rejudge/submissions/{id}",
+    tag = "rejudge",
+    params(("id" = u64, Path, description = "Submission ID")),
+    responses(
+        (status = 202, description = "Queued again", body = RejudgeRet),
+        (status = 404, description = "No such submission", body = ApiErrorRet),
+        (status = 409, description = "Still judging", body = ApiErrorRet),
+        (status = 503, description = "Shutting down", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Rejudge submission", skip(thread_pool, store))]
+pub async fn rejudge_submission(
+    path: web::Path<u64>,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, ApiError> {
+    let submission_id = path.into_inner();
+
+    let record = store
+        .get_submission(submission_id)?
+        .ok_or(ApiError::SubmissionNotFound(submission_id))?;
+
+    let (rejudge_id, queued) = thread_pool.rejudge("", vec![record])?;
+    // already on its way, a second judging would race the first one
+    if queued == 0 {
+        return Err(ApiError::StillJudging(submission_id));
+    }
+    Ok(HttpResponse::Accepted().json(RejudgeRet { rejudge_id, queued }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/rejudge/problems/{problem_id}",
+    tag = "rejudge",
+    params(("problem_id" = u64, Path, description = "Problem ID")),
+    responses(
+        (status = 202, description = "Finished submissions queued again", body = RejudgeRet),
+        (status = 503, description = "Shutting down", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Rejudge problem", skip(thread_pool, store))]
+pub async fn rejudge_problem(
+    path: web::Path<u64>,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, ApiError> {
+    let filter = SubmissionFilter {
+        problem_id: Some(path.into_inner()),
+        ..Default::default()
+    };
+    let records = store.find_submissions(&filter)?;
+    rejudge_records(&thread_pool, "", records)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/rejudge",
+    tag = "rejudge",
+    request_body = RejudgeRequest,
+    responses(
+        (status = 202, description = "Finished submissions queued again", body = RejudgeRet),
+        (status = 400, description = "Invalid filter", body = ApiErrorRet),
+        (status = 503, description = "Shutting down", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Rejudge submissions", skip(thread_pool, store))]
+pub async fn rejudge(
+    form: web::Json<RejudgeRequest>,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, ApiError> {
+    let form = form.into_inner();
+    let status = match form.status.as_deref() {
+        Some(status) => {
+            Some(Verdict::parse(status).ok_or(ApiError::InvalidVerdict(status.to_string()))?)
+        }
+        None => None,
+    };
+    let filter = SubmissionFilter {
+        problem_id: form.problem_id,
+        lang: form.lang,
+        status,
+        since: form.since,
+        ..Default::default()
+    };
+
+    let records = store.find_submissions(&filter)?;
+    rejudge_records(&thread_pool, &form.reason, records)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/rejudges/{id}",
+    tag = "rejudge",
+    params(("id" = u64, Path, description = "Rejudge ID")),
+    responses(
+        (status = 200, description = "Progress and changed verdicts", body = RejudgeReportRet),
+        (status = 404, description = "No such rejudge", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Get rejudge report", skip(store))]
+pub async fn get_rejudge(
+    path: web::Path<u64>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, ApiError> {
+    let rejudge_id = path.into_inner();
+    let report = store
+        .get_rejudge_report(rejudge_id)?
+        .ok_or(ApiError::RejudgeNotFound(rejudge_id))?;
+
+    let ret = RejudgeReportRet {
+        rejudge_id: report.id,
+        total: report.items.len(),
+        finished: report.is_finished(),
+        changed: report
+            .get_changed()
+            .into_iter()
+            .map(RejudgeItemRet::from)
+            .collect(),
+        reason: report.reason,
+        created_at: report.created_at,
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
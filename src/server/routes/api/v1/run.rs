@@ -0,0 +1,100 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+use crate::judge::playground::{self, RunStatus};
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::thread_pool::ThreadPool;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RunRequest {
+    source: String,
+    lang: String,
+    #[serde(default)]
+    stdin: String,
+}
+
+/// `status` is `Exited`, `Signaled`, `TimeLimitExceeded`, `MemoLimitExceeded`
+/// or `CompilationError`.
+#[derive(Default, Serialize, Deserialize, ToSchema)]
+struct RunRet {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+    stdout: String,
+    stderr: String,
+    truncated: bool,
+    time_cost: u64,
+    mem_cost: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compile_log: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/run",
+    tag = "playground",
+    request_body = RunRequest,
+    responses(
+        (status = 200, description = "Ran or failed to compile", body = RunRet),
+        (status = 400, description = "Unsupported language", body = ApiErrorRet),
+        (status = 503, description = "Too many runs queued, or shutting down", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(
+    name = "Run code",
+    skip(form, thread_pool),
+    fields(lang = %form.lang)
+)]
+pub async fn run(
+    form: web::Json<RunRequest>,
+    thread_pool: web::Data<ThreadPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (sender, receiver) = oneshot::channel();
+    thread_pool.run_playground(&form.lang, &form.source, &form.stdin, move |output| {
+        let _ = sender.send(output);
+    })?;
+
+    let output = match receiver.await {
+        Ok(Ok(output)) => output,
+        Ok(Err(playground::Error::LanguageNotFoundError)) => {
+            return Err(ApiError::UnsupportedLanguage(form.lang.clone()))
+        }
+        Ok(Err(playground::Error::CompilationError(log))) => {
+            let ret = RunRet {
+                status: "CompilationError".to_string(),
+                compile_log: Some(log),
+                ..Default::default()
+            };
+            return Ok(HttpResponse::Ok().json(ret));
+        }
+        Ok(Err(e)) => {
+            tracing::error!("playground: {e:?}");
+            return Err(ApiError::Internal);
+        }
+        // dropped unanswered: skipped by a shutdown, or the worker panicked
+        Err(_) if thread_pool.is_shutting_down() => return Err(ApiError::ShuttingDown),
+        Err(_) => return Err(ApiError::Internal),
+    };
+
+    let (exit_code, signal) = match output.status {
+        RunStatus::Exited(code) => (Some(code), None),
+        RunStatus::Signaled(signal) => (None, Some(signal)),
+        _ => (None, None),
+    };
+    let ret = RunRet {
+        status: output.status.as_str().to_string(),
+        exit_code,
+        signal,
+        stdout: output.stdout,
+        stderr: output.stderr,
+        truncated: output.truncated,
+        time_cost: output.time_cost,
+        mem_cost: output.mem_cost,
+        compile_log: None,
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
@@ -0,0 +1,89 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::judge::runner::TestcaseResult;
+use crate::server::auth::{self, Principal};
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::server::models::SubmissionStatus;
+use crate::store::Store;
+
+type SubmissionStatusCode = i16;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(super) struct TestcaseRet {
+    testcase: String,
+    #[schema(example = "Accepted")]
+    verdict: String,
+    time_cost: u64,
+    mem_cost: u64,
+}
+
+impl From<TestcaseResult> for TestcaseRet {
+    fn from(result: TestcaseResult) -> TestcaseRet {
+        TestcaseRet {
+            testcase: result.testcase,
+            verdict: result.verdict.as_str().to_string(),
+            time_cost: result.time_cost,
+            mem_cost: result.mem_cost,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct StatusRet {
+    submission_id: u64,
+    problem_id: u64,
+    lang: String,
+    #[schema(value_type = SubmissionStatus)]
+    status: SubmissionStatusCode,
+    #[schema(example = "Accepted")]
+    verdict: String,
+    info: String,
+    time_cost: u64,
+    mem_cost: u64,
+    created_at: u64,
+    finished_at: Option<u64>,
+    testcases: Vec<TestcaseRet>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/status/{id}",
+    tag = "submissions",
+    params(("id" = u64, Path, description = "Submission ID")),
+    responses(
+        (status = 200, description = "The submission and its testcases", body = StatusRet),
+        (status = 403, description = "Someone else's submission", body = ApiErrorRet),
+        (status = 404, description = "No such submission", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Query status", skip(store, principal))]
+pub async fn status(
+    path: web::Path<u64>,
+    store: web::Data<Store>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let submission_id = path.into_inner();
+
+    let record = store
+        .get_submission(submission_id)?
+        .ok_or(ApiError::SubmissionNotFound(submission_id))?;
+    auth::check_can_read(principal.as_deref(), &record)?;
+    let results = store.get_testcase_results(submission_id)?;
+
+    let ret = StatusRet {
+        submission_id: record.id,
+        problem_id: record.problem_id,
+        lang: record.lang,
+        status: SubmissionStatus::from(record.status) as SubmissionStatusCode,
+        verdict: record.status.as_str().to_string(),
+        info: record.info,
+        time_cost: record.time_cost,
+        mem_cost: record.mem_cost,
+        created_at: record.created_at,
+        finished_at: record.finished_at,
+        testcases: results.into_iter().map(TestcaseRet::from).collect(),
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
@@ -0,0 +1,229 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::judge::Verdict;
+use crate::server::auth::{self, Principal};
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::server::models::SubmissionStatus;
+use crate::store::{Store, SubmissionFilter, SubmissionRecord, MAX_PAGE_SIZE};
+use crate::thread_pool::ThreadPool;
+
+use super::status::TestcaseRet;
+
+type SubmissionStatusCode = i16;
+
+/// `status` is a verdict name like `WrongAnswer`, `since` is a Unix timestamp
+/// and `cursor` is the `next_cursor` of the previous page.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmissionQuery {
+    problem_id: Option<u64>,
+    lang: Option<String>,
+    status: Option<String>,
+    since: Option<u64>,
+    #[param(maximum = 100)]
+    limit: Option<usize>,
+    cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct SubmissionSummary {
+    submission_id: u64,
+    problem_id: u64,
+    lang: String,
+    #[schema(value_type = SubmissionStatus)]
+    status: SubmissionStatusCode,
+    #[schema(example = "Accepted")]
+    verdict: String,
+    time_cost: u64,
+    mem_cost: u64,
+    created_at: u64,
+}
+
+impl From<SubmissionRecord> for SubmissionSummary {
+    fn from(record: SubmissionRecord) -> SubmissionSummary {
+        SubmissionSummary {
+            submission_id: record.id,
+            problem_id: record.problem_id,
+            lang: record.lang,
+            status: SubmissionStatus::from(record.status) as SubmissionStatusCode,
+            verdict: record.status.as_str().to_string(),
+            time_cost: record.time_cost,
+            mem_cost: record.mem_cost,
+            created_at: record.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct SubmissionListRet {
+    submissions: Vec<SubmissionSummary>,
+    /// `None` on the last page
+    next_cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct SubmissionDetailRet {
+    #[serde(flatten)]
+    summary: SubmissionSummary,
+    source: String,
+    info: String,
+    compile_log: String,
+    finished_at: Option<u64>,
+    testcases: Vec<TestcaseRet>,
+}
+
+/// Submitters only see their own submissions.
+#[utoipa::path(
+    get,
+    path = "/api/v1/submissions",
+    tag = "submissions",
+    params(SubmissionQuery),
+    responses(
+        (
+            status = 200,
+            description = "A page of submissions, newest first",
+            body = SubmissionListRet
+        ),
+        (status = 400, description = "Invalid filter", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "List submissions", skip(store, principal))]
+pub async fn list_submissions(
+    query: web::Query<SubmissionQuery>,
+    store: web::Data<Store>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let status = match query.status.as_deref() {
+        Some(status) => {
+            Some(Verdict::parse(status).ok_or(ApiError::InvalidVerdict(status.to_string()))?)
+        }
+        None => None,
+    };
+    let filter = SubmissionFilter {
+        problem_id: query.problem_id,
+        lang: query.lang,
+        status,
+        since: query.since,
+        owner: auth::owner_filter(principal.as_deref()),
+        cursor: query.cursor,
+        limit: query
+            .limit
+            .unwrap_or(SubmissionFilter::default().limit)
+            .clamp(1, MAX_PAGE_SIZE),
+    };
+
+    let records = store.list_submissions(&filter)?;
+    // a short page is the last one
+    let next_cursor = match records.last() {
+        Some(last) if records.len() >= filter.limit => Some(last.id),
+        _ => None,
+    };
+
+    let ret = SubmissionListRet {
+        submissions: records.into_iter().map(SubmissionSummary::from).collect(),
+        next_cursor,
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/submissions/{id}",
+    tag = "submissions",
+    params(("id" = u64, Path, description = "Submission ID")),
+    responses(
+        (
+            status = 200,
+            description = "The submission with its source and testcases",
+            body = SubmissionDetailRet
+        ),
+        (status = 403, description = "Someone else's submission", body = ApiErrorRet),
+        (status = 404, description = "No such submission", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Get submission", skip(store, principal))]
+pub async fn get_submission(
+    path: web::Path<u64>,
+    store: web::Data<Store>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let submission_id = path.into_inner();
+
+    let record = store
+        .get_submission(submission_id)?
+        .ok_or(ApiError::SubmissionNotFound(submission_id))?;
+    auth::check_can_read(principal.as_deref(), &record)?;
+    let results = store.get_testcase_results(submission_id)?;
+
+    let ret = SubmissionDetailRet {
+        source: record.source.clone(),
+        info: record.info.clone(),
+        compile_log: record.compile_log.clone(),
+        finished_at: record.finished_at,
+        summary: SubmissionSummary::from(record),
+        testcases: results.into_iter().map(TestcaseRet::from).collect(),
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct CancelRet {
+    submission_id: u64,
+    info: String,
+}
+
+/// Cancels a submission that's queued or being judged, `already_finished`
+/// once it has a final verdict.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/submissions/{id}",
+    tag = "submissions",
+    params(("id" = u64, Path, description = "Submission ID")),
+    responses(
+        (status = 200, description = "Cancelled, it wasn't queued", body = CancelRet),
+        (status = 202, description = "Cancelling, the verdict follows", body = CancelRet),
+        (status = 403, description = "Someone else's submission", body = ApiErrorRet),
+        (status = 404, description = "No such submission", body = ApiErrorRet),
+        (status = 409, description = "Already finished", body = ApiErrorRet),
+    )
+)]
+#[tracing::instrument(name = "Cancel submission", skip(thread_pool, store, principal))]
+pub async fn cancel_submission(
+    path: web::Path<u64>,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let submission_id = path.into_inner();
+
+    let record = store
+        .get_submission(submission_id)?
+        .ok_or(ApiError::SubmissionNotFound(submission_id))?;
+    auth::check_can_read(principal.as_deref(), &record)?;
+    if record.status.is_final() {
+        return Err(ApiError::AlreadyFinished(submission_id));
+    }
+
+    // the worker records the verdict once whatever is running is killed
+    if thread_pool.cancel(submission_id) {
+        let ret = CancelRet {
+            submission_id,
+            info: "Cancelling".to_string(),
+        };
+        return Ok(HttpResponse::Accepted().json(ret));
+    }
+
+    // not in the queue, e.g. left behind by a judge that gave up on recovery,
+    // or it got its verdict in the meantime
+    if !store.cancel_unfinished(submission_id)? {
+        return Err(ApiError::AlreadyFinished(submission_id));
+    }
+    let ret = CancelRet {
+        submission_id,
+        info: "Cancelled".to_string(),
+    };
+    Ok(HttpResponse::Ok().json(ret))
+}
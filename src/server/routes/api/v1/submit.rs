@@ -1,77 +1,72 @@
-use std::fs;
-use std::sync::Arc;
-
 use actix_web::{web, HttpResponse};
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::judge::{compiler, consts::LANG_EXTENSIONS, runner};
-use crate::server::models::{self, SubmissionStatus};
+use crate::server::auth::Principal;
+use crate::server::error::{ApiError, ApiErrorRet};
+use crate::server::models::{self, SourceLimits, SubmissionStatus};
+use crate::server::rate_limit::{self, RateLimiter};
+use crate::server::utils::body;
+use crate::store::Store;
+use crate::thread_pool::ThreadPool;
 
 type SubmissionStatusCode = i16;
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct SubmitRet {
+    #[schema(value_type = SubmissionStatus)]
     status: SubmissionStatusCode,
     info: String,
+    submission_id: u64,
 }
 
-static _SINGLETON_COMPILER: Lazy<Arc<compiler::Compiler>> =
-    Lazy::new(|| Arc::new(compiler::Compiler::default()));
-static _SINGLETON_RUNNER: Lazy<Arc<runner::Runner>> =
-    Lazy::new(|| Arc::new(runner::Runner::default()));
-
+#[utoipa::path(
+    post,
+    path = "/api/v1/submit",
+    tag = "submissions",
+    request_body = models::Submission,
+    responses(
+        (status = 200, description = "Queued", body = SubmitRet),
+        (status = 400, description = "Invalid submission", body = ApiErrorRet),
+        (status = 404, description = "No such problem", body = ApiErrorRet),
+        (status = 413, description = "Source or body too large", body = ApiErrorRet),
+        (status = 422, description = "The problem has no testcases", body = ApiErrorRet),
+        (status = 429, description = "Rate limited", body = ApiErrorRet),
+        (status = 503, description = "Queue full or shutting down", body = ApiErrorRet),
+    )
+)]
 #[tracing::instrument(
     name = "Submit code",
-    skip(form),
-    fields(
-        source = %form.source,
-        lang = %form.lang,
-    )
+    skip(payload, thread_pool, store, limiter, source_limits, principal),
+    fields(lang, problem_id)
 )]
-pub async fn submit(form: web::Json<models::Submission>) -> HttpResponse {
-    // create the source code file
-    let source = &form.source;
-    let lang = &form.lang;
-    let ext = match LANG_EXTENSIONS.get(lang.as_str()) {
-        Some(ext) => ext,
-        None => return HttpResponse::BadRequest().finish(),
+pub async fn submit(
+    payload: web::Payload,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+    limiter: web::Data<RateLimiter>,
+    source_limits: web::Data<SourceLimits>,
+    principal: Option<web::ReqData<Principal>>,
+) -> Result<HttpResponse, ApiError> {
+    let form: models::Submission = body::read_json(payload, source_limits.body_limit()).await?;
+    tracing::Span::current()
+        .record("lang", form.lang.as_str())
+        .record("problem_id", form.problem_id.as_str());
+    // held until the submission is stored
+    let _reservation = rate_limit::reserve_pending(&limiter, &store, principal.as_deref(), 1)?;
+    let task = form
+        .to_task(&source_limits)
+        .map_err(|e| ApiError::from_submission_error(e, &form.lang, &form.problem_id))?;
+    let task = match &principal {
+        Some(principal) => task.with_owner(&principal.name),
+        None => task,
     };
-    let source_path = format!("Main{}", ext);
-    fs::write(source_path.as_str(), source.as_str()).unwrap();
+    // only acknowledged once it's persisted
+    let submission_id = thread_pool.enqueue_task(task)?;
 
-    let Ok(problem_id) = form.problem_id.parse::<u64>() else {
-        fs::remove_file(source_path).unwrap();
-        let body = SubmitRet {
-            status: SubmissionStatus::UnknownError as SubmissionStatusCode,
-            info: "Wrong problem id".to_string(),
-        };
-        return HttpResponse::Ok() .json(body);
+    let ret = SubmitRet {
+        status: SubmissionStatus::Pending as SubmissionStatusCode,
+        info: "Queued".to_string(),
+        submission_id,
     };
-
-    let _testcase_path = format!("assets/{problem_id}");
-
-    // FIXME
-    // exec task
-    // let this_task = Task::new(problem_id, &testcase_path, lang, source);
-    // let thread_pool = ThreadPoolBuilder::new().build();
-    // let exec_result = this_task.execute(SINGLETON_COMPILER.clone(), SINGLETON_RUNNER.clone(), Arc::new(&thread_pool));
-    // let answer = match exec_result {
-    //     JudgeStatus::Accepted => SubmissionStatus::Accepted,
-    //     JudgeStatus::CompilationError(_) => SubmissionStatus::CompilationError,
-    //     JudgeStatus::WrongAnswer(_, _) => SubmissionStatus::WrongAnswer,
-    //     JudgeStatus::MemoLimitExceeded(_) => SubmissionStatus::MemoLimitExceeded,
-    //     JudgeStatus::TimeLimitExceeded(_) => SubmissionStatus::TimeLimitExceeded,
-    //     JudgeStatus::RuntimeError(_) => SubmissionStatus::RuntimeError,
-    //     JudgeStatus::UnknownError(_) => SubmissionStatus::UnknownError,
-    //     _ => SubmissionStatus::UnknownError,
-    // } as SubmissionStatusCode;
-    // let ret = SubmitRet {
-    //     status: answer,
-    //     info: format!("{:?}", exec_result),
-    // };
-
-    fs::remove_file(source_path).unwrap();
-
-    let ret = "";
-    HttpResponse::Ok().json(ret)
+    Ok(HttpResponse::Ok().json(ret))
 }
@@ -0,0 +1,24 @@
+use actix_web::{http::Method, web, HttpRequest, HttpResponse};
+
+use crate::server::frontend::Frontend;
+
+/// Serves the built frontend for everything no other route matches.
+pub async fn frontend(req: HttpRequest, frontend: web::Data<Frontend>) -> HttpResponse {
+    if req.path().starts_with("/api/") || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return HttpResponse::NotFound().finish();
+    }
+    let Some(asset) = frontend.get(req.path()) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let cache_control = if asset.immutable {
+        "public, max-age=31536000, immutable"
+    } else {
+        // index.html refers to the current assets
+        "no-cache"
+    };
+    HttpResponse::Ok()
+        .content_type(asset.content_type)
+        .insert_header(("Cache-Control", cache_control))
+        .body(asset.content)
+}
@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::server::error::ApiError;
+use crate::server::health::{Check, Readiness};
+use crate::thread_pool::ThreadPool;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct HealthRet {
+    status: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct CheckRet {
+    name: String,
+    ok: bool,
+    info: String,
+}
+
+impl From<Check> for CheckRet {
+    fn from(check: Check) -> CheckRet {
+        CheckRet {
+            name: check.name.to_string(),
+            ok: check.ok,
+            info: check.info,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ReadyRet {
+    ready: bool,
+    checks: Vec<CheckRet>,
+}
+
+/// The process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "probes",
+    security(()),
+    responses((status = 200, description = "Up", body = HealthRet))
+)]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(HealthRet {
+        status: "ok".to_string(),
+    })
+}
+
+/// 200 if the judge can take submissions, 503 otherwise.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "probes",
+    security(()),
+    responses(
+        (status = 200, description = "Ready", body = ReadyRet),
+        (status = 503, description = "Some check failed", body = ReadyRet),
+    )
+)]
+pub async fn readyz(
+    readiness: web::Data<Readiness>,
+    thread_pool: web::Data<ThreadPool>,
+) -> Result<HttpResponse, ApiError> {
+    // toolchains are run as processes
+    let checks = web::block(move || readiness.check(&thread_pool))
+        .await
+        .map_err(|e| {
+            tracing::error!("readiness: {e}");
+            ApiError::Internal
+        })?;
+
+    let ready = checks.iter().all(|check| check.ok);
+    let ret = ReadyRet {
+        ready,
+        checks: checks.into_iter().map(CheckRet::from).collect(),
+    };
+    if ready {
+        Ok(HttpResponse::Ok().json(ret))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(ret))
+    }
+}
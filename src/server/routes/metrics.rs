@@ -0,0 +1,64 @@
+use actix_web::{web, HttpResponse};
+
+use crate::metrics::METRICS;
+use crate::thread_pool::ThreadPool;
+
+/// Prometheus text exposition format.
+static CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "probes",
+    security(()),
+    responses((
+        status = 200,
+        description = "Prometheus text exposition format",
+        body = String,
+        content_type = "text/plain"
+    ))
+)]
+pub async fn metrics(thread_pool: web::Data<ThreadPool>) -> HttpResponse {
+    METRICS.observe_pool(&thread_pool);
+    HttpResponse::Ok()
+        .content_type(CONTENT_TYPE)
+        .body(METRICS.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test, web, App};
+
+    use super::{metrics, CONTENT_TYPE};
+    use crate::config::Config;
+    use crate::judge::consts::CONFIG_PATH;
+    use crate::thread_pool::thread_pool_builder::ThreadPoolBuilder;
+
+    #[actix_web::test]
+    async fn test_scrape() {
+        let thread_pool = ThreadPoolBuilder::new(&Config::load(CONFIG_PATH))
+            .set_pool_size(3)
+            .build();
+        let thread_pool = web::Data::new(thread_pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(thread_pool.clone())
+                .route("/metrics", web::get().to(metrics)),
+        )
+        .await;
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+        // idle workers spin until they're stopped
+        thread_pool.stop_all(true);
+
+        assert!(res.status().is_success());
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            CONTENT_TYPE
+        );
+        let body = test::read_body(res).await;
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.contains("# TYPE coffee_oj_queued_jobs gauge"));
+        assert!(text.contains(r#"coffee_oj_workers{state="max"} 3"#));
+    }
+}
@@ -1,3 +1,7 @@
-pub mod index;
-pub use index::*;
+pub mod frontend;
+pub mod health;
+pub mod metrics;
+pub use frontend::*;
+pub use health::*;
+pub use metrics::*;
 pub mod api;
@@ -1,45 +1,196 @@
-use std::net::TcpListener;
+use std::{fs, io, sync::Arc, time::Duration};
 
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{
+    dev::Server, error::Error, middleware::Condition, web, App, HttpRequest, HttpServer,
+};
+use tokio::signal::unix::{signal, SignalKind};
 use tracing_actix_web::TracingLogger;
 
+use crate::config::Config;
+use crate::server::auth::Authentication;
+use crate::server::error::ApiError;
+use crate::server::frontend::Frontend;
+use crate::server::health::Readiness;
+use crate::server::listener::Listeners;
+use crate::server::rate_limit::{RateLimiter, RateLimiting, UnixPeer};
+use crate::store::Store;
+use crate::thread_pool::{thread_pool_builder::ThreadPoolBuilder, ThreadPool};
+use crate::webhook::Webhooks;
+
 pub struct WebApp {
     server: Server,
     port: u16,
+    thread_pool: web::Data<ThreadPool>,
+    shutdown_timeout: Duration,
+    unix_socket: Option<String>,
 }
 
-pub static HOST: &str = "0.0.0.0";
-pub static PORT: &str = "4514";
-
 impl WebApp {
-    pub async fn new() -> Result<Self, std::io::Error> {
-        let address = format!("{}:{}", HOST, PORT);
-        let listener = TcpListener::bind(address)?;
-        let port = listener.local_addr().unwrap().port();
-        let server = create_server(listener)?;
-        Ok(WebApp { server, port })
+    pub async fn new(config: &Config) -> Result<Self, std::io::Error> {
+        let listeners = Listeners::bind(&config.server)?;
+        let port = listeners.get_port();
+        let scheme = if listeners.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        tracing::info!(
+            "server: listening on {scheme}://{}:{port}",
+            config.server.host
+        );
+        if let Some(path) = &config.server.unix_socket {
+            tracing::info!("server: listening on `{path}`");
+        }
+        let store = Arc::new(Store::from_config(&config.database));
+        let thread_pool = ThreadPoolBuilder::new(config)
+            .set_store(store.clone())
+            .set_webhooks(Arc::new(Webhooks::new(config.webhook.clone())))
+            .build();
+        match thread_pool.recover() {
+            Ok(0) => {}
+            Ok(recovered) => tracing::info!("queue: recovered {recovered} unfinished submissions"),
+            Err(e) => tracing::error!("queue: can't recover unfinished submissions: {e:?}"),
+        }
+        thread_pool.awake_all();
+        let thread_pool = web::Data::new(thread_pool);
+        let server = create_server(
+            listeners,
+            thread_pool.clone(),
+            web::Data::from(store),
+            config,
+        )?;
+        Ok(WebApp {
+            server,
+            port,
+            thread_pool,
+            shutdown_timeout: Duration::from_secs(config.server.shutdown_timeout),
+            unix_socket: config.server.unix_socket.clone(),
+        })
     }
 
+    /// Serves until SIGTERM or SIGINT, then shuts down gracefully: new
+    /// submissions are refused, open connections and started submissions
+    /// get `shutdown_timeout` to finish, and what's left is judged after
+    /// the restart.
     pub async fn run(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let WebApp {
+            mut server,
+            thread_pool,
+            shutdown_timeout,
+            unix_socket,
+            ..
+        } = self;
+        let handle = server.handle();
+        tokio::select! {
+            ret = &mut server => return ret,
+            ret = shutdown_signal() => ret?,
+        }
+
+        tracing::info!("server: shutting down");
+        thread_pool.stop_accepting();
+        // the server only stops while it's polled
+        let (_, ret, summary) = tokio::join!(
+            handle.stop(true),
+            server,
+            web::block(move || thread_pool.shutdown(shutdown_timeout))
+        );
+        ret?;
+        match summary {
+            Ok(summary) => tracing::info!(
+                "queue: {} submissions skipped, {} interrupted, all left pending",
+                summary.skipped,
+                summary.interrupted.len()
+            ),
+            Err(e) => tracing::error!("queue: can't shut down: {e}"),
+        }
+        if let Some(path) = unix_socket {
+            let _ = fs::remove_file(path);
+        }
+        Ok(())
     }
 
+    /// The TCP port bound, also when the configured one is 0.
     pub fn get_port(&self) -> u16 {
         self.port
     }
 }
 
-pub fn create_server(listener: TcpListener) -> Result<Server, std::io::Error> {
-    let server = HttpServer::new(|| {
+async fn shutdown_signal() -> io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        ret = tokio::signal::ctrl_c() => ret,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+/// Malformed bodies, queries and paths are answered like every other error.
+fn invalid_request<E: std::fmt::Display>(e: E, _req: &HttpRequest) -> Error {
+    ApiError::InvalidRequest(e.to_string()).into()
+}
+
+/// The API and the frontend, `config` is only read while building it.
+pub fn create_server(
+    listeners: Listeners,
+    thread_pool: web::Data<ThreadPool>,
+    store: web::Data<Store>,
+    config: &Config,
+) -> Result<Server, std::io::Error> {
+    let Listeners {
+        tcp,
+        unix,
+        tls,
+        workers,
+        shutdown_timeout,
+    } = listeners;
+    let tokens = Arc::new(config.auth.clone());
+    let limiter = web::Data::new(RateLimiter::new(config.rate_limit));
+    let readiness = web::Data::new(Readiness::new(config.health));
+    let started = readiness.clone();
+    let source_limits = web::Data::new(config.max_source_size.clone());
+    let web_config = config.web.clone();
+    let frontend = web::Data::new(Frontend::new(&web_config.dist));
+    let mut server = HttpServer::new(move || {
         App::new()
+            // runs after authentication, which is wrapped around it
+            .wrap(RateLimiting::new(limiter.clone().into_inner()))
+            .wrap(Authentication::new(
+                tokens.clone(),
+                limiter.clone().into_inner(),
+            ))
+            // preflights carry no token
+            .wrap(Condition::new(
+                web_config.is_cors_enabled(),
+                web_config.cors(),
+            ))
             .wrap(TracingLogger::default())
-            .route("/", web::get().to(crate::server::routes::index))
-            .route(
-                "/api/v1/submit",
-                web::post().to(crate::server::routes::api::submit),
-            )
+            .app_data(thread_pool.clone())
+            .app_data(store.clone())
+            .app_data(limiter.clone())
+            .app_data(readiness.clone())
+            .app_data(source_limits.clone())
+            .app_data(frontend.clone())
+            .app_data(web::JsonConfig::default().error_handler(invalid_request))
+            .app_data(web::QueryConfig::default().error_handler(invalid_request))
+            .app_data(web::PathConfig::default().error_handler(invalid_request))
+            // every route but the frontend, with its docs
+            .configure(crate::server::openapi::configure)
+            .default_service(web::to(crate::server::routes::frontend))
     })
-    .listen(listener)?
-    .run();
-    Ok(server)
+    // unix socket clients are rate limited by process
+    .on_connect(UnixPeer::on_connect)
+    // see `WebApp::run`
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout);
+    if workers > 0 {
+        server = server.workers(workers);
+    }
+    server = match tls {
+        Some(tls) => server.listen_rustls(tcp, tls)?,
+        None => server.listen(tcp)?,
+    };
+    if let Some(unix) = unix {
+        server = server.listen_uds(unix)?;
+    }
+    started.mark_started();
+    Ok(server.run())
 }
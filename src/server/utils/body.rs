@@ -0,0 +1,24 @@
+use actix_web::web;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+
+use crate::server::error::ApiError;
+
+/// Reads a JSON body of at most `limit` bytes. Unlike `web::Json`, a body
+/// that isn't UTF-8 or is too large is told apart from malformed JSON.
+pub async fn read_json<T: DeserializeOwned>(
+    mut payload: web::Payload,
+    limit: usize,
+) -> Result<T, ApiError> {
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ApiError::InvalidRequest(e.to_string()))?;
+        if body.len() + chunk.len() > limit {
+            return Err(ApiError::PayloadTooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let body = std::str::from_utf8(&body).map_err(|_| ApiError::InvalidEncoding)?;
+    serde_json::from_str(body).map_err(|e| ApiError::InvalidRequest(e.to_string()))
+}
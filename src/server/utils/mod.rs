@@ -1 +1,3 @@
+pub mod body;
+pub mod route;
 pub mod telemetry;
@@ -0,0 +1,11 @@
+use actix_web::dev::ServiceRequest;
+
+/// The pattern of the route `req` is served by, e.g.
+/// `/api/v1/submissions/{id}`, `None` for the default service.
+///
+/// Matched against the percent-decoded path, as the router does: `req.path()`
+/// and [`actix_web::HttpRequest::match_pattern`] see `/%61pi/v1/rejudge`,
+/// which the router serves as `/api/v1/rejudge`.
+pub fn route_pattern(req: &ServiceRequest) -> Option<String> {
+    req.resource_map().match_pattern(req.match_info().as_str())
+}
@@ -0,0 +1,972 @@
+use std::{
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{
+    params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension, Row,
+};
+use toml::{Table, Value};
+
+use crate::judge::{consts::DEFAULT_DATABASE_PATH, runner::TestcaseResult, Verdict};
+use crate::thread_pool::queue::Priority;
+
+#[derive(Debug)]
+pub enum Error {
+    FileSystemError(String),
+    DatabaseError(rusqlite::Error),
+    /// the caller wasn't given a store
+    Unavailable,
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::DatabaseError(e)
+    }
+}
+
+/// Applied in order, `PRAGMA user_version` counts the ones already applied.
+const MIGRATIONS: &[&str] = &[
+    r#"
+CREATE TABLE IF NOT EXISTS submissions (
+    id          INTEGER PRIMARY KEY,
+    problem_id  INTEGER NOT NULL,
+    lang        TEXT NOT NULL,
+    source      TEXT NOT NULL,
+    status      TEXT NOT NULL,
+    info        TEXT NOT NULL DEFAULT '',
+    time_cost   INTEGER NOT NULL DEFAULT 0,
+    mem_cost    INTEGER NOT NULL DEFAULT 0,
+    created_at  INTEGER NOT NULL,
+    updated_at  INTEGER NOT NULL,
+    finished_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS testcase_results (
+    submission_id INTEGER NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    testcase      TEXT NOT NULL,
+    verdict       TEXT NOT NULL,
+    time_cost     INTEGER NOT NULL,
+    mem_cost      INTEGER NOT NULL,
+    PRIMARY KEY (submission_id, testcase)
+);
+"#,
+    r#"
+ALTER TABLE submissions ADD COLUMN compile_log TEXT NOT NULL DEFAULT '';
+CREATE INDEX IF NOT EXISTS submissions_by_problem ON submissions (problem_id, id);
+CREATE INDEX IF NOT EXISTS submissions_by_status ON submissions (status, id);
+CREATE INDEX IF NOT EXISTS submissions_by_created_at ON submissions (created_at);
+"#,
+    r#"
+ALTER TABLE submissions ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS rejudges (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    reason     TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS rejudge_items (
+    rejudge_id    INTEGER NOT NULL REFERENCES rejudges(id) ON DELETE CASCADE,
+    submission_id INTEGER NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    old_status    TEXT NOT NULL,
+    new_status    TEXT,
+    PRIMARY KEY (rejudge_id, submission_id)
+);
+CREATE INDEX IF NOT EXISTS rejudge_items_by_submission ON rejudge_items (submission_id);
+"#,
+    r#"
+ALTER TABLE submissions ADD COLUMN callback_url TEXT;
+"#,
+    r#"
+ALTER TABLE submissions ADD COLUMN owner TEXT;
+CREATE INDEX IF NOT EXISTS submissions_by_owner ON submissions (owner, id);
+"#,
+    r#"
+ALTER TABLE testcase_results ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+ALTER TABLE submissions ADD COLUMN priority TEXT NOT NULL DEFAULT 'Live';
+"#,
+    // ids aren't given again once the latest submission is gone
+    r#"
+CREATE TABLE submissions_autoincrement (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    problem_id   INTEGER NOT NULL,
+    lang         TEXT NOT NULL,
+    source       TEXT NOT NULL,
+    status       TEXT NOT NULL,
+    info         TEXT NOT NULL DEFAULT '',
+    time_cost    INTEGER NOT NULL DEFAULT 0,
+    mem_cost     INTEGER NOT NULL DEFAULT 0,
+    created_at   INTEGER NOT NULL,
+    updated_at   INTEGER NOT NULL,
+    finished_at  INTEGER,
+    compile_log  TEXT NOT NULL DEFAULT '',
+    attempts     INTEGER NOT NULL DEFAULT 0,
+    callback_url TEXT,
+    owner        TEXT,
+    priority     TEXT NOT NULL DEFAULT 'Live'
+);
+INSERT INTO submissions_autoincrement
+    (id, problem_id, lang, source, status, info, time_cost, mem_cost, created_at,
+     updated_at, finished_at, compile_log, attempts, callback_url, owner, priority)
+SELECT id, problem_id, lang, source, status, info, time_cost, mem_cost, created_at,
+       updated_at, finished_at, compile_log, attempts, callback_url, owner, priority
+FROM submissions;
+DROP TABLE submissions;
+ALTER TABLE submissions_autoincrement RENAME TO submissions;
+CREATE INDEX submissions_by_problem ON submissions (problem_id, id);
+CREATE INDEX submissions_by_status ON submissions (status, id);
+CREATE INDEX submissions_by_created_at ON submissions (created_at);
+CREATE INDEX submissions_by_owner ON submissions (owner, id);
+"#,
+];
+
+/// Upper bound of [`SubmissionFilter::limit`].
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// A submission as stored, timestamps are seconds since the Unix epoch.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SubmissionRecord {
+    pub id: u64,
+    pub problem_id: u64,
+    pub lang: String,
+    pub source: String,
+    pub status: Verdict,
+    pub info: String,
+    pub compile_log: String,
+    /// how many times judging was started
+    pub attempts: u64,
+    /// where the result is POSTed to once finished, see [`crate::webhook`]
+    pub callback_url: Option<String>,
+    /// name of the API token it was submitted with
+    pub owner: Option<String>,
+    /// what it's queued as, again after a restart
+    pub priority: Priority,
+    pub time_cost: u64,
+    pub mem_cost: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+impl SubmissionRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<SubmissionRecord> {
+        let status: String = row.get("status")?;
+        let priority: String = row.get("priority")?;
+        Ok(SubmissionRecord {
+            id: row.get("id")?,
+            problem_id: row.get("problem_id")?,
+            lang: row.get("lang")?,
+            source: row.get("source")?,
+            status: Verdict::parse(&status).unwrap_or(Verdict::SystemError),
+            info: row.get("info")?,
+            compile_log: row.get("compile_log")?,
+            attempts: row.get("attempts")?,
+            callback_url: row.get("callback_url")?,
+            owner: row.get("owner")?,
+            priority: Priority::parse(&priority).unwrap_or(Priority::Live),
+            time_cost: row.get("time_cost")?,
+            mem_cost: row.get("mem_cost")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+            finished_at: row.get("finished_at")?,
+        })
+    }
+}
+
+/// A submission to persist as pending, see [`Store::insert_submissions`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct NewSubmission<'a> {
+    pub problem_id: u64,
+    pub lang: &'a str,
+    pub source: &'a str,
+    pub callback_url: Option<&'a str>,
+    pub owner: Option<&'a str>,
+}
+
+/// Narrows [`Store::list_submissions`], newest first. `since` is compared
+/// with `created_at`, `cursor` is the last id of the previous page.
+#[derive(Debug, Clone)]
+pub struct SubmissionFilter {
+    pub problem_id: Option<u64>,
+    pub lang: Option<String>,
+    pub status: Option<Verdict>,
+    pub since: Option<u64>,
+    pub owner: Option<String>,
+    pub cursor: Option<u64>,
+    pub limit: usize,
+}
+
+impl Default for SubmissionFilter {
+    fn default() -> SubmissionFilter {
+        SubmissionFilter {
+            problem_id: None,
+            lang: None,
+            status: None,
+            since: None,
+            owner: None,
+            cursor: None,
+            limit: 20,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RejudgeItem {
+    pub submission_id: u64,
+    pub problem_id: u64,
+    pub old_status: Verdict,
+    /// `None` until the submission is judged again
+    pub new_status: Option<Verdict>,
+}
+
+/// What a rejudge changed, for telling the affected users.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RejudgeReport {
+    pub id: u64,
+    pub reason: String,
+    pub created_at: u64,
+    pub items: Vec<RejudgeItem>,
+}
+
+impl RejudgeReport {
+    pub fn is_finished(&self) -> bool {
+        self.items.iter().all(|item| item.new_status.is_some())
+    }
+
+    /// Items whose verdict differs from the one before, e.g. AC to WA.
+    pub fn get_changed(&self) -> Vec<&RejudgeItem> {
+        self.items
+            .iter()
+            .filter(|item| {
+                item.new_status
+                    .is_some_and(|status| status != item.old_status)
+            })
+            .collect()
+    }
+}
+
+/// Submissions and their results in an embedded SQLite database.
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+/// `[database]` in the config.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DatabaseConfig {
+    pub path: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig::from_config(&Table::new())
+    }
+}
+
+impl DatabaseConfig {
+    pub fn from_config(data: &Table) -> DatabaseConfig {
+        let path = match data.get("database") {
+            Some(Value::Table(database)) => match database.get("path") {
+                Some(Value::String(path)) => path.as_str(),
+                None => DEFAULT_DATABASE_PATH,
+                _ => panic!("config: [database] should be set correctly"),
+            },
+            None => DEFAULT_DATABASE_PATH,
+            _ => panic!("config: [database] should be set correctly"),
+        };
+
+        DatabaseConfig {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Store {
+    /// Panics when the database can't be opened.
+    pub fn from_config(config: &DatabaseConfig) -> Store {
+        let path = &config.path;
+        Store::open(path).unwrap_or_else(|e| panic!("store: can't open `{path}`: {e:?}"))
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store, Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::FileSystemError(format!("{e}")))?;
+        }
+        let conn = Connection::open(path)?;
+        // workers and the web server write concurrently
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Store::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Store, Error> {
+        Store::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Store, Error> {
+        // a table rebuilt by a migration would take the rows referencing it
+        // along when dropped, and the pragma is a no-op in a transaction
+        conn.pragma_update(None, "foreign_keys", false)?;
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        Ok(Store {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Persists a pending submission, returns the id it's given.
+    pub fn insert_submission(
+        &self,
+        problem_id: u64,
+        lang: &str,
+        source: &str,
+        callback_url: Option<&str>,
+        owner: Option<&str>,
+        priority: Priority,
+    ) -> Result<u64, Error> {
+        let submission = NewSubmission {
+            problem_id,
+            lang,
+            source,
+            callback_url,
+            owner,
+        };
+        let conn = self.conn.lock().unwrap();
+        insert_submission(&conn, &submission, priority, now())
+    }
+
+    /// Persists either all of the submissions or none, returns their ids in
+    /// order.
+    pub fn insert_submissions(
+        &self,
+        submissions: &[NewSubmission],
+        priority: Priority,
+    ) -> Result<Vec<u64>, Error> {
+        let now = now();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let ids = submissions
+            .iter()
+            .map(|submission| insert_submission(&tx, submission, priority, now))
+            .collect::<Result<Vec<u64>, Error>>()?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    pub fn set_status(&self, id: u64, status: Verdict) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE submissions SET status = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, status.as_str(), now()],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the submission as judging and counts the attempt.
+    pub fn start_judging(&self, id: u64) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE submissions SET status = ?2, attempts = attempts + 1, updated_at = ?3
+             WHERE id = ?1",
+            params![id, Verdict::Judging.as_str(), now()],
+        )?;
+        Ok(())
+    }
+
+    /// Puts a submission whose judging was interrupted by a shutdown back to
+    /// pending, the attempt isn't counted.
+    pub fn interrupt_judging(&self, id: u64) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE submissions SET status = ?2, attempts = MAX(attempts - 1, 0), updated_at = ?3
+             WHERE id = ?1 AND status = ?4",
+            params![
+                id,
+                Verdict::Pending.as_str(),
+                now(),
+                Verdict::Judging.as_str()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Submissions still pending or judging, oldest first. After a restart
+    /// these are the ones the previous process didn't finish.
+    pub fn list_unfinished(&self) -> Result<Vec<SubmissionRecord>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT * FROM submissions WHERE status IN (?1, ?2) ORDER BY id")?;
+        let records = stmt
+            .query_map(
+                params![Verdict::Pending.as_str(), Verdict::Judging.as_str()],
+                SubmissionRecord::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Submissions of `owner` still pending or judging.
+    pub fn count_unfinished(&self, owner: &str) -> Result<u64, Error> {
+        let count = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM submissions WHERE owner = ?1 AND status IN (?2, ?3)",
+            params![owner, Verdict::Pending.as_str(), Verdict::Judging.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    pub fn set_compile_log(&self, id: u64, compile_log: &str) -> Result<(), Error> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE submissions SET compile_log = ?2 WHERE id = ?1",
+            params![id, compile_log],
+        )?;
+        Ok(())
+    }
+
+    /// Records the final verdict and the testcase results in one transaction,
+    /// the results are read back in the order given.
+    pub fn finish_submission(
+        &self,
+        id: u64,
+        status: Verdict,
+        info: &str,
+        results: &[TestcaseResult],
+    ) -> Result<(), Error> {
+        let time_cost: u64 = results.iter().map(|r| r.time_cost).sum();
+        let mem_cost = results.iter().map(|r| r.mem_cost).max().unwrap_or(0);
+        let now = now();
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM testcase_results WHERE submission_id = ?1",
+            params![id],
+        )?;
+        for (position, result) in results.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO testcase_results
+                 (submission_id, position, testcase, verdict, time_cost, mem_cost)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    position,
+                    result.testcase,
+                    result.verdict.as_str(),
+                    result.time_cost,
+                    result.mem_cost
+                ],
+            )?;
+        }
+        tx.execute(
+            "UPDATE submissions
+             SET status = ?2, info = ?3, time_cost = ?4, mem_cost = ?5, updated_at = ?6, finished_at = ?6
+             WHERE id = ?1",
+            params![id, status.as_str(), info, time_cost, mem_cost, now],
+        )?;
+        tx.execute(
+            "UPDATE rejudge_items SET new_status = ?2
+             WHERE submission_id = ?1 AND new_status IS NULL",
+            params![id, status.as_str()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Gives the submission the verdict `Cancelled` unless it has a final
+    /// one already, returns whether it did.
+    pub fn cancel_unfinished(&self, id: u64) -> Result<bool, Error> {
+        let changed = self.conn.lock().unwrap().execute(
+            "UPDATE submissions SET status = ?2, updated_at = ?3, finished_at = ?3
+             WHERE id = ?1 AND status IN (?4, ?5)",
+            params![
+                id,
+                Verdict::Cancelled.as_str(),
+                now(),
+                Verdict::Pending.as_str(),
+                Verdict::Judging.as_str()
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn get_submission(&self, id: u64) -> Result<Option<SubmissionRecord>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let record = conn
+            .query_row(
+                "SELECT * FROM submissions WHERE id = ?1",
+                params![id],
+                SubmissionRecord::from_row,
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    pub fn list_submissions(
+        &self,
+        filter: &SubmissionFilter,
+    ) -> Result<Vec<SubmissionRecord>, Error> {
+        self.query_submissions(filter, Some(filter.limit.clamp(1, MAX_PAGE_SIZE)))
+    }
+
+    /// Like [`Store::list_submissions`] without the page size limit.
+    pub fn find_submissions(
+        &self,
+        filter: &SubmissionFilter,
+    ) -> Result<Vec<SubmissionRecord>, Error> {
+        self.query_submissions(filter, None)
+    }
+
+    fn query_submissions(
+        &self,
+        filter: &SubmissionFilter,
+        limit: Option<usize>,
+    ) -> Result<Vec<SubmissionRecord>, Error> {
+        let mut conditions: Vec<&str> = vec![];
+        let mut values: Vec<SqlValue> = vec![];
+        if let Some(problem_id) = filter.problem_id {
+            conditions.push("problem_id = ?");
+            values.push(SqlValue::Integer(problem_id as i64));
+        }
+        if let Some(lang) = &filter.lang {
+            conditions.push("lang = ?");
+            values.push(SqlValue::Text(lang.clone()));
+        }
+        if let Some(status) = filter.status {
+            conditions.push("status = ?");
+            values.push(SqlValue::Text(status.as_str().to_string()));
+        }
+        if let Some(since) = filter.since {
+            conditions.push("created_at >= ?");
+            values.push(SqlValue::Integer(since as i64));
+        }
+        if let Some(owner) = &filter.owner {
+            conditions.push("owner = ?");
+            values.push(SqlValue::Text(owner.clone()));
+        }
+        if let Some(cursor) = filter.cursor {
+            conditions.push("id < ?");
+            values.push(SqlValue::Integer(cursor as i64));
+        }
+
+        let mut sql = "SELECT * FROM submissions".to_string();
+        if !conditions.is_empty() {
+            sql += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        sql += " ORDER BY id DESC";
+        if let Some(limit) = limit {
+            sql += &format!(" LIMIT {limit}");
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let records = stmt
+            .query_map(params_from_iter(values), SubmissionRecord::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Puts the submissions back to pending at [`Priority::Rejudge`], keeping
+    /// their verdicts in the report. Only the ones still final when the
+    /// transaction runs are taken, returns the id of the rejudge and theirs.
+    pub fn create_rejudge(
+        &self,
+        reason: &str,
+        submission_ids: &[u64],
+    ) -> Result<(u64, Vec<u64>), Error> {
+        let now = now();
+        let (pending, judging) = (Verdict::Pending.as_str(), Verdict::Judging.as_str());
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO rejudges (reason, created_at) VALUES (?1, ?2)",
+            params![reason, now],
+        )?;
+        let rejudge_id = tx.last_insert_rowid() as u64;
+        let mut taken = vec![];
+        for &id in submission_ids {
+            let inserted = tx.execute(
+                "INSERT INTO rejudge_items (rejudge_id, submission_id, old_status)
+                 SELECT ?1, id, status FROM submissions
+                 WHERE id = ?2 AND status NOT IN (?3, ?4)",
+                params![rejudge_id, id, pending, judging],
+            )?;
+            if inserted == 0 {
+                // waiting or being judged already
+                continue;
+            }
+            tx.execute(
+                "DELETE FROM testcase_results WHERE submission_id = ?1",
+                params![id],
+            )?;
+            tx.execute(
+                "UPDATE submissions
+                 SET status = ?2, info = '', attempts = 0, priority = ?3, updated_at = ?4,
+                     finished_at = NULL
+                 WHERE id = ?1",
+                params![id, pending, Priority::Rejudge.as_str(), now],
+            )?;
+            taken.push(id);
+        }
+        tx.commit()?;
+
+        Ok((rejudge_id, taken))
+    }
+
+    pub fn get_rejudge_report(&self, id: u64) -> Result<Option<RejudgeReport>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let report = conn
+            .query_row(
+                "SELECT reason, created_at FROM rejudges WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(RejudgeReport {
+                        id,
+                        reason: row.get(0)?,
+                        created_at: row.get(1)?,
+                        items: vec![],
+                    })
+                },
+            )
+            .optional()?;
+        let Some(mut report) = report else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT i.submission_id, s.problem_id, i.old_status, i.new_status
+             FROM rejudge_items i JOIN submissions s ON s.id = i.submission_id
+             WHERE i.rejudge_id = ?1 ORDER BY i.submission_id",
+        )?;
+        report.items = stmt
+            .query_map(params![id], |row| {
+                let old_status: String = row.get(2)?;
+                let new_status: Option<String> = row.get(3)?;
+                Ok(RejudgeItem {
+                    submission_id: row.get(0)?,
+                    problem_id: row.get(1)?,
+                    old_status: Verdict::parse(&old_status).unwrap_or(Verdict::SystemError),
+                    new_status: new_status
+                        .map(|status| Verdict::parse(&status).unwrap_or(Verdict::SystemError)),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(report))
+    }
+
+    pub fn get_testcase_results(&self, id: u64) -> Result<Vec<TestcaseResult>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT testcase, verdict, time_cost, mem_cost FROM testcase_results
+             WHERE submission_id = ?1 ORDER BY position, testcase",
+        )?;
+        let results = stmt
+            .query_map(params![id], |row| {
+                let verdict: String = row.get(1)?;
+                Ok(TestcaseResult {
+                    testcase: row.get(0)?,
+                    verdict: Verdict::parse(&verdict).unwrap_or(Verdict::SystemError),
+                    time_cost: row.get(2)?,
+                    mem_cost: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(results)
+    }
+}
+
+/// A transaction derefs to its connection.
+fn insert_submission(
+    conn: &Connection,
+    submission: &NewSubmission,
+    priority: Priority,
+    now: u64,
+) -> Result<u64, Error> {
+    conn.execute(
+        "INSERT INTO submissions
+             (problem_id, lang, source, status, callback_url, owner, priority,
+              created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+        params![
+            submission.problem_id,
+            submission.lang,
+            submission.source,
+            Verdict::Pending.as_str(),
+            submission.callback_url,
+            submission.owner,
+            priority.as_str(),
+            now
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::judge::{runner::TestcaseResult, Verdict};
+    use crate::thread_pool::queue::Priority;
+
+    use rusqlite::{params, Connection};
+
+    use super::{NewSubmission, Store, SubmissionFilter, MIGRATIONS};
+
+    #[test]
+    fn test_submission_lifecycle() {
+        let store = Store::open_in_memory().unwrap();
+        let id = store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+
+        let record = store.get_submission(id).unwrap().unwrap();
+        assert_eq!(record.status, Verdict::Pending);
+        assert_eq!(record.finished_at, None);
+
+        store.start_judging(id).unwrap();
+        assert_eq!(store.list_unfinished().unwrap()[0].attempts, 1);
+        store.interrupt_judging(id).unwrap();
+        let record = store.get_submission(id).unwrap().unwrap();
+        assert_eq!((record.status, record.attempts), (Verdict::Pending, 0));
+        store.start_judging(id).unwrap();
+        let results = vec![
+            TestcaseResult {
+                testcase: "2.in".to_string(),
+                verdict: Verdict::Accepted,
+                time_cost: 3,
+                mem_cost: 100,
+            },
+            TestcaseResult {
+                testcase: "10.in".to_string(),
+                verdict: Verdict::WrongAnswer,
+                time_cost: 4,
+                mem_cost: 200,
+            },
+        ];
+        store
+            .finish_submission(id, Verdict::WrongAnswer, "", &results)
+            .unwrap();
+
+        let record = store.get_submission(id).unwrap().unwrap();
+        assert_eq!(record.status, Verdict::WrongAnswer);
+        assert_eq!((record.time_cost, record.mem_cost), (7, 200));
+        assert!(record.finished_at.is_some());
+        assert!(store.list_unfinished().unwrap().is_empty());
+        assert_eq!(store.get_testcase_results(id).unwrap(), results);
+        assert_eq!(store.get_submission(id + 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_ids_are_not_given_again() {
+        let store = Store::open_in_memory().unwrap();
+        for _ in 1..=2 {
+            store
+                .insert_submission(1, "cpp", "", None, None, Priority::Live)
+                .unwrap();
+        }
+        let conn = store.conn.lock().unwrap();
+        conn.execute("DELETE FROM submissions WHERE id = 2", [])
+            .unwrap();
+        drop(conn);
+
+        let id = store
+            .insert_submission(1, "cpp", "", None, None, Priority::Live)
+            .unwrap();
+        assert_eq!(id, 3);
+    }
+
+    #[test]
+    fn test_migrations_keep_results() {
+        let conn = Connection::open_in_memory().unwrap();
+        let previous = MIGRATIONS.len() - 1;
+        for migration in &MIGRATIONS[..previous] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", previous).unwrap();
+        conn.execute(
+            "INSERT INTO submissions (id, problem_id, lang, source, status, created_at, updated_at)
+             VALUES (7, 1, 'cpp', '', 'Accepted', 0, 0)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO testcase_results (submission_id, testcase, verdict, time_cost, mem_cost)
+             VALUES (?1, '1.in', 'Accepted', 1, 2)",
+            params![7],
+        )
+        .unwrap();
+
+        let store = Store::init(conn).unwrap();
+        assert_eq!(store.get_testcase_results(7).unwrap().len(), 1);
+        let id = store
+            .insert_submission(1, "cpp", "", None, None, Priority::Live)
+            .unwrap();
+        assert_eq!(id, 8);
+    }
+
+    #[test]
+    fn test_cancel_unfinished() {
+        let store = Store::open_in_memory().unwrap();
+        for _ in 1..=2 {
+            store
+                .insert_submission(1, "cpp", "", None, None, Priority::Live)
+                .unwrap();
+        }
+        store
+            .finish_submission(2, Verdict::Accepted, "", &[])
+            .unwrap();
+
+        assert!(store.cancel_unfinished(1).unwrap());
+        assert!(!store.cancel_unfinished(2).unwrap());
+        assert!(!store.cancel_unfinished(3).unwrap());
+        let status = |id| store.get_submission(id).unwrap().unwrap().status;
+        assert_eq!(
+            (status(1), status(2)),
+            (Verdict::Cancelled, Verdict::Accepted)
+        );
+    }
+
+    #[test]
+    fn test_list_submissions() {
+        let store = Store::open_in_memory().unwrap();
+        for id in 1..=5 {
+            let lang = if id % 2 == 0 { "cpp" } else { "python" };
+            let owner = (id % 2 == 0).then_some("alice");
+            let inserted = store.insert_submission(id % 2, lang, "", None, owner, Priority::Live);
+            assert_eq!(inserted.unwrap(), id);
+        }
+        store
+            .finish_submission(4, Verdict::Accepted, "", &[])
+            .unwrap();
+        let ids = |filter: SubmissionFilter| -> Vec<u64> {
+            let records = store.list_submissions(&filter).unwrap();
+            records.into_iter().map(|record| record.id).collect()
+        };
+
+        assert_eq!(ids(SubmissionFilter::default()), vec![5, 4, 3, 2, 1]);
+        let cpp = SubmissionFilter {
+            lang: Some("cpp".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(cpp), vec![4, 2]);
+        let accepted = SubmissionFilter {
+            status: Some(Verdict::Accepted),
+            ..Default::default()
+        };
+        assert_eq!(ids(accepted), vec![4]);
+        let alice = SubmissionFilter {
+            owner: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(alice), vec![4, 2]);
+        assert_eq!(store.count_unfinished("alice").unwrap(), 1);
+
+        let first_page = SubmissionFilter {
+            problem_id: Some(1),
+            limit: 2,
+            ..Default::default()
+        };
+        assert_eq!(ids(first_page.clone()), vec![5, 3]);
+        let second_page = SubmissionFilter {
+            cursor: Some(3),
+            ..first_page
+        };
+        assert_eq!(ids(second_page), vec![1]);
+    }
+
+    #[test]
+    fn test_insert_submissions_all_or_none() {
+        let store = Store::open_in_memory().unwrap();
+        let submission = |source| NewSubmission {
+            problem_id: 1,
+            lang: "cpp",
+            source,
+            callback_url: None,
+            owner: Some("alice"),
+        };
+        let ids = store
+            .insert_submissions(&[submission("a"), submission("b")], Priority::Batch)
+            .unwrap();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(
+            store.get_submission(2).unwrap().unwrap().priority,
+            Priority::Batch
+        );
+
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER refuse BEFORE INSERT ON submissions WHEN NEW.source = 'bad'
+                 BEGIN SELECT RAISE(ABORT, 'refused'); END;",
+            )
+            .unwrap();
+        let batch = [submission("c"), submission("bad")];
+        assert!(store.insert_submissions(&batch, Priority::Batch).is_err());
+        assert_eq!(store.list_unfinished().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rejudge_report() {
+        let store = Store::open_in_memory().unwrap();
+        for _ in 1..=2 {
+            let id = store
+                .insert_submission(1, "cpp", "", None, None, Priority::Batch)
+                .unwrap();
+            store
+                .finish_submission(id, Verdict::Accepted, "", &[])
+                .unwrap();
+        }
+        // still waiting, left alone
+        store
+            .insert_submission(1, "cpp", "", None, None, Priority::Live)
+            .unwrap();
+
+        let (rejudge_id, taken) = store.create_rejudge("fixed 1.out", &[1, 2, 3]).unwrap();
+        assert_eq!(taken, vec![1, 2]);
+        let unfinished = store.list_unfinished().unwrap();
+        let priorities: Vec<_> = unfinished.iter().map(|record| record.priority).collect();
+        assert_eq!(
+            priorities,
+            vec![Priority::Rejudge, Priority::Rejudge, Priority::Live]
+        );
+        // a second rejudge doesn't take them again
+        assert_eq!(
+            store.create_rejudge("", &[1, 2]).unwrap().1,
+            Vec::<u64>::new()
+        );
+
+        store
+            .finish_submission(1, Verdict::WrongAnswer, "", &[])
+            .unwrap();
+        let report = store.get_rejudge_report(rejudge_id).unwrap().unwrap();
+        assert_eq!(report.reason, "fixed 1.out");
+        assert!(!report.is_finished());
+
+        store
+            .finish_submission(2, Verdict::Accepted, "", &[])
+            .unwrap();
+        let report = store.get_rejudge_report(rejudge_id).unwrap().unwrap();
+        assert!(report.is_finished());
+        let changed = report.get_changed();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].submission_id, 1);
+        assert_eq!(changed[0].new_status, Some(Verdict::WrongAnswer));
+        assert_eq!(store.get_rejudge_report(rejudge_id + 2).unwrap(), None);
+    }
+}
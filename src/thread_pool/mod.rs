@@ -1,24 +1,77 @@
+pub mod queue;
 pub mod tests;
 pub mod thread_pool_builder;
 
 use std::{
+    collections::{BTreeMap, HashMap},
     fmt::Debug,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
-        mpsc::{channel, Receiver, Sender},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc, Condvar, Mutex,
     },
     thread,
+    time::{Duration, Instant},
 };
 
+use toml::{Table, Value};
+
+use crate::config::Config;
 use crate::judge::{
+    cancel::WeakCancellationToken,
     compiler,
+    consts::{DEFAULT_MAX_ATTEMPTS, DEFAULT_MAX_QUEUED},
+    events::{EventBus, JudgeEvent},
+    file,
+    playground::{self, Playground, RunOutput},
     runner::{self, RunnerJob},
+    sandbox::{self, Sandbox},
     task::Task,
+    workspace::WorkspaceManager,
+    Verdict,
 };
+use crate::store::{self, NewSubmission, Store, SubmissionRecord};
+use crate::webhook::Webhooks;
+
+use queue::{JobQueue, Priority};
+
+#[derive(Debug)]
+pub enum Error {
+    /// `max_queued` submissions, or playground runs, are queued or being
+    /// judged already
+    QueueFull,
+    /// the pool is being shut down, see [`ThreadPool::shutdown`]
+    ShuttingDown,
+    StoreError(store::Error),
+}
+
+impl From<store::Error> for Error {
+    fn from(e: store::Error) -> Error {
+        Error::StoreError(e)
+    }
+}
 
 type Thunk<'a> = Box<dyn FnOnce() -> Vec<RunnerJob> + Send + 'a>;
 
+pub struct QueuedJob {
+    /// `None` for jobs judging no submission, e.g. playground runs
+    pub submission_id: Option<u64>,
+    /// a testcase of a submission that's already compiled
+    pub started: bool,
+    pub run: Thunk<'static>,
+}
+
+/// What [`ThreadPool::shutdown`] left for the next start.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// submissions dropped from the queue before they were started
+    pub skipped: usize,
+    /// submissions whose judging was interrupted at the deadline
+    pub interrupted: Vec<u64>,
+}
+
+/// How long interrupted workers get to return, killing runs is quick.
+const INTERRUPT_GRACE: Duration = Duration::from_secs(5);
+
 struct Sentinel<'a> {
     id: usize,
     shared_data: &'a Arc<SharedData>,
@@ -42,6 +95,10 @@ impl<'a> Sentinel<'a> {
 impl<'a> Drop for Sentinel<'a> {
     fn drop(&mut self) {
         if self.active {
+            self.shared_data.running.lock().unwrap().remove(&self.id);
+            self.shared_data
+                .alive_thread_count
+                .fetch_sub(1, Ordering::SeqCst);
             self.shared_data
                 .active_thread_count
                 .fetch_sub(1, Ordering::SeqCst);
@@ -57,23 +114,45 @@ impl<'a> Drop for Sentinel<'a> {
 }
 
 pub struct SharedData {
-    pub job_sender: Arc<Sender<Thunk<'static>>>,
-    pub job_receiver: Mutex<Receiver<Thunk<'static>>>,
+    pub job_queue: JobQueue<QueuedJob>,
 
     pub global_compiler: Arc<compiler::Compiler>,
     pub global_runner: Arc<runner::Runner>,
+    pub global_workspaces: Arc<WorkspaceManager>,
+    pub global_playground: Arc<Playground>,
+    pub global_store: Option<Arc<Store>>,
+    pub global_webhooks: Option<Arc<Webhooks>>,
+    /// Progress of the submissions of this pool.
+    pub events: Arc<EventBus>,
+    /// Given to the tasks sent without a submission id. It isn't persisted,
+    /// so without a store the ids start over at 1 on every start, those of
+    /// the submissions of a previous process included.
+    pub next_submission_id: AtomicU64,
+    pub max_attempts: u64,
+    /// 0 for no cap
+    pub max_queued: u64,
+    /// Tokens of the submissions queued or being judged, by submission id.
+    pub cancellations: Mutex<HashMap<u64, WeakCancellationToken>>,
+    /// Submissions let through `max_queued` that aren't queued yet.
+    pub reserved_queue_slots: Mutex<usize>,
+    /// The submission each busy worker is judging, by worker id.
+    pub running: Mutex<BTreeMap<usize, u64>>,
 
     pub empty_trigger: Mutex<()>,
     pub empty_condvar: Condvar,
     pub join_times: AtomicUsize,
 
     pub max_thread_count: AtomicUsize,
+    /// workers spawned and not exited, busy or not
+    pub alive_thread_count: AtomicUsize,
     pub active_thread_count: AtomicUsize,
     pub panic_thread_count: AtomicUsize,
     pub queued_job_count: AtomicUsize,
 
     pub is_all_done: AtomicBool,
     pub is_all_active: AtomicBool,
+    /// new submissions are refused
+    pub is_shutting_down: AtomicBool,
 }
 
 impl SharedData {
@@ -90,40 +169,120 @@ impl SharedData {
 }
 
 pub struct ThreadPool {
-    pub job_sender: Arc<Sender<Thunk<'static>>>,
     pub shared_data: Arc<SharedData>,
 }
 
+/// Submissions let through by [`ThreadPool::reserve_queue_slots`], to be
+/// held until they're queued. Counted twice for a moment after that, which
+/// errs on the side of the cap.
+struct QueueReservation<'a> {
+    shared_data: &'a SharedData,
+    count: usize,
+}
+
+impl Drop for QueueReservation<'_> {
+    fn drop(&mut self) {
+        *self.shared_data.reserved_queue_slots.lock().unwrap() -= self.count;
+    }
+}
+
+/// `[queue]` in the config.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct QueueConfig {
+    /// times judging of a submission may be started
+    pub max_attempts: u64,
+    /// submissions queued or being judged, 0 for no cap
+    pub max_queued: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> QueueConfig {
+        QueueConfig::from_config(&Table::new())
+    }
+}
+
+impl QueueConfig {
+    pub fn from_config(data: &Table) -> QueueConfig {
+        let queue = match data.get("queue") {
+            Some(Value::Table(queue)) => queue.clone(),
+            None => Table::new(),
+            _ => panic!("config: [queue] should be set correctly"),
+        };
+        let get_u64 = |key: &str, default: u64, min: i64| match queue.get(key) {
+            Some(Value::Integer(val)) if *val >= min => *val as u64,
+            None => default,
+            _ => panic!("config: [queue] should be set correctly"),
+        };
+
+        QueueConfig {
+            max_attempts: get_u64("max_attempts", DEFAULT_MAX_ATTEMPTS, 1),
+            max_queued: get_u64("max_queued", DEFAULT_MAX_QUEUED, 0),
+        }
+    }
+}
+
 impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
-        let (sender, receiver) = channel::<Thunk<'static>>();
-        let sender = Arc::new(sender);
+    pub fn new(
+        config: &Config,
+        size: usize,
+        sandbox: Option<Arc<dyn Sandbox>>,
+        store: Option<Arc<Store>>,
+        webhooks: Option<Arc<Webhooks>>,
+        queue: QueueConfig,
+    ) -> ThreadPool {
+        // one sandbox, and cgroup controller, for compiling and running
+        let sandbox = sandbox
+            .unwrap_or_else(|| sandbox::from_config(&config.sandbox, &config.workspace.root));
+        let global_compiler = Arc::new(compiler::Compiler::new(config, sandbox.clone()));
+        let global_runner = Arc::new(runner::Runner::new(config, sandbox));
+        let global_workspaces = Arc::new(WorkspaceManager::from_config(&config.workspace));
+        let global_playground = Arc::new(
+            Playground::new(
+                global_compiler.clone(),
+                global_runner.clone(),
+                global_workspaces.clone(),
+            )
+            .with_limits(config.playground),
+        );
         let shared_data = Arc::new(SharedData {
-            job_sender: sender.clone(),
-            job_receiver: Mutex::new(receiver),
-            global_compiler: Arc::new(compiler::Compiler::default()),
-            global_runner: Arc::new(runner::Runner::default()),
+            job_queue: JobQueue::new(),
+            global_compiler,
+            global_runner,
+            global_workspaces,
+            global_playground,
+            global_store: store,
+            global_webhooks: webhooks,
+            events: Arc::new(EventBus::default()),
+            next_submission_id: AtomicU64::new(1),
+            max_attempts: queue.max_attempts,
+            max_queued: queue.max_queued,
+            cancellations: Mutex::new(HashMap::new()),
+            reserved_queue_slots: Mutex::new(0),
+            running: Mutex::new(BTreeMap::new()),
             empty_trigger: Mutex::new(()),
             empty_condvar: Condvar::new(),
             join_times: AtomicUsize::new(0),
             max_thread_count: AtomicUsize::new(size),
+            alive_thread_count: AtomicUsize::new(0),
             active_thread_count: AtomicUsize::new(0),
             panic_thread_count: AtomicUsize::new(0),
             queued_job_count: AtomicUsize::new(0),
             is_all_done: AtomicBool::new(false),
             is_all_active: AtomicBool::new(false),
+            is_shutting_down: AtomicBool::new(false),
         });
 
-        ThreadPool {
-            job_sender: sender,
-            shared_data,
-        }
+        ThreadPool { shared_data }
     }
 
     pub fn max_thread_count(&self) -> usize {
         self.shared_data.max_thread_count.load(Ordering::Relaxed)
     }
 
+    pub fn alive_thread_count(&self) -> usize {
+        self.shared_data.alive_thread_count.load(Ordering::SeqCst)
+    }
+
     pub fn active_thread_count(&self) -> usize {
         self.shared_data.active_thread_count.load(Ordering::SeqCst)
     }
@@ -140,6 +299,20 @@ impl ThreadPool {
         self.shared_data.is_idle()
     }
 
+    /// The compilers and interpreters submissions are judged with.
+    pub fn get_toolchains(&self) -> Vec<String> {
+        let mut programs = self.shared_data.global_compiler.get_programs();
+        programs.extend(self.shared_data.global_runner.get_programs());
+        programs.sort();
+        programs.dedup();
+        programs
+    }
+
+    /// The submission each busy worker is judging, by worker id.
+    pub fn running_submissions(&self) -> BTreeMap<usize, u64> {
+        self.shared_data.running.lock().unwrap().clone()
+    }
+
     pub fn awake_all(&self) {
         self.shared_data.is_all_active.store(true, Ordering::SeqCst);
     }
@@ -152,10 +325,13 @@ impl ThreadPool {
 
     pub fn stop_all(&self, unconditional: bool) {
         if unconditional {
-            let lock = self.shared_data.job_receiver.lock().unwrap();
-            while lock.recv().is_ok() {}
+            let dropped = self.shared_data.job_queue.clear();
+            self.shared_data
+                .queued_job_count
+                .fetch_sub(dropped, Ordering::SeqCst);
         }
         self.shared_data.is_all_done.store(true, Ordering::SeqCst);
+        self.shared_data.job_queue.close();
     }
 
     pub fn join(&self) {
@@ -182,31 +358,392 @@ impl ThreadPool {
     where
         F: FnOnce() -> Vec<RunnerJob> + Send + 'static,
     {
+        self.send_job_with_priority(Priority::Live, job);
+    }
+
+    /// Jobs of a lower priority run less often while higher ones are queued,
+    /// see [`queue::WEIGHTS`].
+    pub fn send_job_with_priority<F>(&self, priority: Priority, job: F)
+    where
+        F: FnOnce() -> Vec<RunnerJob> + Send + 'static,
+    {
+        self.push_job(priority, None, Box::new(job));
+    }
+
+    fn push_job(&self, priority: Priority, submission_id: Option<u64>, run: Thunk<'static>) {
         self.shared_data
             .queued_job_count
             .fetch_add(1, Ordering::SeqCst);
-        self.job_sender.send(Box::new(job)).unwrap();
+        self.shared_data.job_queue.push(
+            priority,
+            QueuedJob {
+                submission_id,
+                started: false,
+                run,
+            },
+        );
+    }
+
+    /// Refuses new submissions and rejudges from now on, see
+    /// [`ThreadPool::shutdown`].
+    pub fn stop_accepting(&self) {
+        self.shared_data
+            .is_shutting_down
+            .store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shared_data.is_shutting_down.load(Ordering::SeqCst)
     }
 
-    pub fn send_task(&self, task: Task) {
+    /// Stops the pool for good. Submissions not started yet are dropped,
+    /// started ones get until `deadline` to finish, then the rest is
+    /// interrupted. Neither gets a verdict: with a store they're left pending
+    /// for [`ThreadPool::recover`] after the restart, and their event streams
+    /// end with [`JudgeEvent::Requeued`]. Webhooks due until `deadline` are
+    /// still delivered, the rest is dead-lettered. Blocks until the workers
+    /// and the webhooks are done.
+    pub fn shutdown(&self, deadline: Duration) -> ShutdownSummary {
+        let shutdown_at = Instant::now();
+        self.stop_accepting();
+        let skipped = self.shared_data.job_queue.retain(|job| job.started);
+        self.shared_data
+            .queued_job_count
+            .fetch_sub(skipped.len(), Ordering::SeqCst);
+        let skipped: Vec<u64> = skipped
+            .into_iter()
+            .filter_map(|job| job.submission_id)
+            .collect();
+
+        let mut interrupted = vec![];
+        if !self.wait_idle(deadline) {
+            let cancellations = self.shared_data.cancellations.lock().unwrap();
+            for (submission_id, token) in cancellations.iter() {
+                if let Some(token) = token.upgrade() {
+                    token.interrupt();
+                    interrupted.push(*submission_id);
+                }
+            }
+            interrupted.sort();
+        }
+
+        self.stop_all(true);
+        let started_at = Instant::now();
+        while self.alive_thread_count() > 0 {
+            if started_at.elapsed() > INTERRUPT_GRACE {
+                tracing::warn!(
+                    "queue: {} workers didn't stop in time",
+                    self.alive_thread_count()
+                );
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        // testcases queued by the workers meanwhile
+        let dropped = self.shared_data.job_queue.clear();
+        self.shared_data
+            .queued_job_count
+            .fetch_sub(dropped, Ordering::SeqCst);
+
+        if let Some(store) = &self.shared_data.global_store {
+            for submission_id in &interrupted {
+                if let Err(e) = store.interrupt_judging(*submission_id) {
+                    tracing::warn!("store: can't update submission {submission_id}: {e:?}");
+                }
+            }
+        }
+        // the interrupted ones whose workers didn't get to it
+        for submission_id in skipped.iter().chain(&interrupted) {
+            self.shared_data
+                .events
+                .publish(*submission_id, JudgeEvent::Requeued);
+        }
+        if let Some(webhooks) = &self.shared_data.global_webhooks {
+            webhooks.shutdown(deadline.saturating_sub(shutdown_at.elapsed()));
+        }
+        ShutdownSummary {
+            skipped: skipped.len(),
+            interrupted,
+        }
+    }
+
+    /// Waits until nothing is queued or running, false if it took longer
+    /// than `timeout`.
+    fn wait_idle(&self, timeout: Duration) -> bool {
+        let started_at = Instant::now();
+        let mut trigger = self.shared_data.empty_trigger.lock().unwrap();
+        while !self.is_idle() {
+            let Some(left) = timeout.checked_sub(started_at.elapsed()) else {
+                return false;
+            };
+            // the condvar is notified without the lock, so it's polled too
+            let poll = left.min(Duration::from_millis(50));
+            trigger = self
+                .shared_data
+                .empty_condvar
+                .wait_timeout(trigger, poll)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+
+    /// Where the progress of the submissions judged by this pool goes.
+    pub fn events(&self) -> Arc<EventBus> {
+        self.shared_data.events.clone()
+    }
+
+    /// Submissions queued or being judged.
+    pub fn queued_task_count(&self) -> usize {
+        let cancellations = self.shared_data.cancellations.lock().unwrap();
+        // the tokens of finished submissions are gone with their jobs
+        cancellations
+            .values()
+            .filter(|token| token.is_alive())
+            .count()
+    }
+
+    /// Whether `count` more submissions fit below `max_queued`, counting the
+    /// ones let through that aren't queued yet.
+    pub fn has_room_for(&self, count: usize) -> bool {
+        let reserved = self.shared_data.reserved_queue_slots.lock().unwrap();
+        self.fits(*reserved + count)
+    }
+
+    fn fits(&self, count: usize) -> bool {
+        let max_queued = self.shared_data.max_queued as usize;
+        max_queued == 0 || self.queued_task_count() + count <= max_queued
+    }
+
+    /// Lets `count` more submissions through `max_queued`. The room is
+    /// checked and taken under one lock, so concurrent enqueues can't both
+    /// pass.
+    fn reserve_queue_slots(&self, count: usize) -> Result<QueueReservation<'_>, Error> {
+        let mut reserved = self.shared_data.reserved_queue_slots.lock().unwrap();
+        if !self.fits(*reserved + count) {
+            return Err(Error::QueueFull);
+        }
+        *reserved += count;
+        Ok(QueueReservation {
+            shared_data: &self.shared_data,
+            count,
+        })
+    }
+
+    /// Persists the submission as pending before queueing it, so that it's
+    /// judged even if this process dies first. Returns its submission id.
+    pub fn enqueue_task(&self, task: Task) -> Result<u64, Error> {
+        self.enqueue_task_with_priority(Priority::Live, task)
+    }
+
+    /// Like [`ThreadPool::enqueue_task`], see [`ThreadPool::send_task_with_priority`].
+    pub fn enqueue_task_with_priority(&self, priority: Priority, task: Task) -> Result<u64, Error> {
+        if self.is_shutting_down() {
+            return Err(Error::ShuttingDown);
+        }
+        let _reservation = self.reserve_queue_slots(1)?;
+        let mut task = task;
+        if let Some(store) = &self.shared_data.global_store {
+            let submission_id = store.insert_submission(
+                task.get_problem_id(),
+                task.get_lang(),
+                task.get_source_code(),
+                task.get_callback_url(),
+                task.get_owner(),
+                priority,
+            )?;
+            task.set_submission_id(submission_id);
+        }
+        Ok(self.send_task_with_priority(priority, task))
+    }
+
+    /// Like [`ThreadPool::enqueue_task_with_priority`] for many tasks, which
+    /// are persisted in one transaction: either all of them are queued or
+    /// none. Returns their submission ids in order.
+    pub fn enqueue_tasks_with_priority(
+        &self,
+        priority: Priority,
+        tasks: Vec<Task>,
+    ) -> Result<Vec<u64>, Error> {
+        if self.is_shutting_down() {
+            return Err(Error::ShuttingDown);
+        }
+        let _reservation = self.reserve_queue_slots(tasks.len())?;
+        let mut tasks = tasks;
+        if let Some(store) = &self.shared_data.global_store {
+            let submissions: Vec<NewSubmission> = tasks
+                .iter()
+                .map(|task| NewSubmission {
+                    problem_id: task.get_problem_id(),
+                    lang: task.get_lang(),
+                    source: task.get_source_code(),
+                    callback_url: task.get_callback_url(),
+                    owner: task.get_owner(),
+                })
+                .collect();
+            let ids = store.insert_submissions(&submissions, priority)?;
+            for (task, submission_id) in tasks.iter_mut().zip(ids) {
+                task.set_submission_id(submission_id);
+            }
+        }
+        Ok(tasks
+            .into_iter()
+            .map(|task| self.send_task_with_priority(priority, task))
+            .collect())
+    }
+
+    /// Queues unfinished submissions left in the store by a previous process,
+    /// at the priority they were queued with. The ones whose judging was
+    /// already started `max_attempts` times are given up on as system errors.
+    /// Returns how many were queued.
+    pub fn recover(&self) -> Result<usize, store::Error> {
+        let Some(store) = &self.shared_data.global_store else {
+            return Ok(0);
+        };
+        let max_attempts = self.shared_data.max_attempts;
+
+        let mut recovered = 0;
+        for record in store.list_unfinished()? {
+            if record.attempts >= max_attempts {
+                tracing::error!(
+                    "queue: submission {} crashed the judge {} times, giving up",
+                    record.id,
+                    record.attempts
+                );
+                let info = format!("judging was interrupted {} times", record.attempts);
+                store.finish_submission(record.id, Verdict::SystemError, &info, &[])?;
+                continue;
+            }
+            self.send_task_with_priority(record.priority, task_from_record(&record));
+            recovered += 1;
+        }
+
+        Ok(recovered)
+    }
+
+    /// Judges finished submissions again below live submissions, keeping
+    /// their old verdicts in a report. Submissions still waiting or being
+    /// judged when the store takes them are left alone. Returns the id of the
+    /// rejudge and how many were queued.
+    pub fn rejudge(
+        &self,
+        reason: &str,
+        records: Vec<SubmissionRecord>,
+    ) -> Result<(u64, usize), Error> {
+        if self.is_shutting_down() {
+            return Err(Error::ShuttingDown);
+        }
+        let Some(store) = &self.shared_data.global_store else {
+            return Err(store::Error::Unavailable.into());
+        };
+
+        let ids: Vec<u64> = records.iter().map(|record| record.id).collect();
+        let (rejudge_id, taken) = store.create_rejudge(reason, &ids)?;
+        for record in records.iter().filter(|record| taken.contains(&record.id)) {
+            self.send_task_with_priority(Priority::Rejudge, task_from_record(record));
+        }
+
+        Ok((rejudge_id, taken.len()))
+    }
+
+    /// Queues the task in memory only, returns its submission id. A task
+    /// without one is given the next one of this pool, counted from 1, which
+    /// only makes sense without a store.
+    pub fn send_task(&self, task: Task) -> u64 {
+        self.send_task_with_priority(Priority::Live, task)
+    }
+
+    /// Like [`ThreadPool::send_task`], its testcases get the same priority.
+    pub fn send_task_with_priority(&self, priority: Priority, mut task: Task) -> u64 {
+        if !task.has_submission_id() {
+            let submission_id = self
+                .shared_data
+                .next_submission_id
+                .fetch_add(1, Ordering::SeqCst);
+            task.set_submission_id(submission_id);
+        }
+        let submission_id = task.get_submission_id();
+        {
+            let mut cancellations = self.shared_data.cancellations.lock().unwrap();
+            // the tokens of finished submissions are gone with their jobs
+            cancellations.retain(|_, token| token.is_alive());
+            cancellations.insert(submission_id, task.get_cancellation_token().downgrade());
+        }
+        self.shared_data
+            .events
+            .publish(submission_id, JudgeEvent::Queued);
         let shared_data = self.shared_data.clone();
-        self.send_job(move || {
+        let job = move || {
             let result = task.execute(
                 shared_data.global_compiler.clone(),
                 shared_data.global_runner.clone(),
+                shared_data.global_workspaces.clone(),
+                shared_data.global_store.clone(),
+                shared_data.global_webhooks.clone(),
+                shared_data.events.clone(),
             );
 
-            if let Ok(runner_jobs) = result {
-                runner_jobs
-            } else {
-                // dbg!(&result.unwrap_err());
-                vec![]
-            }
+            // dbg!(&result.unwrap_err());
+            result.unwrap_or_default()
+        };
+        self.push_job(priority, Some(submission_id), Box::new(job));
+
+        submission_id
+    }
+
+    /// Queues a playground run like a live submission, `callback` gets its
+    /// output once it's done. Runs have a lane of their own, capped by
+    /// `[playground] max_queued`; a job dropped at shutdown never calls back.
+    pub fn run_playground<F>(
+        &self,
+        lang: &str,
+        source_code: &str,
+        stdin: &str,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(Result<RunOutput, playground::Error>) + Send + 'static,
+    {
+        if self.is_shutting_down() {
+            return Err(Error::ShuttingDown);
+        }
+        let Some(slot) = self.shared_data.global_playground.reserve() else {
+            return Err(Error::QueueFull);
+        };
+        let (lang, source_code, stdin) =
+            (lang.to_string(), source_code.to_string(), stdin.to_string());
+        self.send_job(move || {
+            let output = slot.run(&lang, &source_code, &stdin);
+            // free before answering, so that the client may run again at once
+            drop(slot);
+            callback(output);
+            vec![]
         });
+        Ok(())
+    }
+
+    /// Cancels a submission queued or being judged by this pool, it ends up
+    /// as `Cancelled`. False if there's nothing of it left to cancel.
+    pub fn cancel(&self, submission_id: u64) -> bool {
+        let cancellations = self.shared_data.cancellations.lock().unwrap();
+        match cancellations
+            .get(&submission_id)
+            .and_then(WeakCancellationToken::upgrade)
+        {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn spawn_thread(id: usize, shared_data: Arc<SharedData>) {
         let builder = thread::Builder::new();
+        // counted right away, a probe right after the pool is built sees it
+        shared_data
+            .alive_thread_count
+            .fetch_add(1, Ordering::SeqCst);
 
         builder
             .spawn(move || {
@@ -220,17 +757,9 @@ impl ThreadPool {
                         continue;
                     }
 
-                    let msg = {
-                        let lock = shared_data
-                            .job_receiver
-                            .lock()
-                            .expect(format!("Worker #{id}: unable to lock job_receiver").as_str());
-                        lock.recv()
-                    };
-
-                    let job = match msg {
-                        Ok(job) => job,
-                        Err(_) => continue,
+                    let (priority, job) = match shared_data.job_queue.pop() {
+                        Some(msg) => msg,
+                        None => continue,
                     };
 
                     shared_data
@@ -238,17 +767,33 @@ impl ThreadPool {
                         .fetch_add(1, Ordering::SeqCst);
                     shared_data.queued_job_count.fetch_sub(1, Ordering::SeqCst);
 
-                    let runner_jobs: Vec<RunnerJob> = job();
+                    if let Some(submission_id) = job.submission_id {
+                        shared_data
+                            .running
+                            .lock()
+                            .unwrap()
+                            .insert(id, submission_id);
+                    }
+                    let runner_jobs: Vec<RunnerJob> = (job.run)();
+                    shared_data.running.lock().unwrap().remove(&id);
                     // dbg!(&id, &runner_jobs);
-                    for job in runner_jobs {
+                    // pushed to the front, so in reverse to keep their order
+                    for job in runner_jobs.into_iter().rev() {
                         shared_data.queued_job_count.fetch_add(1, Ordering::SeqCst);
-                        shared_data
-                            .job_sender
-                            .send(Box::new(|| {
-                                job.execute_once().unwrap();
-                                vec![]
-                            }))
-                            .unwrap();
+                        // a started submission goes first, its workspace is waiting
+                        shared_data.job_queue.push_front(
+                            priority,
+                            QueuedJob {
+                                submission_id: Some(job.get_submission_id()),
+                                started: true,
+                                run: Box::new(|| {
+                                    if let Err(e) = job.execute_once() {
+                                        tracing::warn!("runner: testcase failed to run: {e:?}");
+                                    }
+                                    vec![]
+                                }),
+                            },
+                        );
                     }
 
                     shared_data
@@ -259,11 +804,29 @@ impl ThreadPool {
                 }
 
                 setinel.cancel();
+                shared_data
+                    .alive_thread_count
+                    .fetch_sub(1, Ordering::SeqCst);
             })
             .unwrap();
     }
 }
 
+/// A task judging a submission of the store again.
+fn task_from_record(record: &SubmissionRecord) -> Task {
+    let task = Task::with_submission_id(
+        record.id,
+        record.problem_id,
+        &file::get_testcases_path(record.problem_id),
+        &record.lang,
+        &record.source,
+    );
+    match &record.callback_url {
+        Some(callback_url) => task.with_callback_url(callback_url),
+        None => task,
+    }
+}
+
 impl Debug for ThreadPool {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ThreadPool")
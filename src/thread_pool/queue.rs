@@ -0,0 +1,217 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+};
+
+/// Lower values are served more often, see [`WEIGHTS`].
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Priority {
+    Live = 0,
+    /// bulk imports
+    Batch,
+    Rejudge,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Live => "Live",
+            Priority::Batch => "Batch",
+            Priority::Rejudge => "Rejudge",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Priority> {
+        let priority = match s {
+            "Live" => Priority::Live,
+            "Batch" => Priority::Batch,
+            "Rejudge" => Priority::Rejudge,
+            _ => return None,
+        };
+        Some(priority)
+    }
+}
+
+const PRIORITY_COUNT: usize = 3;
+
+/// Jobs popped per round by priority while every queue has some, so that a
+/// steady stream of live submissions slows batches and rejudges down
+/// without starving them.
+pub const WEIGHTS: [u32; PRIORITY_COUNT] = [4, 2, 1];
+
+struct Queues<T> {
+    queues: [VecDeque<T>; PRIORITY_COUNT],
+    /// jobs popped by priority in this round
+    served: [u32; PRIORITY_COUNT],
+    closed: bool,
+}
+
+impl<T> Queues<T> {
+    /// The highest priority with jobs and some of its weight left, a new
+    /// round begins once there's none.
+    fn next(&mut self) -> Option<(Priority, T)> {
+        for _ in 0..2 {
+            for (index, priority) in [Priority::Live, Priority::Batch, Priority::Rejudge]
+                .into_iter()
+                .enumerate()
+            {
+                if self.served[index] >= WEIGHTS[index] {
+                    continue;
+                }
+                if let Some(job) = self.queues[index].pop_front() {
+                    self.served[index] += 1;
+                    return Some((priority, job));
+                }
+            }
+            self.served = [0; PRIORITY_COUNT];
+        }
+        None
+    }
+}
+
+/// A blocking multi-producer multi-consumer queue with a FIFO per priority,
+/// served by weighted round robin.
+pub struct JobQueue<T> {
+    queues: Mutex<Queues<T>>,
+    condvar: Condvar,
+}
+
+impl<T> Default for JobQueue<T> {
+    fn default() -> JobQueue<T> {
+        JobQueue {
+            queues: Mutex::new(Queues {
+                queues: Default::default(),
+                served: [0; PRIORITY_COUNT],
+                closed: false,
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+}
+
+impl<T> JobQueue<T> {
+    pub fn new() -> JobQueue<T> {
+        JobQueue::default()
+    }
+
+    pub fn push(&self, priority: Priority, job: T) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.queues[priority as usize].push_back(job);
+        self.condvar.notify_one();
+    }
+
+    /// Queues `job` ahead of everything else of the same priority, used for
+    /// the testcases of a submission that has already started.
+    pub fn push_front(&self, priority: Priority, job: T) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.queues[priority as usize].push_front(job);
+        self.condvar.notify_one();
+    }
+
+    /// Blocks until a job is available, `None` once the queue is closed.
+    pub fn pop(&self) -> Option<(Priority, T)> {
+        let mut queues = self.queues.lock().unwrap();
+        loop {
+            if queues.closed {
+                return None;
+            }
+            if let Some(job) = queues.next() {
+                return Some(job);
+            }
+            queues = self.condvar.wait(queues).unwrap();
+        }
+    }
+
+    /// Drops every queued job, returns how many there were.
+    pub fn clear(&self) -> usize {
+        let mut queues = self.queues.lock().unwrap();
+        queues
+            .queues
+            .iter_mut()
+            .map(|queue| queue.drain(..).count())
+            .sum()
+    }
+
+    /// Keeps the jobs `f` is true for, returns the others.
+    pub fn retain<F>(&self, mut f: F) -> Vec<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut queues = self.queues.lock().unwrap();
+        let mut removed = vec![];
+        for queue in queues.queues.iter_mut() {
+            for job in std::mem::take(queue) {
+                if f(&job) {
+                    queue.push_back(job);
+                } else {
+                    removed.push(job);
+                }
+            }
+        }
+        removed
+    }
+
+    /// Wakes up every consumer, `pop` returns `None` from now on.
+    pub fn close(&self) {
+        self.queues.lock().unwrap().closed = true;
+        self.condvar.notify_all();
+    }
+
+    pub fn len(&self) -> usize {
+        let queues = self.queues.lock().unwrap();
+        queues.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobQueue, Priority};
+
+    #[test]
+    fn test_priorities() {
+        let queue = JobQueue::new();
+        queue.push(Priority::Rejudge, 1);
+        queue.push(Priority::Batch, 6);
+        queue.push(Priority::Live, 2);
+        queue.push(Priority::Live, 3);
+        queue.push_front(Priority::Live, 4);
+
+        assert_eq!(queue.pop(), Some((Priority::Live, 4)));
+        assert_eq!(queue.pop(), Some((Priority::Live, 2)));
+        assert_eq!(queue.pop(), Some((Priority::Live, 3)));
+        assert_eq!(queue.pop(), Some((Priority::Batch, 6)));
+        assert_eq!(queue.pop(), Some((Priority::Rejudge, 1)));
+
+        queue.push(Priority::Live, 5);
+        queue.push(Priority::Batch, 7);
+        queue.push(Priority::Batch, 8);
+        assert_eq!(queue.retain(|job| *job != 7), vec![7]);
+        assert_eq!(queue.pop(), Some((Priority::Live, 5)));
+        assert_eq!(queue.clear(), 1);
+        queue.close();
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_lower_priorities_are_not_starved() {
+        let queue = JobQueue::new();
+        for job in 0..10 {
+            queue.push(Priority::Live, job);
+        }
+        queue.push(Priority::Batch, 10);
+        queue.push(Priority::Batch, 11);
+        queue.push(Priority::Batch, 12);
+        queue.push(Priority::Rejudge, 13);
+        queue.push(Priority::Rejudge, 14);
+
+        let order: Vec<i32> = (0..15).map(|_| queue.pop().unwrap().1).collect();
+        assert_eq!(
+            order,
+            vec![0, 1, 2, 3, 10, 11, 13, 4, 5, 6, 7, 12, 14, 8, 9]
+        );
+    }
+}
@@ -1,11 +1,32 @@
 #[cfg(test)]
 mod test {
-    use crate::judge::runner::RunnerJob;
-    use crate::{judge::task::Task, thread_pool::thread_pool_builder::ThreadPoolBuilder};
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use crate::config::Config;
+    use crate::judge::consts::CONFIG_PATH;
+    use crate::judge::{
+        events::JudgeEvent,
+        runner::RunnerJob,
+        sandbox::{FakeSandbox, KillReason, SandboxResult},
+        Verdict,
+    };
+    use crate::store::{Store, SubmissionFilter};
+    use crate::{
+        judge::task::Task,
+        thread_pool::{queue::Priority, thread_pool_builder::ThreadPoolBuilder, Error},
+    };
+
+    fn config() -> Config {
+        Config::load(CONFIG_PATH)
+    }
 
     #[test]
     fn thread_pool_join() {
-        let thread_pool = ThreadPoolBuilder::new().build();
+        let thread_pool = ThreadPoolBuilder::new(&config()).build();
         for _ in 0..50 {
             let task = Task::new(
                 1,
@@ -24,7 +45,7 @@ mod test {
 
     #[test]
     fn test_1359() {
-        let thread_pool = ThreadPoolBuilder::new().build();
+        let thread_pool = ThreadPoolBuilder::new(&config()).build();
         for _ in 0..1 {
             let task = Task::new(
                 1,
@@ -167,10 +188,10 @@ mod test {
         assert_eq!(thread_pool.active_thread_count(), 0);
         assert_eq!(thread_pool.queued_job_count(), 0);
     }
-    
+
     #[test]
     fn thread_pool_thread_panic() {
-        let thread_pool = ThreadPoolBuilder::new().build();
+        let thread_pool = ThreadPoolBuilder::new(&config()).build();
         for i in 0..20 {
             if i % 4 == 0 {
                 thread_pool.send_job(|| -> Vec<RunnerJob> { panic!() })
@@ -192,4 +213,348 @@ mod test {
         assert_eq!(thread_pool.queued_job_count(), 0);
         assert_eq!(thread_pool.panic_thread_count(), 5);
     }
+
+    #[test]
+    fn thread_pool_fake_sandbox() {
+        let sandbox = Arc::new(FakeSandbox::default());
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(sandbox.clone())
+            .build();
+        for _ in 0..10 {
+            let task = Task::new(1, "assets/1", "cpp", "int main() {}");
+            thread_pool.send_task(task);
+        }
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        assert_eq!(thread_pool.queued_job_count(), 0);
+        // one compilation and three testcases per task
+        assert_eq!(sandbox.history().len(), 40);
+    }
+
+    #[test]
+    fn thread_pool_records_results() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(store.clone())
+            .build();
+        // `cat` echoes the input, which is never doubled
+        let task = Task::new(1, "assets/1", "cpp", "int main() {}");
+        let submission_id = thread_pool.enqueue_task(task).unwrap();
+        let record = store.get_submission(submission_id).unwrap().unwrap();
+        assert_eq!(record.status, Verdict::Pending);
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        let record = store.get_submission(submission_id).unwrap().unwrap();
+        assert_eq!(record.status, Verdict::WrongAnswer);
+        assert!(record.finished_at.is_some());
+        assert_eq!(store.get_testcase_results(submission_id).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn thread_pool_keeps_callback_urls() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(store.clone())
+            .build();
+        let task = Task::new(1, "assets/1", "cpp", "int main() {}")
+            .with_callback_url("http://127.0.0.1:1/callback");
+        let submission_id = thread_pool.enqueue_task(task).unwrap();
+        let task = Task::new(1, "assets/1", "cpp", "int main() {}");
+        let without = thread_pool.enqueue_task(task).unwrap();
+
+        let callback_url = |id| store.get_submission(id).unwrap().unwrap().callback_url;
+        assert_eq!(
+            callback_url(submission_id).as_deref(),
+            Some("http://127.0.0.1:1/callback")
+        );
+        assert_eq!(callback_url(without), None);
+        thread_pool.awake_all();
+        thread_pool.join();
+    }
+
+    #[test]
+    fn thread_pool_recovers_unfinished() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        // left behind by a judge that crashed while judging it, twice
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+        store.start_judging(1).unwrap();
+        store.start_judging(1).unwrap();
+        // accepted but never started
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(store.clone())
+            .set_max_attempts(2)
+            .build();
+        assert_eq!(thread_pool.recover().unwrap(), 1);
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        let status = |id| store.get_submission(id).unwrap().unwrap().status;
+        assert_eq!(status(1), Verdict::SystemError);
+        assert_eq!(status(2), Verdict::WrongAnswer);
+        assert!(store.list_unfinished().unwrap().is_empty());
+    }
+
+    #[test]
+    fn thread_pool_recovers_priorities() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Rejudge)
+            .unwrap();
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+
+        let sandbox = Arc::new(FakeSandbox::default());
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_pool_size(1)
+            .set_sandbox(sandbox.clone())
+            .set_store(store.clone())
+            .build();
+        assert_eq!(thread_pool.recover().unwrap(), 2);
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        // the live one is judged first though it came second
+        let first = sandbox.history()[0].command.join(" ");
+        assert!(first.contains("sub-2"), "{first}");
+        assert!(store.list_unfinished().unwrap().is_empty());
+    }
+
+    #[test]
+    fn thread_pool_cancels_queued() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let sandbox = Arc::new(FakeSandbox::default());
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(sandbox.clone())
+            .set_store(store.clone())
+            .build();
+        let cancelled = thread_pool
+            .enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}"))
+            .unwrap();
+        let judged = thread_pool
+            .enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}"))
+            .unwrap();
+        assert!(thread_pool.cancel(cancelled));
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        let status = |id| store.get_submission(id).unwrap().unwrap().status;
+        assert_eq!(status(cancelled), Verdict::Cancelled);
+        assert_eq!(status(judged), Verdict::WrongAnswer);
+        // only the other one was compiled and run
+        assert_eq!(sandbox.history().len(), 4);
+        assert!(!thread_pool.cancel(judged));
+    }
+
+    #[test]
+    fn thread_pool_shuts_down() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        // compiles right away, runs until killed
+        let sandbox = Arc::new(FakeSandbox::new(|command, _| {
+            let cancel = command.cancel.as_ref().unwrap();
+            if command.stdin.is_none() {
+                return (String::new(), FakeSandbox::exited(0));
+            }
+            while !cancel.is_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let killed = SandboxResult {
+                killed: Some(KillReason::Cancelled),
+                ..FakeSandbox::exited(0)
+            };
+            (String::new(), killed)
+        }));
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_pool_size(1)
+            .set_sandbox(sandbox.clone())
+            .set_store(store.clone())
+            .build();
+        let started = thread_pool
+            .enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}"))
+            .unwrap();
+        let queued = thread_pool
+            .enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}"))
+            .unwrap();
+        thread_pool.awake_all();
+        while sandbox.history().len() < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let bus = thread_pool.events();
+        let mut receivers = [started, queued].map(|id| bus.subscribe(id).unwrap().1);
+
+        let summary = thread_pool.shutdown(Duration::from_millis(100));
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.interrupted, vec![started]);
+        // the event streams of both end
+        for receiver in &mut receivers {
+            let mut events = vec![];
+            while let Ok(event) = receiver.try_recv() {
+                events.push(event);
+            }
+            assert_eq!(events.last(), Some(&JudgeEvent::Requeued));
+            assert!(receiver.blocking_recv().is_none());
+        }
+        assert!(bus.is_empty());
+        assert_eq!(thread_pool.alive_thread_count(), 0);
+        assert!(matches!(
+            thread_pool.enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}")),
+            Err(Error::ShuttingDown)
+        ));
+        // both are judged again after the restart, neither attempt counts
+        let unfinished = store.list_unfinished().unwrap();
+        assert_eq!(
+            unfinished
+                .iter()
+                .map(|record| (record.id, record.status, record.attempts))
+                .collect::<Vec<_>>(),
+            vec![
+                (started, Verdict::Pending, 0),
+                (queued, Verdict::Pending, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn thread_pool_rejects_when_full() {
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(Arc::new(Store::open_in_memory().unwrap()))
+            .set_max_queued(2)
+            .build();
+        for _ in 0..2 {
+            thread_pool
+                .enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}"))
+                .unwrap();
+        }
+        assert!(!thread_pool.has_room_for(1));
+        assert!(matches!(
+            thread_pool.enqueue_task(Task::new(1, "assets/1", "cpp", "int main() {}")),
+            Err(Error::QueueFull)
+        ));
+
+        thread_pool.awake_all();
+        thread_pool.join();
+        assert!(thread_pool.has_room_for(2));
+    }
+
+    #[test]
+    fn thread_pool_caps_concurrent_enqueues() {
+        let thread_pool = Arc::new(
+            ThreadPoolBuilder::new(&config())
+                .set_sandbox(Arc::new(FakeSandbox::default()))
+                .set_store(Arc::new(Store::open_in_memory().unwrap()))
+                .set_max_queued(2)
+                .build(),
+        );
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let thread_pool = thread_pool.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let task = Task::new(1, "assets/1", "cpp", "int main() {}");
+                    thread_pool.enqueue_task(task).is_ok()
+                })
+            })
+            .collect();
+        let queued = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|queued| *queued)
+            .count();
+
+        assert_eq!(queued, 2);
+        assert_eq!(thread_pool.queued_task_count(), 2);
+        thread_pool.stop_all(true);
+    }
+
+    #[test]
+    fn thread_pool_publishes_progress() {
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .build();
+        let submission_id = thread_pool.send_task(Task::new(1, "assets/1", "cpp", "int main() {}"));
+        let bus = thread_pool.events();
+        let (history, mut receiver) = bus.subscribe(submission_id).unwrap();
+        assert_eq!(history, vec![JudgeEvent::Queued]);
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], JudgeEvent::Compiling);
+        assert!(matches!(
+            events[1],
+            JudgeEvent::Compiled { success: true, .. }
+        ));
+        assert!(matches!(
+            events[4],
+            JudgeEvent::TestcaseFinished {
+                checked: 3,
+                total: 3,
+                ..
+            }
+        ));
+        assert_eq!(
+            events[5],
+            JudgeEvent::Finished {
+                verdict: Verdict::WrongAnswer
+            }
+        );
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn thread_pool_rejudges() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        // judged before the testcases were fixed
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+        store
+            .finish_submission(1, Verdict::Accepted, "", &[])
+            .unwrap();
+        // still waiting, rejudging it would judge it twice
+        store
+            .insert_submission(1, "cpp", "int main() {}", None, None, Priority::Live)
+            .unwrap();
+
+        let thread_pool = ThreadPoolBuilder::new(&config())
+            .set_sandbox(Arc::new(FakeSandbox::default()))
+            .set_store(store.clone())
+            .build();
+        let records = store
+            .find_submissions(&SubmissionFilter {
+                problem_id: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        let (rejudge_id, queued) = thread_pool.rejudge("fixed 1.out", records).unwrap();
+        assert_eq!(queued, 1);
+        thread_pool.awake_all();
+        thread_pool.join();
+
+        let report = store.get_rejudge_report(rejudge_id).unwrap().unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert!(report.is_finished());
+        let changed = report.get_changed();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].old_status, Verdict::Accepted);
+        assert_eq!(changed[0].new_status, Some(Verdict::WrongAnswer));
+    }
 }
@@ -1,13 +1,30 @@
-use super::ThreadPool;
+use std::sync::Arc;
+
+use crate::{config::Config, judge::sandbox::Sandbox, store::Store, webhook::Webhooks};
+
+use super::{QueueConfig, ThreadPool};
 
-#[derive(Default)]
 pub struct ThreadPoolBuilder {
+    config: Config,
     pool_size: Option<usize>,
+    sandbox: Option<Arc<dyn Sandbox>>,
+    store: Option<Arc<Store>>,
+    webhooks: Option<Arc<Webhooks>>,
+    /// `[queue]`, with the overrides
+    queue: QueueConfig,
 }
 
 impl ThreadPoolBuilder {
-    pub fn new() -> ThreadPoolBuilder {
-        ThreadPoolBuilder::default()
+    /// The compiler, runner, workspaces and queue are set up as `config` says.
+    pub fn new(config: &Config) -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            config: config.clone(),
+            pool_size: None,
+            sandbox: None,
+            store: None,
+            webhooks: None,
+            queue: config.queue,
+        }
     }
 
     pub fn set_pool_size(mut self, size: usize) -> ThreadPoolBuilder {
@@ -16,10 +33,47 @@ impl ThreadPoolBuilder {
         self
     }
 
+    pub fn set_sandbox(mut self, sandbox: Arc<dyn Sandbox>) -> ThreadPoolBuilder {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Submissions and their results are recorded in `store`.
+    pub fn set_store(mut self, store: Arc<Store>) -> ThreadPoolBuilder {
+        self.store = Some(store);
+        self
+    }
+
+    /// Finished submissions are sent to their webhooks through `webhooks`.
+    pub fn set_webhooks(mut self, webhooks: Arc<Webhooks>) -> ThreadPoolBuilder {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Overrides `[queue] max_attempts`.
+    pub fn set_max_attempts(mut self, max_attempts: u64) -> ThreadPoolBuilder {
+        assert!(max_attempts > 0);
+        self.queue.max_attempts = max_attempts;
+        self
+    }
+
+    /// Overrides `[queue] max_queued`, 0 for no cap.
+    pub fn set_max_queued(mut self, max_queued: u64) -> ThreadPoolBuilder {
+        self.queue.max_queued = max_queued;
+        self
+    }
+
     pub fn build(self) -> ThreadPool {
         let pool_size = self.pool_size.unwrap_or(num_cpus::get());
 
-        let thread_pool = ThreadPool::new(pool_size);
+        let thread_pool = ThreadPool::new(
+            &self.config,
+            pool_size,
+            self.sandbox,
+            self.store,
+            self.webhooks,
+            self.queue,
+        );
 
         for i in 0..pool_size {
             ThreadPool::spawn_thread(i, thread_pool.shared_data.clone());
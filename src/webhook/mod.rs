@@ -0,0 +1,811 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use toml::{Table, Value};
+
+use crate::judge::{
+    consts::{
+        DEFAULT_WEBHOOK_BACKOFF, DEFAULT_WEBHOOK_DEAD_LETTER_PATH, DEFAULT_WEBHOOK_MAX_ATTEMPTS,
+        DEFAULT_WEBHOOK_MAX_BACKOFF, DEFAULT_WEBHOOK_TIMEOUT,
+    },
+    runner::TestcaseResult,
+    Verdict,
+};
+
+/// `sha256=<hex HMAC-SHA256 of the body>`, keyed with `[webhook] secret`.
+pub static SIGNATURE_HEADER: &str = "X-CoffeeOJ-Signature";
+/// The submission id, a delivery may arrive more than once.
+pub static DELIVERY_HEADER: &str = "X-CoffeeOJ-Delivery";
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WebhookTestcase {
+    pub testcase: String,
+    pub verdict: String,
+    pub time_cost: u64,
+    pub mem_cost: u64,
+}
+
+/// The body POSTed once a submission is finished.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub submission_id: u64,
+    pub problem_id: u64,
+    pub verdict: String,
+    pub info: String,
+    pub time_cost: u64,
+    pub mem_cost: u64,
+    pub testcases: Vec<WebhookTestcase>,
+}
+
+impl WebhookPayload {
+    pub fn new(
+        submission_id: u64,
+        problem_id: u64,
+        verdict: Verdict,
+        info: &str,
+        results: &[TestcaseResult],
+    ) -> WebhookPayload {
+        WebhookPayload {
+            submission_id,
+            problem_id,
+            verdict: verdict.as_str().to_string(),
+            info: info.to_string(),
+            time_cost: results.iter().map(|r| r.time_cost).sum(),
+            mem_cost: results.iter().map(|r| r.mem_cost).max().unwrap_or(0),
+            testcases: results
+                .iter()
+                .map(|r| WebhookTestcase {
+                    testcase: r.testcase.clone(),
+                    verdict: r.verdict.as_str().to_string(),
+                    time_cost: r.time_cost,
+                    mem_cost: r.mem_cost,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The value of [`SIGNATURE_HEADER`] for `body`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// used by submissions without a callback url of their own
+    pub url: Option<String>,
+    /// can't be empty if `url` is set, anyone could sign its deliveries
+    pub secret: String,
+    pub max_attempts: u64,
+    /// before the first retry, doubled every retry
+    pub backoff: Duration,
+    /// between retries at most
+    pub max_backoff: Duration,
+    pub timeout: Duration,
+    pub dead_letter_path: PathBuf,
+    /// hosts callback urls may point at even if they resolve to loopback,
+    /// private or link-local addresses, the host of `url` is one of them
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> WebhookConfig {
+        WebhookConfig::from_config(&Table::new())
+    }
+}
+
+impl WebhookConfig {
+    pub fn from_config(data: &Table) -> WebhookConfig {
+        let webhook = match data.get("webhook") {
+            Some(Value::Table(webhook)) => webhook.clone(),
+            None => Table::new(),
+            _ => panic!("config: [webhook] should be set correctly"),
+        };
+        let get_string = |key: &str| match webhook.get(key) {
+            Some(Value::String(val)) => Some(val.clone()),
+            None => None,
+            _ => panic!("config: [webhook] should be set correctly"),
+        };
+        let get_integer = |key: &str, default: u64| match webhook.get(key) {
+            Some(Value::Integer(val)) if *val > 0 => *val as u64,
+            None => default,
+            _ => panic!("config: [webhook] should be set correctly"),
+        };
+
+        let allowed_hosts = match webhook.get("allowed_hosts") {
+            Some(Value::Array(hosts)) => hosts
+                .iter()
+                .map(|host| match host {
+                    Value::String(host) => host.clone(),
+                    _ => panic!("config: [webhook] should be set correctly"),
+                })
+                .collect(),
+            None => vec![],
+            _ => panic!("config: [webhook] should be set correctly"),
+        };
+
+        let url = get_string("url");
+        let secret = get_string("secret").unwrap_or_default();
+        if url.is_some() && secret.is_empty() {
+            panic!("config: [webhook] secret should be set with url");
+        }
+
+        WebhookConfig {
+            url,
+            secret,
+            max_attempts: get_integer("max_attempts", DEFAULT_WEBHOOK_MAX_ATTEMPTS),
+            backoff: Duration::from_millis(get_integer("backoff", DEFAULT_WEBHOOK_BACKOFF)),
+            max_backoff: Duration::from_millis(get_integer(
+                "max_backoff",
+                DEFAULT_WEBHOOK_MAX_BACKOFF,
+            )),
+            timeout: Duration::from_millis(get_integer("timeout", DEFAULT_WEBHOOK_TIMEOUT)),
+            dead_letter_path: get_string("dead_letter_file")
+                .unwrap_or(DEFAULT_WEBHOOK_DEAD_LETTER_PATH.to_string())
+                .into(),
+            allowed_hosts,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Delivery {
+    submission_id: u64,
+    url: String,
+    body: String,
+    attempts: u64,
+    due: Instant,
+}
+
+// the earliest due delivery is the greatest, for the max-heap
+impl Ord for Delivery {
+    fn cmp(&self, other: &Delivery) -> Ordering {
+        other.due.cmp(&self.due)
+    }
+}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Delivery) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Delivery) -> bool {
+        self.due == other.due
+    }
+}
+
+impl Eq for Delivery {}
+
+enum Message {
+    Deliver(Delivery),
+    /// deliver what's due until then, dead-letter the rest and stop
+    Shutdown(Instant),
+}
+
+/// A line of the dead-letter log, the payload can be replayed from it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub submission_id: u64,
+    pub url: String,
+    pub attempts: u64,
+    pub error: String,
+    pub failed_at: u64,
+    pub body: String,
+}
+
+/// Delivers webhooks on a background thread, judging never waits for the
+/// receiving end.
+pub struct Webhooks {
+    config: WebhookConfig,
+    sender: Mutex<mpsc::Sender<Message>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Webhooks {
+        Webhooks::new(WebhookConfig::default())
+    }
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Webhooks {
+        let (sender, receiver) = mpsc::channel();
+        let delivery_config = config.clone();
+        let worker = thread::Builder::new()
+            .name("webhooks".to_string())
+            .spawn(move || deliver(receiver, delivery_config))
+            .unwrap();
+
+        Webhooks {
+            config,
+            sender: Mutex::new(sender),
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Queues the payload for `url`, or for `[webhook] url` if the submission
+    /// has none. Nothing is sent if neither is set.
+    pub fn notify(&self, url: Option<&str>, payload: &WebhookPayload) {
+        let Some(url) = url.or(self.config.url.as_deref()) else {
+            return;
+        };
+        let body = match serde_json::to_string(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!(
+                    "webhook: can't serialize submission {}: {e}",
+                    payload.submission_id
+                );
+                return;
+            }
+        };
+
+        let delivery = Delivery {
+            submission_id: payload.submission_id,
+            url: url.to_string(),
+            body,
+            attempts: 0,
+            due: Instant::now(),
+        };
+        let message = Message::Deliver(delivery);
+        if self.sender.lock().unwrap().send(message).is_err() {
+            tracing::error!("webhook: the delivery thread is gone");
+        }
+    }
+
+    /// Stops the delivery thread once the deliveries due within `grace`, the
+    /// retries among them, are done. The ones due later are written to the
+    /// dead-letter log, so that they can be replayed.
+    pub fn shutdown(&self, grace: Duration) {
+        let message = Message::Shutdown(Instant::now() + grace);
+        let _ = self.sender.lock().unwrap().send(message);
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Runs until the [`Webhooks`] is dropped and every queued delivery either
+/// succeeded or was given up on, or until it's shut down.
+fn deliver(receiver: mpsc::Receiver<Message>, config: WebhookConfig) {
+    let agent = ureq::AgentBuilder::new()
+        .resolver(PublicResolver::new(&config))
+        .build();
+    let mut pending: BinaryHeap<Delivery> = BinaryHeap::new();
+    let mut disconnected = false;
+    let mut deadline = None;
+
+    loop {
+        if let Some(deadline) = deadline {
+            // the last ones notified before the shutdown
+            while let Ok(message) = receiver.try_recv() {
+                if let Message::Deliver(delivery) = message {
+                    pending.push(delivery);
+                }
+            }
+            match pending.peek() {
+                None => break,
+                Some(next) if next.due > deadline || Instant::now() >= deadline => {
+                    return dead_letter_pending(&config, pending)
+                }
+                Some(next) => thread::sleep(next.due.saturating_duration_since(Instant::now())),
+            }
+        } else {
+            let timeout = pending
+                .peek()
+                .map(|next| next.due.saturating_duration_since(Instant::now()));
+            match (timeout, disconnected) {
+                (None, true) => break,
+                (Some(timeout), true) => thread::sleep(timeout),
+                (None, false) => match receiver.recv() {
+                    Ok(Message::Deliver(delivery)) => pending.push(delivery),
+                    Ok(Message::Shutdown(at)) => deadline = Some(at),
+                    Err(_) => disconnected = true,
+                },
+                (Some(timeout), false) => match receiver.recv_timeout(timeout) {
+                    Ok(Message::Deliver(delivery)) => pending.push(delivery),
+                    Ok(Message::Shutdown(at)) => deadline = Some(at),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => disconnected = true,
+                },
+            }
+        }
+
+        while pending
+            .peek()
+            .is_some_and(|next| next.due <= Instant::now())
+        {
+            // a slow receiver can't hold the shutdown past its deadline
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => left.min(config.timeout),
+                    _ => break,
+                },
+                None => config.timeout,
+            };
+            let mut delivery = pending.pop().unwrap();
+            delivery.attempts += 1;
+            let error = match post(&agent, &config, &delivery, timeout) {
+                Ok(()) => continue,
+                Err(e) => e,
+            };
+
+            if delivery.attempts >= config.max_attempts {
+                tracing::error!(
+                    "webhook: giving up on submission {} after {} attempts: {error}",
+                    delivery.submission_id,
+                    delivery.attempts
+                );
+                write_dead_letter(&config, &delivery, error);
+                continue;
+            }
+            let backoff = backoff(&config, delivery.attempts);
+            tracing::warn!(
+                "webhook: submission {} failed, retrying in {backoff:?}: {error}",
+                delivery.submission_id
+            );
+            delivery.due = Instant::now() + backoff;
+            pending.push(delivery);
+        }
+    }
+}
+
+fn dead_letter_pending(config: &WebhookConfig, pending: BinaryHeap<Delivery>) {
+    if !pending.is_empty() {
+        tracing::warn!(
+            "webhook: shutting down, {} deliveries written to `{}`",
+            pending.len(),
+            config.dead_letter_path.display()
+        );
+    }
+    for delivery in pending {
+        write_dead_letter(config, &delivery, "shut down before delivery".to_string());
+    }
+}
+
+/// `backoff` doubled for every failed attempt after the first one, at most
+/// `max_backoff`.
+fn backoff(config: &WebhookConfig, attempts: u64) -> Duration {
+    u32::try_from(attempts.saturating_sub(1))
+        .ok()
+        .and_then(|exponent| 2u32.checked_pow(exponent))
+        .and_then(|factor| config.backoff.checked_mul(factor))
+        .map_or(config.max_backoff, |backoff| {
+            backoff.min(config.max_backoff)
+        })
+}
+
+/// Resolves the hosts of callback urls, which come with the submissions,
+/// leaving out the addresses of the judge's own networks: loopback,
+/// private, link-local and the like. Redirects are resolved by it as well.
+struct PublicResolver {
+    allowed_hosts: Vec<String>,
+}
+
+impl PublicResolver {
+    fn new(config: &WebhookConfig) -> PublicResolver {
+        let mut allowed_hosts = config.allowed_hosts.clone();
+        let configured = config.url.as_deref().and_then(|url| {
+            let authority = url.split("://").nth(1)?.split(['/', '?', '#']).next()?;
+            let netloc = authority.rsplit('@').next()?;
+            Some(host_of(netloc).to_string())
+        });
+        allowed_hosts.extend(configured);
+        PublicResolver { allowed_hosts }
+    }
+}
+
+impl ureq::Resolver for PublicResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let addrs: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+        let host = host_of(netloc);
+        if self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        {
+            return Ok(addrs);
+        }
+
+        let public: Vec<SocketAddr> = addrs
+            .into_iter()
+            .filter(|addr| is_public(addr.ip()))
+            .collect();
+        if public.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{host} doesn't resolve to a public address"),
+            ));
+        }
+        Ok(public)
+    }
+}
+
+/// `example.com` of `example.com:443`, `::1` of `[::1]:80`.
+fn host_of(netloc: &str) -> &str {
+    let host = match netloc.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => netloc,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local and link-local
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+fn post(
+    agent: &ureq::Agent,
+    config: &WebhookConfig,
+    delivery: &Delivery,
+    timeout: Duration,
+) -> Result<(), String> {
+    agent
+        .post(&delivery.url)
+        .timeout(timeout)
+        .set("Content-Type", "application/json")
+        .set(
+            SIGNATURE_HEADER,
+            &sign(&config.secret, delivery.body.as_bytes()),
+        )
+        .set(DELIVERY_HEADER, &delivery.submission_id.to_string())
+        .send_string(&delivery.body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn write_dead_letter(config: &WebhookConfig, delivery: &Delivery, error: String) {
+    let letter = DeadLetter {
+        submission_id: delivery.submission_id,
+        url: delivery.url.clone(),
+        attempts: delivery.attempts,
+        error,
+        failed_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        body: delivery.body.clone(),
+    };
+
+    let path = &config.dead_letter_path;
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let ret = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            let line = serde_json::to_string(&letter).unwrap_or_default();
+            writeln!(file, "{line}")
+        });
+    if let Err(e) = ret {
+        tracing::error!("webhook: can't write to `{}`: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        panic,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use toml::{Table, Value};
+
+    use crate::judge::Verdict;
+
+    use super::{
+        backoff, host_of, is_public, sign, DeadLetter, WebhookConfig, WebhookPayload, Webhooks,
+        SIGNATURE_HEADER,
+    };
+
+    /// A local stand-in for the receiving service, answers the first
+    /// `failures` requests with 500 and sends the signature and body of
+    /// every request over the channel.
+    fn stand_in(failures: usize) -> (String, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/callback", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut signature, mut length) = (String::new(), 0);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(": ").unwrap_or((line, ""));
+                    if name.eq_ignore_ascii_case(SIGNATURE_HEADER) {
+                        signature = value.to_string();
+                    } else if name.eq_ignore_ascii_case("content-length") {
+                        length = value.parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let status = if i < failures {
+                    "500 Internal Server Error"
+                } else {
+                    "200 OK"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                let _ = sender.send((signature, String::from_utf8(body).unwrap()));
+            }
+        });
+        (url, receiver)
+    }
+
+    fn config(name: &str) -> WebhookConfig {
+        WebhookConfig {
+            url: None,
+            secret: "s3cret".to_string(),
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(2),
+            dead_letter_path: std::env::temp_dir()
+                .join(format!("coj-webhook-{name}-{}.log", std::process::id())),
+            // the stand-in
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+        }
+    }
+
+    /// The first line of the dead-letter log at `path`, once it's written.
+    fn first_dead_letter(path: &std::path::Path) -> DeadLetter {
+        for _ in 0..100 {
+            if path.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let log = fs::read_to_string(path).unwrap();
+        let letter = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        letter
+    }
+
+    #[test]
+    fn test_signed_delivery_is_retried() {
+        let (url, requests) = stand_in(2);
+        let webhooks = Webhooks::new(config("retry"));
+        let payload = WebhookPayload::new(1, 1, Verdict::Accepted, "", &[]);
+        webhooks.notify(Some(&url), &payload);
+
+        let timeout = Duration::from_secs(5);
+        for _ in 0..3 {
+            let (signature, body) = requests.recv_timeout(timeout).unwrap();
+            assert_eq!(signature, sign("s3cret", body.as_bytes()));
+            assert_eq!(
+                serde_json::from_str::<WebhookPayload>(&body).unwrap(),
+                payload
+            );
+        }
+        // delivered on the third attempt
+        assert!(requests.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        // nothing listens on a port that was just released
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/callback", listener.local_addr().unwrap())
+        };
+        let config = config("dead");
+        let path = config.dead_letter_path.clone();
+        let webhooks = Webhooks::new(WebhookConfig {
+            url: Some(url),
+            ..config
+        });
+        webhooks.notify(
+            None,
+            &WebhookPayload::new(2, 1, Verdict::WrongAnswer, "", &[]),
+        );
+        // pending deliveries outlive the sender
+        drop(webhooks);
+
+        let letter = first_dead_letter(&path);
+        assert_eq!(letter.submission_id, 2);
+        assert_eq!(letter.attempts, 3);
+    }
+
+    #[test]
+    fn test_private_callbacks_are_refused() {
+        let (url, requests) = stand_in(0);
+        let config = config("private");
+        let path = config.dead_letter_path.clone();
+        let webhooks = Webhooks::new(WebhookConfig {
+            allowed_hosts: vec![],
+            ..config
+        });
+        webhooks.notify(
+            Some(&url),
+            &WebhookPayload::new(3, 1, Verdict::Accepted, "", &[]),
+        );
+
+        let letter = first_dead_letter(&path);
+        assert_eq!(letter.submission_id, 3);
+        assert!(letter.error.contains("public address"), "{}", letter.error);
+        assert!(requests.try_recv().is_err());
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "::1",
+            "fe80::1",
+            "::ffff:192.168.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert_eq!(host_of("[::1]:8080"), "::1");
+        assert_eq!(host_of("example.com:443"), "example.com");
+    }
+
+    #[test]
+    fn test_pending_retries_are_dead_lettered_on_shutdown() {
+        let (url, requests) = stand_in(usize::MAX);
+        let config = config("shutdown");
+        let path = config.dead_letter_path.clone();
+        let webhooks = Webhooks::new(WebhookConfig {
+            backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+            ..config
+        });
+        webhooks.notify(
+            Some(&url),
+            &WebhookPayload::new(4, 1, Verdict::Accepted, "", &[]),
+        );
+        // the first attempt failed, the retry is a minute away
+        requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        webhooks.shutdown(Duration::from_secs(1));
+
+        let letter = first_dead_letter(&path);
+        assert_eq!((letter.submission_id, letter.attempts), (4, 1));
+        assert_eq!(letter.error, "shut down before delivery");
+    }
+
+    #[test]
+    fn test_due_deliveries_are_drained_on_shutdown() {
+        let (url, requests) = stand_in(1);
+        let config = config("drain");
+        let path = config.dead_letter_path.clone();
+        let webhooks = Webhooks::new(WebhookConfig {
+            backoff: Duration::from_millis(200),
+            ..config
+        });
+        webhooks.notify(
+            Some(&url),
+            &WebhookPayload::new(5, 1, Verdict::Accepted, "", &[]),
+        );
+        // the retry is due within the grace period
+        webhooks.shutdown(Duration::from_secs(5));
+
+        let timeout = Duration::from_secs(5);
+        requests.recv_timeout(timeout).unwrap();
+        requests.recv_timeout(timeout).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_slow_receivers_are_cut_at_the_deadline() {
+        // hangs up on the first attempt, never answers the retry
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/callback", listener.local_addr().unwrap());
+        let (sender, attempts) = mpsc::channel();
+        thread::spawn(move || {
+            let mut held = vec![];
+            for (i, stream) in listener.incoming().enumerate() {
+                if i > 0 {
+                    held.push(stream);
+                }
+                let _ = sender.send(());
+            }
+        });
+        let config = config("slow");
+        let path = config.dead_letter_path.clone();
+        let webhooks = Webhooks::new(WebhookConfig {
+            backoff: Duration::from_millis(200),
+            timeout: Duration::from_secs(60),
+            ..config
+        });
+        webhooks.notify(
+            Some(&url),
+            &WebhookPayload::new(6, 1, Verdict::Accepted, "", &[]),
+        );
+        attempts.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // the retry is due within the grace period, its answer isn't
+        let started_at = Instant::now();
+        webhooks.shutdown(Duration::from_secs(1));
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+        attempts.recv_timeout(Duration::from_secs(5)).unwrap();
+        let letter = first_dead_letter(&path);
+        assert_eq!((letter.submission_id, letter.attempts), (6, 2));
+    }
+
+    #[test]
+    fn test_url_needs_a_secret() {
+        let url = "https://example.com/callback";
+        let mut webhook = Table::new();
+        webhook.insert("url".to_string(), Value::String(url.to_string()));
+        let mut data = Table::new();
+        data.insert("webhook".to_string(), Value::Table(webhook.clone()));
+        assert!(panic::catch_unwind(|| WebhookConfig::from_config(&data)).is_err());
+
+        webhook.insert("secret".to_string(), Value::String("s3cret".to_string()));
+        data.insert("webhook".to_string(), Value::Table(webhook));
+        assert_eq!(WebhookConfig::from_config(&data).url.as_deref(), Some(url));
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let config = config("backoff");
+        assert_eq!(backoff(&config, 1), Duration::from_millis(10));
+        assert_eq!(backoff(&config, 3), Duration::from_millis(40));
+        assert_eq!(backoff(&config, 5), Duration::from_millis(100));
+        assert_eq!(backoff(&config, u64::MAX), Duration::from_millis(100));
+    }
+}